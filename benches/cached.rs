@@ -0,0 +1,75 @@
+// Copyright 2017 1aim GmbH
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is furnished to do
+// so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Compare a memoized `CachedRegex` with looking the pattern up in a shared
+//! cache on every match, with many threads matching a hot pattern.
+//!
+//! Run with `cargo bench --bench cached`.
+
+use std::hint::black_box;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use regex_cache::{RegexCache, CachedRegex, SharedRegexCache};
+
+const THREADS:    usize = 8;
+const ITERATIONS: usize = 100_000;
+const PATTERN:    &str  = r"^\d+-\d+$";
+
+fn measure(memoized: bool) -> (Duration, u64) {
+	let cache  = Arc::new(Mutex::new(RegexCache::new(16)));
+	let shared = SharedRegexCache::from(cache.clone());
+	let re     = CachedRegex::new(cache, PATTERN).unwrap();
+	let start  = Instant::now();
+
+	thread::scope(|scope| {
+		for t in 0 .. THREADS {
+			let (re, shared) = (&re, &shared);
+
+			scope.spawn(move || {
+				let text = format!("{}-{}", t, t * 2);
+
+				for _ in 0 .. ITERATIONS {
+					if memoized {
+						black_box(re.is_match(black_box(&text)));
+					}
+					else {
+						black_box(shared.is_match(PATTERN, black_box(&text)).unwrap());
+					}
+				}
+			});
+		}
+	});
+
+	let elapsed = start.elapsed();
+	let stats   = shared.stats();
+
+	(elapsed, stats.hits + stats.misses)
+}
+
+fn main() {
+	for &(name, memoized) in &[("lookup", false), ("memoized", true)] {
+		let (elapsed, locks) = measure(memoized);
+
+		println!("{:>12}: {:>8.2} ns/match, {:>8} lock acquisitions", name,
+			elapsed.as_nanos() as f64 / (THREADS * ITERATIONS) as f64, locks);
+	}
+}
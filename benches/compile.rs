@@ -0,0 +1,66 @@
+// Copyright 2017 1aim GmbH
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is furnished to do
+// so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Compare the hit path of `RegexCache::compile` against a `contains` check
+//! followed by `compile`, which does two lookups.
+//!
+//! Run with `cargo bench --bench compile`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use regex_cache::RegexCache;
+
+const PATTERNS:   usize = 100;
+const ITERATIONS: usize = 1_000_000;
+
+fn measure<F: FnMut(&str)>(patterns: &[String], mut f: F) -> Duration {
+	let start = Instant::now();
+
+	for i in 0 .. ITERATIONS {
+		f(&patterns[i % patterns.len()]);
+	}
+
+	start.elapsed()
+}
+
+fn main() {
+	let patterns = (0 .. PATTERNS).map(|i| format!(r"^\d{{{}}}$", i))
+		.collect::<Vec<_>>();
+
+	let mut cache = RegexCache::new(PATTERNS);
+	cache.warm(&patterns);
+
+	let double = measure(&patterns, |source| {
+		if cache.contains(source) {
+			black_box(cache.compile(source).unwrap());
+		}
+	});
+
+	let single = measure(&patterns, |source| {
+		black_box(cache.compile(source).unwrap());
+	});
+
+	println!("contains + compile:     {:>8.2} ns/hit",
+		double.as_nanos() as f64 / ITERATIONS as f64);
+
+	println!("compile:                {:>8.2} ns/hit",
+		single.as_nanos() as f64 / ITERATIONS as f64);
+}
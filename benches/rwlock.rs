@@ -0,0 +1,77 @@
+// Copyright 2017 1aim GmbH
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is furnished to do
+// so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Compare a mutex and a read-write lock around a shared cache, with many
+//! threads hitting a small set of hot patterns.
+//!
+//! Run with `cargo bench --bench rwlock`.
+
+use std::hint::black_box;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use regex::{Regex, Error};
+use regex_cache::{SharedRegexCache, RwLockRegexCache};
+
+const THREADS:    usize = 8;
+const PATTERNS:   usize = 16;
+const ITERATIONS: usize = 100_000;
+
+fn measure<F>(compile: F) -> Duration
+	where F: Fn(&str) -> Result<Regex, Error> + Sync
+{
+	let patterns = (0 .. PATTERNS).map(|i| format!("^pattern-{}$", i))
+		.collect::<Vec<_>>();
+
+	for pattern in &patterns {
+		compile(pattern).unwrap();
+	}
+
+	let start = Instant::now();
+
+	thread::scope(|scope| {
+		for t in 0 .. THREADS {
+			let (compile, patterns) = (&compile, &patterns);
+
+			scope.spawn(move || {
+				for i in 0 .. ITERATIONS {
+					black_box(compile(&patterns[(i * 7 + t) % PATTERNS]).unwrap());
+				}
+			});
+		}
+	});
+
+	start.elapsed()
+}
+
+fn main() {
+	let mutex  = SharedRegexCache::new(PATTERNS * 2);
+	let rwlock = RwLockRegexCache::new(PATTERNS * 2);
+
+	let results = [
+		("mutex", measure(|source| mutex.compile(source))),
+		("rwlock", measure(|source| rwlock.compile(source))),
+	];
+
+	for &(name, elapsed) in &results {
+		println!("{:>12}: {:>8.2} ns/hit", name,
+			elapsed.as_nanos() as f64 / (THREADS * ITERATIONS) as f64);
+	}
+}
@@ -0,0 +1,69 @@
+// Copyright 2017 1aim GmbH
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is furnished to do
+// so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Compare lock contention between a single shard and eight shards with
+//! many threads hitting the cache.
+//!
+//! Run with `cargo bench --bench sharded`.
+
+use std::hint::black_box;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use regex_cache::ShardedRegexCache;
+
+const THREADS:    usize = 8;
+const PATTERNS:   usize = 256;
+const ITERATIONS: usize = 100_000;
+
+fn measure(shards: usize) -> Duration {
+	// Leave room for uneven shards so the measurement only covers hits.
+	let cache    = ShardedRegexCache::new(PATTERNS * 2, shards);
+	let patterns = (0 .. PATTERNS).map(|i| format!("^pattern-{}$", i))
+		.collect::<Vec<_>>();
+
+	cache.warm(&patterns);
+
+	let start = Instant::now();
+
+	thread::scope(|scope| {
+		for t in 0 .. THREADS {
+			let cache    = &cache;
+			let patterns = &patterns;
+
+			scope.spawn(move || {
+				for i in 0 .. ITERATIONS {
+					black_box(cache.compile(&patterns[(i * 31 + t) % PATTERNS]).unwrap());
+				}
+			});
+		}
+	});
+
+	start.elapsed()
+}
+
+fn main() {
+	for &shards in &[1, 8] {
+		let elapsed = measure(shards);
+
+		println!("{} shard(s): {:>8.2} ns/hit", shards,
+			elapsed.as_nanos() as f64 / (THREADS * ITERATIONS) as f64);
+	}
+}
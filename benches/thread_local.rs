@@ -0,0 +1,84 @@
+// Copyright 2017 1aim GmbH
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is furnished to do
+// so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Compare a shared cache with and without a thread-local cache in front of
+//! it, with many threads hitting a small set of hot patterns.
+//!
+//! Run with `cargo bench --bench thread_local`.
+
+use std::hint::black_box;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use regex_cache::SharedRegexCache;
+
+const THREADS:    usize = 8;
+const PATTERNS:   usize = 16;
+const ITERATIONS: usize = 100_000;
+
+fn measure(l1_capacity: Option<usize>) -> (Duration, u64) {
+	let shared   = SharedRegexCache::new(PATTERNS * 2);
+	let patterns = (0 .. PATTERNS).map(|i| format!("^pattern-{}$", i))
+		.collect::<Vec<_>>();
+
+	for pattern in &patterns {
+		shared.compile(pattern).unwrap();
+	}
+
+	shared.reset_stats();
+	let start = Instant::now();
+
+	thread::scope(|scope| {
+		for t in 0 .. THREADS {
+			let shared   = &shared;
+			let patterns = &patterns;
+
+			scope.spawn(move || {
+				let local = l1_capacity.map(|capacity| shared.with_thread_local(capacity));
+
+				for i in 0 .. ITERATIONS {
+					let pattern = &patterns[(i * 7 + t) % PATTERNS];
+
+					match local {
+						Some(ref local) =>
+							black_box(local.compile(pattern).unwrap()),
+
+						None =>
+							black_box(shared.compile(pattern).unwrap()),
+					};
+				}
+			});
+		}
+	});
+
+	let elapsed = start.elapsed();
+	let stats   = shared.stats();
+
+	(elapsed, stats.hits + stats.misses)
+}
+
+fn main() {
+	for &(name, l1_capacity) in &[("shared", None), ("thread-local", Some(PATTERNS))] {
+		let (elapsed, locks) = measure(l1_capacity);
+
+		println!("{:>12}: {:>8.2} ns/hit, {:>8} lock acquisitions", name,
+			elapsed.as_nanos() as f64 / (THREADS * ITERATIONS) as f64, locks);
+	}
+}
@@ -0,0 +1,150 @@
+// Copyright 2017 1aim GmbH
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is furnished to do
+// so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Procedural macros for `regex-cache`, use them through the `macros`
+//! feature of the main crate.
+
+#![allow(clippy::redundant_field_names, clippy::tabs_in_doc_comments)]
+
+extern crate proc_macro;
+extern crate regex_syntax as syntax;
+
+use proc_macro::{TokenStream, TokenTree, Delimiter};
+
+/// Create a `LazyRegex` from a string literal, checking the syntax of the
+/// pattern at compile time.
+///
+/// The macro expands to an expression, since a `LazyRegex` can't be built in
+/// a constant context, but an invalid pattern is reported as a compilation
+/// error and the pattern isn't parsed again at runtime.
+///
+/// # Example
+///
+/// ```
+/// # use regex_cache_macros::const_lazy_regex;
+/// let re = const_lazy_regex!(r"^\d+$");
+/// assert!(re.is_match("1234"));
+/// ```
+///
+/// ```compile_fail
+/// # use regex_cache_macros::const_lazy_regex;
+/// let re = const_lazy_regex!(r"^(\d+$");
+/// ```
+#[proc_macro]
+pub fn const_lazy_regex(input: TokenStream) -> TokenStream {
+	let source = match literal(input) {
+		Ok(source) =>
+			source,
+
+		Err(message) =>
+			return error(message),
+	};
+
+	if let Err(err) = syntax::Parser::new().parse(&source) {
+		return error(&format!("invalid regular expression: {}", err));
+	}
+
+	format!("::regex_cache::LazyRegex::new_unchecked({:?})", source).parse().unwrap()
+}
+
+/// Emit a compilation error with the given message.
+fn error(message: &str) -> TokenStream {
+	format!("compile_error!({:?})", message).parse().unwrap()
+}
+
+/// Extract the value of the single string literal in the input.
+fn literal(input: TokenStream) -> Result<String, &'static str> {
+	const EXPECTED: &str = "expected a single string literal";
+
+	let mut tokens = input.into_iter();
+	let token      = tokens.next().ok_or(EXPECTED)?;
+
+	if tokens.next().is_some() {
+		return Err(EXPECTED);
+	}
+
+	match token {
+		TokenTree::Group(ref group) if group.delimiter() == Delimiter::None =>
+			literal(group.stream()),
+
+		TokenTree::Literal(literal) =>
+			unquote(&literal.to_string()).ok_or(EXPECTED),
+
+		_ =>
+			Err(EXPECTED),
+	}
+}
+
+/// Get the value of a string literal from its source representation.
+fn unquote(literal: &str) -> Option<String> {
+	if let Some(raw) = literal.strip_prefix('r') {
+		let hashes = raw.len() - raw.trim_start_matches('#').len();
+
+		return raw[hashes ..].strip_suffix(&raw[.. hashes])?
+			.strip_prefix('"')?.strip_suffix('"').map(String::from);
+	}
+
+	let inner     = literal.strip_prefix('"')?.strip_suffix('"')?;
+	let mut value = String::with_capacity(inner.len());
+	let mut chars = inner.chars().peekable();
+
+	while let Some(ch) = chars.next() {
+		if ch != '\\' {
+			value.push(ch);
+			continue;
+		}
+
+		match chars.next()? {
+			'n'  => value.push('\n'),
+			'r'  => value.push('\r'),
+			't'  => value.push('\t'),
+			'0'  => value.push('\0'),
+			'\\' => value.push('\\'),
+			'\'' => value.push('\''),
+			'"'  => value.push('"'),
+
+			'x' => {
+				let code = chars.by_ref().take(2).collect::<String>();
+				value.push(u8::from_str_radix(&code, 16).ok()? as char);
+			}
+
+			'u' => {
+				if chars.next()? != '{' {
+					return None;
+				}
+
+				let code = chars.by_ref().take_while(|&c| c != '}')
+					.filter(|&c| c != '_').collect::<String>();
+				value.push(std::char::from_u32(u32::from_str_radix(&code, 16).ok()?)?);
+			}
+
+			'\n' => {
+				while chars.peek().is_some_and(|c| c.is_whitespace()) {
+					chars.next();
+				}
+			}
+
+			_ =>
+				return None,
+		}
+	}
+
+	Some(value)
+}
@@ -0,0 +1,945 @@
+// Copyright 2017 1aim GmbH
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is furnished to do
+// so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Types working on arbitrary bytes instead of UTF-8 text, like
+//! `regex::bytes`.
+
+use std::ops::Deref;
+use std::borrow::Cow;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str;
+
+use std::sync::{Arc, Mutex, OnceLock};
+use oncemutex::OnceMutex;
+use linked_hash_map::LinkedHashMap;
+
+use regex::Error;
+use regex::bytes::{Regex, RegexBuilder, RegexSet, RegexSetBuilder};
+use regex::bytes::{Match, Captures, Replacer, Split, SplitN, CaptureNames};
+use crate::syntax;
+use crate::options::Options;
+use crate::cache::{RegexCacheKey, CacheStats, DEFAULT_CAPACITY, bounded};
+use crate::lazy::LazyRegexBuilder;
+
+/// Check the syntax of a pattern meant to match arbitrary bytes.
+fn check(source: &str) -> Result<(), Error> {
+	if let Err(err) = syntax::ParserBuilder::new().allow_invalid_utf8(true).build().parse(source) {
+		return Err(Error::Syntax(err.to_string()));
+	}
+
+	Ok(())
+}
+
+/// A lazily created `Regex` over bytes.
+///
+/// At the first `Deref` the pattern will be compiled, and the result is shared
+/// by all the clones.
+///
+/// # Example
+///
+/// ```
+/// # use regex_cache::bytes::LazyRegex;
+/// let re = LazyRegex::new(r"(?-u)\xFF+").unwrap();
+///
+/// assert!(re.is_match(b"a\xFF\xFF"));
+/// assert!(re.to_text().is_err());
+/// ```
+#[derive(Clone)]
+pub struct LazyRegex {
+	source:  String,
+	options: Options,
+	regex:   Arc<OnceMutex<Option<Regex>>>,
+}
+
+impl LazyRegex {
+	/// Create a new lazy `Regex` for the given source, checking the syntax is
+	/// valid.
+	pub fn new(source: &str) -> Result<LazyRegex, Error> {
+		check(source)?;
+		Ok(LazyRegex::from_parts(source.to_owned(), Options::default()))
+	}
+
+	/// Create a lazy `Regex` from a source known to be valid.
+	pub(crate) fn from_parts(source: String, options: Options) -> LazyRegex {
+		LazyRegex {
+			source:  source,
+			options: options,
+			regex:   Arc::new(OnceMutex::new(None)),
+		}
+	}
+
+	/// Get the source of the regular expression.
+	pub fn as_str(&self) -> &str {
+		&self.source
+	}
+
+	/// Get a lazy regular expression over text with the same pattern and
+	/// options, which is compiled at its own first use.
+	///
+	/// Fails if the pattern can match invalid UTF-8, which is only allowed
+	/// over bytes.
+	pub fn to_text(&self) -> Result<crate::LazyRegex, Error> {
+		LazyRegexBuilder::with_options(&self.source, self.options).build()
+	}
+
+	fn create(&self) -> Regex {
+		self.options.define_bytes(&mut RegexBuilder::new(&self.source))
+			.build().unwrap()
+	}
+}
+
+impl Deref for LazyRegex {
+	type Target = Regex;
+
+	fn deref(&self) -> &Regex {
+		self.as_ref()
+	}
+}
+
+impl AsRef<Regex> for LazyRegex {
+	fn as_ref(&self) -> &Regex {
+		if let Some(mut guard) = self.regex.lock() {
+			if guard.is_none() {
+				*guard = Some(self.create());
+			}
+		}
+
+		(*self.regex).as_ref().unwrap()
+	}
+}
+
+impl fmt::Debug for LazyRegex {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(&**self, f)
+	}
+}
+
+/// A lazily created `RegexSet` over bytes.
+///
+/// At the first `Deref` the given patterns will be compiled, and the result is
+/// shared by all the clones.
+///
+/// # Example
+///
+/// ```
+/// # use regex_cache::bytes::LazyRegexSet;
+/// let set = LazyRegexSet::new([r"^\d+$", r"(?-u)\xFF"]).unwrap();
+///
+/// assert_eq!(set.matches(b"\x00\xFF").into_iter().collect::<Vec<_>>(), [1]);
+/// ```
+#[derive(Clone)]
+pub struct LazyRegexSet {
+	builder: LazyRegexSetBuilder,
+	set:     Arc<OnceMutex<Option<RegexSet>>>,
+}
+
+impl LazyRegexSet {
+	/// Create a new lazy `RegexSet` for the given patterns, checking the syntax
+	/// is valid.
+	pub fn new<I, S>(patterns: I) -> Result<LazyRegexSet, Error>
+		where I: IntoIterator<Item = S>,
+		      S: AsRef<str>
+	{
+		LazyRegexSetBuilder::new(patterns).build()
+	}
+
+	fn from_builder(builder: LazyRegexSetBuilder) -> Self {
+		LazyRegexSet {
+			builder: builder,
+			set:     Arc::new(OnceMutex::new(None)),
+		}
+	}
+
+	fn create(builder: &LazyRegexSetBuilder) -> RegexSet {
+		builder.options.define_bytes_set(&mut RegexSetBuilder::new(&builder.patterns))
+			.build().unwrap()
+	}
+}
+
+impl Deref for LazyRegexSet {
+	type Target = RegexSet;
+
+	fn deref(&self) -> &RegexSet {
+		self.as_ref()
+	}
+}
+
+impl AsRef<RegexSet> for LazyRegexSet {
+	fn as_ref(&self) -> &RegexSet {
+		if let Some(mut guard) = self.set.lock() {
+			if guard.is_none() {
+				*guard = Some(LazyRegexSet::create(&self.builder));
+			}
+		}
+
+		(*self.set).as_ref().unwrap()
+	}
+}
+
+impl fmt::Debug for LazyRegexSet {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(&**self, f)
+	}
+}
+
+/// A configurable builder for a lazy `RegexSet` over bytes.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct LazyRegexSetBuilder {
+	patterns: Vec<String>,
+	options:  Options,
+}
+
+impl LazyRegexSetBuilder {
+	/// Create a new regular expression set builder with the given patterns.
+	///
+	/// If any pattern is invalid, then an error will be returned when `build`
+	/// is called.
+	pub fn new<I, S>(patterns: I) -> LazyRegexSetBuilder
+		where I: IntoIterator<Item = S>,
+		      S: AsRef<str>
+	{
+		LazyRegexSetBuilder {
+			patterns: patterns.into_iter().map(|p| p.as_ref().to_owned()).collect(),
+			options:  Default::default(),
+		}
+	}
+
+	/// Consume the builder and compile the regular expression set.
+	pub fn build(&self) -> Result<LazyRegexSet, Error> {
+		for pattern in &self.patterns {
+			check(pattern)?;
+		}
+
+		Ok(LazyRegexSet::from_builder(self.clone()))
+	}
+
+	/// Set the value for the case insensitive (`i`) flag.
+	pub fn case_insensitive(&mut self, yes: bool) -> &mut LazyRegexSetBuilder {
+		self.options.case_insensitive = yes;
+		self
+	}
+
+	/// Set the value for the multi-line matching (`m`) flag.
+	pub fn multi_line(&mut self, yes: bool) -> &mut LazyRegexSetBuilder {
+		self.options.multi_line = yes;
+		self
+	}
+
+	/// Set the value for the any character (`s`) flag, where in `.` matches
+	/// any byte when `s` is set and matches anything except for new line when
+	/// it is not set (the default).
+	pub fn dot_matches_new_line(&mut self, yes: bool) -> &mut LazyRegexSetBuilder {
+		self.options.dot_matches_new_line = yes;
+		self
+	}
+
+	/// Set the value for the greedy swap (`U`) flag.
+	pub fn swap_greed(&mut self, yes: bool) -> &mut LazyRegexSetBuilder {
+		self.options.swap_greed = yes;
+		self
+	}
+
+	/// Set the value for the ignore whitespace (`x`) flag.
+	pub fn ignore_whitespace(&mut self, yes: bool) -> &mut LazyRegexSetBuilder {
+		self.options.ignore_whitespace = yes;
+		self
+	}
+
+	/// Set the value for the Unicode (`u`) flag.
+	///
+	/// When disabled, `.` and negated classes can match invalid UTF-8.
+	pub fn unicode(&mut self, yes: bool) -> &mut LazyRegexSetBuilder {
+		self.options.unicode = yes;
+		self
+	}
+
+	/// Set the byte treated as a line terminator by `^` and `$` in multi-line
+	/// mode.
+	pub fn line_terminator(&mut self, byte: u8) -> &mut LazyRegexSetBuilder {
+		self.options.line_terminator = byte;
+		self
+	}
+
+	/// Set the approximate size limit of the compiled regular expressions.
+	pub fn size_limit(&mut self, limit: usize) -> &mut LazyRegexSetBuilder {
+		self.options.size_limit = limit;
+		self
+	}
+
+	/// Set the approximate size of the cache used by the DFA.
+	pub fn dfa_size_limit(&mut self, limit: usize) -> &mut LazyRegexSetBuilder {
+		self.options.dfa_size_limit = limit;
+		self
+	}
+}
+
+/// An LRU cache for regular expressions over bytes.
+///
+/// This is a simpler counterpart of the text `RegexCache`, entries are keyed
+/// by their source and options.
+///
+/// # Example
+///
+/// ```
+/// # use regex_cache::bytes::RegexCache;
+/// let mut cache = RegexCache::new(100);
+///
+/// assert!(cache.compile(r"(?-u)^\xFF+$").unwrap().is_match(b"\xFF\xFF"));
+/// assert!(cache.is_match(r"(?-u)^\xFF+$", b"\xFF").unwrap());
+/// assert_eq!(cache.stats().hits, 1);
+/// ```
+#[derive(Clone, Debug)]
+pub struct RegexCache {
+	cache:    LinkedHashMap<RegexCacheKey, Regex>,
+	capacity: usize,
+	stats:    CacheStats,
+	scratch:  RegexCacheKey,
+}
+
+impl RegexCache {
+	/// Create a new LRU cache with the given size limit, where `0` means the
+	/// cache is unbounded.
+	pub fn new(capacity: usize) -> RegexCache {
+		RegexCache {
+			cache:    LinkedHashMap::new(),
+			capacity: bounded(capacity),
+			stats:    CacheStats::default(),
+			scratch:  RegexCacheKey::new("", Options::default()),
+		}
+	}
+
+	/// Get the number of entries in the cache.
+	pub fn len(&self) -> usize {
+		self.cache.len()
+	}
+
+	/// Check if the cache is empty.
+	pub fn is_empty(&self) -> bool {
+		self.cache.is_empty()
+	}
+
+	/// Get the maximum number of entries in the cache.
+	pub fn capacity(&self) -> usize {
+		self.capacity
+	}
+
+	/// Check if a regular expression for the given source is in the cache,
+	/// without counting as an access.
+	pub fn contains(&self, source: &str) -> bool {
+		self.contains_key(&RegexCacheKey::new(source, Options::default()))
+	}
+
+	/// Check if the given key is in the cache, without counting as an access.
+	pub(crate) fn contains_key(&self, key: &RegexCacheKey) -> bool {
+		self.cache.contains_key(key)
+	}
+
+	/// Get the hit and miss statistics of the cache.
+	pub fn stats(&self) -> CacheStats {
+		self.stats
+	}
+
+	/// Reset the hit and miss statistics of the cache.
+	pub fn reset_stats(&mut self) {
+		self.stats = CacheStats::default();
+	}
+
+	/// Remove all the entries from the cache.
+	pub fn clear(&mut self) {
+		self.cache.clear();
+	}
+
+	/// Save the given regular expression in the cache, keyed by its source.
+	pub fn save(&mut self, re: Regex) -> &Regex {
+		let source = re.as_str().to_owned();
+
+		match self.fetch(&source, &Options::default(), || Ok(re)) {
+			Ok(re) =>
+				re,
+
+			Err(_) =>
+				unreachable!(),
+		}
+	}
+
+	/// Create a new regular expression in the cache.
+	///
+	/// See `compile_with`.
+	pub fn compile(&mut self, source: &str) -> Result<&Regex, Error> {
+		self.compile_with(source, &Options::default())
+	}
+
+	/// Create a new regular expression in the cache with the given options.
+	///
+	/// Entries are keyed by both the source and the options, so the same
+	/// source can be cached with different options at the same time.
+	pub fn compile_with(&mut self, source: &str, options: &Options) -> Result<&Regex, Error> {
+		self.fetch(source, options, ||
+			options.define_bytes(&mut RegexBuilder::new(source)).build())
+	}
+
+	/// Configure a new regular expression.
+	///
+	/// The builder can't be part of the key, so the entry is cached under the
+	/// source with the default options, the same as `compile`.
+	pub fn configure<F>(&mut self, source: &str, f: F) -> Result<&Regex, Error>
+		where F: FnOnce(&mut RegexBuilder) -> &mut RegexBuilder
+	{
+		self.fetch(source, &Options::default(), ||
+			f(&mut RegexBuilder::new(source)).build())
+	}
+
+	/// Compile (or fetch) a regular expression and check if it matches `text`
+	/// in one call.
+	pub fn is_match(&mut self, source: &str, text: &[u8]) -> Result<bool, Error> {
+		Ok(self.compile(source)?.is_match(text))
+	}
+
+	/// Compile (or fetch) a regular expression and replace the first match in
+	/// `text`.
+	///
+	/// Refer to `Regex::replace` for the supported replacements.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::bytes::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	///
+	/// assert_eq!(cache.replace(r"(?P<n>\d+)", b"a1 b2", &b"<$n>"[..]).unwrap(), b"a<1> b2");
+	/// ```
+	pub fn replace<R: Replacer>(&mut self, source: &str, text: &[u8], rep: R) -> Result<Vec<u8>, Error> {
+		Ok(self.compile(source)?.replace(text, rep).into_owned())
+	}
+
+	/// Compile (or fetch) a regular expression and replace all the matches in
+	/// `text`.
+	///
+	/// Refer to `Regex::replace_all` for the supported replacements.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::bytes::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	///
+	/// assert_eq!(cache.replace_all(r"(?-u)\xFF", b"a\xFFb\xFF", &b"#"[..]).unwrap(), b"a#b#");
+	/// ```
+	pub fn replace_all<R: Replacer>(&mut self, source: &str, text: &[u8], rep: R) -> Result<Vec<u8>, Error> {
+		Ok(self.compile(source)?.replace_all(text, rep).into_owned())
+	}
+
+	/// Compile (or fetch) a regular expression and replace at most `limit`
+	/// matches in `text`, all of them if `limit` is `0`.
+	///
+	/// Refer to `Regex::replacen` for the supported replacements.
+	pub fn replacen<R: Replacer>(&mut self, source: &str, text: &[u8], limit: usize, rep: R) -> Result<Vec<u8>, Error> {
+		Ok(self.compile(source)?.replacen(text, limit, rep).into_owned())
+	}
+
+	/// Look up the entry for the given source and options, inserting the
+	/// result of `f` if it's missing.
+	///
+	/// The key buffer is reused so hits don't allocate.
+	fn fetch<F>(&mut self, source: &str, options: &Options, f: F) -> Result<&Regex, Error>
+		where F: FnOnce() -> Result<Regex, Error>
+	{
+		self.scratch.source.clear();
+		self.scratch.source.push_str(source);
+		self.scratch.options = *options;
+
+		if self.cache.get_refresh(&self.scratch).is_some() {
+			self.stats.hits += 1;
+		}
+		else {
+			self.stats.misses += 1;
+			let re = f()?;
+
+			while self.cache.len() >= self.capacity && self.cache.pop_front().is_some() {
+				self.stats.evictions += 1;
+			}
+
+			self.cache.insert(self.scratch.clone(), re);
+		}
+
+		Ok(self.cache.back().unwrap().1)
+	}
+}
+
+/// A `Regex` over bytes compiled on demand through a shared `RegexCache`.
+#[derive(Clone)]
+pub struct CachedRegex {
+	builder: CachedRegexBuilder,
+	memo:    OnceLock<Regex>,
+}
+
+/// Get the memoized regular expression, which can only fail to compile for
+/// the unchecked constructors.
+macro_rules! regex {
+	($self:ident) => (
+		match $self.compiled() {
+			Ok(re) =>
+				re,
+
+			Err(err) =>
+				panic!("invalid pattern {:?}: {}", $self.builder.source, err),
+		}
+	)
+}
+
+impl CachedRegex {
+	/// Create a new cached `Regex` for the given source, compiling it through
+	/// the cache so any error is returned here.
+	pub fn new(cache: Arc<Mutex<RegexCache>>, source: &str) -> Result<CachedRegex, Error> {
+		CachedRegexBuilder::new(cache, source).build()
+	}
+
+	/// Create a new cached `Regex` for the given source, compiled at the first
+	/// use.
+	///
+	/// Only use this if you know that the pattern is valid or you are ready to
+	/// handle errors later on with `compiled`, the other methods panic if the
+	/// pattern fails to compile.
+	pub fn new_unchecked(cache: Arc<Mutex<RegexCache>>, source: &str) -> CachedRegex {
+		CachedRegex::from(CachedRegexBuilder::new(cache, source))
+	}
+
+	fn from(builder: CachedRegexBuilder) -> Self {
+		CachedRegex {
+			builder: builder,
+			memo:    OnceLock::new(),
+		}
+	}
+
+	/// Get the compiled regular expression, fetching it from the cache at the
+	/// first use.
+	///
+	/// Only the unchecked constructors can leave an error to report here,
+	/// the others compile the regular expression up front.
+	pub fn compiled(&self) -> Result<&Regex, Error> {
+		if let Some(re) = self.memo.get() {
+			return Ok(re);
+		}
+
+		let re = self.builder.cache.lock().unwrap()
+			.compile_with(&self.builder.source, &self.builder.options)?.clone();
+
+		Ok(self.memo.get_or_init(|| re))
+	}
+
+	/// Refer to `Regex::is_match`.
+	pub fn is_match(&self, text: &[u8]) -> bool {
+		regex!(self).is_match(text)
+	}
+
+	/// Refer to `Regex::find`.
+	pub fn find<'t>(&self, text: &'t [u8]) -> Option<Match<'t>> {
+		regex!(self).find(text)
+	}
+
+	/// Refer to `Regex::captures`.
+	pub fn captures<'t>(&self, text: &'t [u8]) -> Option<Captures<'t>> {
+		regex!(self).captures(text)
+	}
+
+	/// Refer to `Regex::replace`.
+	pub fn replace<'t, R: Replacer>(&self, text: &'t [u8], rep: R) -> Cow<'t, [u8]> {
+		regex!(self).replace(text, rep)
+	}
+
+	/// Refer to `Regex::replace_all`.
+	pub fn replace_all<'t, R: Replacer>(&self, text: &'t [u8], rep: R) -> Cow<'t, [u8]> {
+		regex!(self).replace_all(text, rep)
+	}
+
+	/// Refer to `Regex::split`.
+	pub fn split<'r, 't>(&'r self, text: &'t [u8]) -> Split<'r, 't> {
+		regex!(self).split(text)
+	}
+
+	/// Refer to `Regex::replacen`.
+	pub fn replacen<'t, R: Replacer>(&self, text: &'t [u8], limit: usize, rep: R) -> Cow<'t, [u8]> {
+		regex!(self).replacen(text, limit, rep)
+	}
+
+	/// Refer to `Regex::splitn`.
+	pub fn splitn<'r, 't>(&'r self, text: &'t [u8], limit: usize) -> SplitN<'r, 't> {
+		regex!(self).splitn(text, limit)
+	}
+
+	/// Refer to `Regex::shortest_match`.
+	pub fn shortest_match(&self, text: &[u8]) -> Option<usize> {
+		regex!(self).shortest_match(text)
+	}
+
+	/// Refer to `Regex::captures_len`.
+	pub fn captures_len(&self) -> usize {
+		regex!(self).captures_len()
+	}
+
+	/// Refer to `Regex::capture_names`.
+	pub fn capture_names(&self) -> CaptureNames<'_> {
+		regex!(self).capture_names()
+	}
+
+	/// Get the cache shared by the whole process, used by `FromStr`.
+	///
+	/// It holds up to `DEFAULT_CAPACITY` entries and is created at the first
+	/// call.
+	pub fn default_cache() -> Arc<Mutex<RegexCache>> {
+		static CACHE: OnceLock<Arc<Mutex<RegexCache>>> = OnceLock::new();
+		CACHE.get_or_init(|| Arc::new(Mutex::new(RegexCache::new(DEFAULT_CAPACITY)))).clone()
+	}
+
+	/// Get the source given to `new`.
+	pub fn as_str(&self) -> &str {
+		&self.builder.source
+	}
+}
+
+/// Create a regular expression over bytes in `CachedRegex::default_cache`.
+///
+/// # Example
+///
+/// ```
+/// # use regex_cache::bytes::CachedRegex;
+/// let re = r"^\d+$".parse::<CachedRegex>().unwrap();
+///
+/// assert!(re.is_match(b"1234"));
+/// ```
+impl str::FromStr for CachedRegex {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<CachedRegex, Error> {
+		CachedRegex::new(CachedRegex::default_cache(), s)
+	}
+}
+
+/// Two cached regular expressions are equal when they have the same source
+/// and options, whatever cache they use.
+///
+/// Different patterns matching the same texts are not equal.
+impl PartialEq for CachedRegex {
+	fn eq(&self, other: &CachedRegex) -> bool {
+		self.builder.source == other.builder.source &&
+			self.builder.options == other.builder.options
+	}
+}
+
+impl Eq for CachedRegex { }
+
+impl Hash for CachedRegex {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.builder.source.hash(state);
+		self.builder.options.hash(state);
+	}
+}
+
+/// Shows the source, the options and whether the regular expression is in the
+/// cache, which is `None` if the cache is locked or poisoned.
+///
+/// The cache is never waited on and nothing gets compiled, so formatting is
+/// safe from a panic handler.
+impl fmt::Debug for CachedRegex {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let key    = RegexCacheKey::new(&self.builder.source, self.builder.options);
+		let cached = self.builder.cache.try_lock().ok().map(|cache| cache.contains_key(&key));
+
+		f.debug_struct("CachedRegex")
+			.field("source", &self.builder.source)
+			.field("options", &self.builder.options)
+			.field("cached", &cached)
+			.finish()
+	}
+}
+
+/// Shows the source, without locking the cache.
+impl fmt::Display for CachedRegex {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(&self.builder.source)
+	}
+}
+
+/// A configurable builder for a cached `Regex` over bytes.
+#[derive(Clone, Debug)]
+pub struct CachedRegexBuilder {
+	cache:   Arc<Mutex<RegexCache>>,
+	source:  String,
+	options: Options,
+}
+
+impl CachedRegexBuilder {
+	/// Create a new regular expression builder with the given pattern.
+	///
+	/// If the pattern is invalid, then an error will be returned when `build`
+	/// is called.
+	pub fn new(cache: Arc<Mutex<RegexCache>>, source: &str) -> CachedRegexBuilder {
+		CachedRegexBuilder {
+			cache:   cache,
+			source:  source.to_owned(),
+			options: Default::default(),
+		}
+	}
+
+	/// Consume the builder and compile the regular expression through the
+	/// cache, so syntax errors and exceeded limits are returned here.
+	pub fn build(&self) -> Result<CachedRegex, Error> {
+		let re = CachedRegex::from(self.clone());
+		re.compiled()?;
+
+		Ok(re)
+	}
+
+	/// Consume the builder and create the cached regular expression, compiled
+	/// at the first use.
+	///
+	/// Only use this if you know that the pattern is valid or you are ready to
+	/// handle errors later on, see `CachedRegex::compiled`.
+	pub fn build_unchecked(&self) -> CachedRegex {
+		CachedRegex::from(self.clone())
+	}
+
+	/// Set the value for the case insensitive (`i`) flag.
+	pub fn case_insensitive(&mut self, yes: bool) -> &mut CachedRegexBuilder {
+		self.options.case_insensitive = yes;
+		self
+	}
+
+	/// Set the value for the multi-line matching (`m`) flag.
+	pub fn multi_line(&mut self, yes: bool) -> &mut CachedRegexBuilder {
+		self.options.multi_line = yes;
+		self
+	}
+
+	/// Set the value for the any character (`s`) flag, where in `.` matches
+	/// any byte when `s` is set and matches anything except for new line when
+	/// it is not set (the default).
+	pub fn dot_matches_new_line(&mut self, yes: bool) -> &mut CachedRegexBuilder {
+		self.options.dot_matches_new_line = yes;
+		self
+	}
+
+	/// Set the value for the greedy swap (`U`) flag.
+	pub fn swap_greed(&mut self, yes: bool) -> &mut CachedRegexBuilder {
+		self.options.swap_greed = yes;
+		self
+	}
+
+	/// Set the value for the ignore whitespace (`x`) flag.
+	pub fn ignore_whitespace(&mut self, yes: bool) -> &mut CachedRegexBuilder {
+		self.options.ignore_whitespace = yes;
+		self
+	}
+
+	/// Set the value for the Unicode (`u`) flag.
+	///
+	/// When disabled, `.` and negated classes can match invalid UTF-8.
+	pub fn unicode(&mut self, yes: bool) -> &mut CachedRegexBuilder {
+		self.options.unicode = yes;
+		self
+	}
+
+	/// Set the line terminator used by `^` and `$` in multi-line mode, the
+	/// default is `\n`.
+	pub fn line_terminator(&mut self, byte: u8) -> &mut CachedRegexBuilder {
+		self.options.line_terminator = byte;
+		self
+	}
+
+	/// Set the maximum nesting depth of the pattern, the default is `250`.
+	///
+	/// Both the syntax check in `build` and the compilation use this limit.
+	pub fn nest_limit(&mut self, limit: u32) -> &mut CachedRegexBuilder {
+		self.options.nest_limit = limit;
+		self
+	}
+
+	/// Set the approximate size limit of the compiled regular expression.
+	pub fn size_limit(&mut self, limit: usize) -> &mut CachedRegexBuilder {
+		self.options.size_limit = limit;
+		self
+	}
+
+	/// Set the approximate size of the cache used by the DFA.
+	pub fn dfa_size_limit(&mut self, limit: usize) -> &mut CachedRegexBuilder {
+		self.options.dfa_size_limit = limit;
+		self
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::sync::{Arc, Mutex};
+	use regex::Error;
+	use regex::bytes::Regex;
+	use crate::bytes::{LazyRegex, LazyRegexSet, LazyRegexSetBuilder, RegexCache, CachedRegex, CachedRegexBuilder};
+
+	fn matches(set: &LazyRegexSet, text: &[u8]) -> Vec<usize> {
+		set.matches(text).into_iter().collect()
+	}
+
+	#[test]
+	fn set() {
+		let set = LazyRegexSet::new([r"^\d+$", r"(?-u)\xFF\x00", r"[a-z]+"]).unwrap();
+
+		assert_eq!(matches(&set, b"1234"), [0]);
+		assert_eq!(matches(&set, b"ab\xFF\x00cd"), [1, 2]);
+		assert!(!set.is_match(b"\xFE"));
+
+		let other = set.clone();
+		assert_eq!(matches(&other, b"abc"), [2]);
+	}
+
+	#[test]
+	fn builder() {
+		let set = LazyRegexSetBuilder::new(["^abc$", "^def$"])
+			.case_insensitive(true)
+			.multi_line(true)
+			.build().unwrap();
+
+		assert_eq!(matches(&set, b"x\nABC\nDef"), [0, 1]);
+		assert!(LazyRegexSet::new(["a", "("]).is_err());
+	}
+
+	#[test]
+	fn cache() {
+		let mut cache = RegexCache::new(2);
+
+		assert!(cache.compile(r"(?-u)\xFF").unwrap().is_match(b"a\xFF"));
+		assert!(cache.compile_with("abc", &crate::Options { case_insensitive: true, .. Default::default() })
+			.unwrap().is_match(b"ABC"));
+		assert!(!cache.compile("abc").unwrap().is_match(b"ABC"));
+		assert!(!cache.contains(r"(?-u)\xFF"));
+		assert!(cache.compile("(").is_err());
+
+		let stats = cache.stats();
+		assert_eq!((stats.hits, stats.misses, stats.evictions), (0, 4, 1));
+		assert_eq!(cache.len(), 2);
+	}
+
+	#[test]
+	fn cache_replace() {
+		let mut cache = RegexCache::new(10);
+
+		assert_eq!(cache.replace_all(r"\d", b"abc", &b"#"[..]).unwrap(), b"abc");
+		assert_eq!(cache.replace(r"\d", b"a1b2", &b"#"[..]).unwrap(), b"a#b2");
+		assert_eq!(cache.replacen(r"\d", b"1234", 2, &b"#"[..]).unwrap(), b"##34");
+
+		assert_eq!(cache.replace_all(r"(?P<k>\w+)=(?P<v>\w+)", b"a=1 b=2", &b"$v=$k"[..]).unwrap(),
+			b"1=a 2=b");
+
+		assert_eq!(cache.replace_all(r"(?-u)[\x80-\xFF]+", b"a\xFF\xFEb", |caps: &regex::bytes::Captures| {
+			caps[0].len().to_string().into_bytes()
+		}).unwrap(), b"a2b");
+
+		assert!(cache.replace_all("(", b"abc", &b""[..]).is_err());
+	}
+
+	#[test]
+	fn cached_regex() {
+		let cache = Arc::new(Mutex::new(RegexCache::new(10)));
+		let re    = CachedRegex::new(cache.clone(), r"(?-u)[\x00\xFF]").unwrap();
+		let ci    = CachedRegexBuilder::new(cache.clone(), "ab").case_insensitive(true).build().unwrap();
+
+		assert!(re.is_match(b"a\xFFb"));
+		assert_eq!(re.split(b"a\x00b\xFFc").collect::<Vec<_>>(), [&b"a"[..], b"b", b"c"]);
+		assert_eq!(re.replace_all(b"a\x00b", &b"-"[..]), &b"a-b"[..]);
+		assert_eq!(ci.find(b"xAB").map(|m| m.start()), Some(1));
+		assert!(CachedRegex::new(cache.clone(), "(").is_err());
+
+		assert_eq!(cache.lock().unwrap().len(), 2);
+		assert_eq!(re.to_string(), r"(?-u)[\x00\xFF]");
+	}
+
+	#[test]
+	fn cached_regex_parity() {
+		let cache  = Arc::new(Mutex::new(RegexCache::new(10)));
+		let source = r"(?-u)(?P<key>\w+)=(\xFF)?";
+		let cached = CachedRegex::new(cache, source).unwrap();
+		let plain  = Regex::new(source).unwrap();
+		let text   = b"a=\xFF b= c=\xFF d=";
+
+		assert_eq!(cached.replacen(text, 2, &b"$key"[..]), plain.replacen(text, 2, &b"$key"[..]));
+		assert!(cached.splitn(text, 3).eq(plain.splitn(text, 3)));
+		assert_eq!(cached.shortest_match(text), plain.shortest_match(text));
+		assert_eq!(cached.captures_len(), plain.captures_len());
+		assert!(cached.capture_names().eq(plain.capture_names()));
+	}
+
+	#[test]
+	fn cached_regex_options() {
+		let cache   = Arc::new(Mutex::new(RegexCache::new(100)));
+		let large   = CachedRegexBuilder::new(cache.clone(), r"\w{10}").size_limit(1 << 20).build().unwrap();
+		let limited = CachedRegexBuilder::new(cache.clone(), r"\w{10}").size_limit(100).clone();
+
+		// The limited one doesn't get the entry cached for the other limit,
+		// and reports the error instead of panicking at the first match.
+		assert!(matches!(limited.build(), Err(Error::CompiledTooBig(100))));
+		assert!(limited.build_unchecked().compiled().is_err());
+		assert!(large.is_match(b"abcdefghij"));
+		assert_eq!(cache.lock().unwrap().len(), 1);
+
+		let crlf = CachedRegexBuilder::new(cache.clone(), "^b$")
+			.multi_line(true)
+			.line_terminator(b'\r')
+			.build().unwrap();
+		assert!(crlf.is_match(b"a\rb\rc"));
+
+		assert!(CachedRegexBuilder::new(cache.clone(), "((a))").nest_limit(1).build().is_err());
+		assert!(CachedRegexBuilder::new(cache, "((a))").nest_limit(2).build().is_ok());
+	}
+
+	#[test]
+	fn lazy_conversions() {
+		let text  = crate::LazyRegexBuilder::new(r"\d+").anchor_pattern(true).case_insensitive(true).build().unwrap();
+		let bytes = text.to_bytes();
+
+		assert!(bytes.is_match(b"123"));
+		assert!(!bytes.is_match(b"a123"));
+
+		let back = bytes.to_text().unwrap();
+		assert!(back.is_match("123") && !back.is_match("123a"));
+		assert_eq!(back.as_str(), bytes.as_str());
+
+		assert!(LazyRegex::new(r"(?-u)\xFF").unwrap().to_text().is_err());
+		assert!(LazyRegex::new("(").is_err());
+	}
+
+	#[test]
+	fn cached_regex_flags_in_key() {
+		for &insensitive_first in &[true, false] {
+			let cache = Arc::new(Mutex::new(RegexCache::new(100)));
+			let build = |insensitive: bool| if insensitive {
+				CachedRegexBuilder::new(cache.clone(), "abc").case_insensitive(true).build().unwrap()
+			}
+			else {
+				CachedRegex::new(cache.clone(), "abc").unwrap()
+			};
+
+			let first  = build(insensitive_first);
+			assert_eq!(first.is_match(b"ABC"), insensitive_first);
+
+			let second = build(!insensitive_first);
+			assert_eq!(second.is_match(b"ABC"), !insensitive_first);
+			assert_eq!(cache.lock().unwrap().len(), 2);
+		}
+	}
+}
@@ -18,265 +18,3020 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-use std::ops::{Deref, DerefMut};
-use std::sync::{Mutex, Arc};
+use std::sync::{Mutex, Arc, OnceLock};
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::RandomState;
+use std::hash::{Hash, Hasher, BuildHasher};
+use std::time::{Duration, Instant};
 use std::borrow::Cow;
+use std::mem;
+use std::thread;
+use std::error;
 use std::fmt;
 use std::str;
 
 use regex::{Regex, RegexBuilder, Error};
-use regex::{Match, Captures, Replacer};
+use regex::{Match, Captures, Replacer, Split, SplitN, CaptureNames};
+use linked_hash_map::LinkedHashMap;
 use crate::syntax;
-use crate::options::Options;
-use crate::lru::LruCache;
+use crate::options::{Options, RegexFlags};
+use crate::guard::{PatternLimits, guard};
+use crate::clock::{Clock, SystemClock};
+use crate::shared::SharedRegexCache;
+use crate::captures::{OwnedCaptures, OwnedMatch};
+use crate::policy::{EvictionPolicy, LruPolicy};
 
 /// An LRU cache for regular expressions.
-#[derive(Clone, Debug)]
-pub struct RegexCache(LruCache<String, Regex>);
+///
+/// Entries can optionally expire after a time-to-live, see `set_ttl`.
+pub type RegexCache = GenericRegexCache<LruPolicy>;
+
+/// An LRU cache for regular expressions carrying user metadata with each
+/// entry, see `compile_with_meta`.
+pub type MetaRegexCache<M> = GenericRegexCache<LruPolicy, RandomState, M>;
+
+/// A cache for regular expressions with a pluggable eviction policy.
+///
+/// Entries with a lower access weight are still evicted first (see
+/// `compile_with_hint`), the policy decides the order among entries with the
+/// same weight. The keys are hashed with `S`, see `with_hasher`, and each
+/// entry can carry metadata of type `M`, see `compile_with_meta`.
+///
+/// Cloning the cache keeps the entries in the same order with the same
+/// configuration and statistics, use `reset_stats` on the clone to start
+/// counting afresh. The clones share the memory of the compiled programs, since
+/// `Regex` is reference counted, but are otherwise independent, so a clone can
+/// be taken before a risky batch of work and restored afterwards.
+///
+/// # Example
+///
+/// ```
+/// # use regex_cache::{GenericRegexCache, LruPolicy};
+/// let mut cache = GenericRegexCache::<LruPolicy>::new(100);
+///
+/// assert!(cache.compile(r"^\d+$").unwrap().is_match("1234"));
+/// ```
+#[derive(Clone)]
+pub struct GenericRegexCache<P, S: BuildHasher = RandomState, M = ()> {
+	cache:    P,
+	ttl:      Option<Duration>,
+	clock:    Arc<dyn Clock>,
+	budget:   Option<usize>,
+	used:     usize,
+	weighted: usize,
+	meta:     HashMap<RegexCacheKey, Meta, S>,
+	groups:   HashMap<String, HashSet<RegexCacheKey, S>, S>,
+	names:    HashMap<String, String, S>,
+	limits:   Option<Arc<PatternLimits>>,
+	stats:    CacheStats,
+	scratch:  RegexCacheKey,
+	defaults: Options,
+	data:     HashMap<RegexCacheKey, M, S>,
+	errors:   Option<LinkedHashMap<RegexCacheKey, Error, S>>,
+	on_evict: Option<OnEvict<M>>,
+	hasher:   S,
+}
+
+/// The function called with the metadata of entries dropped by the cache.
+type OnEvict<M> = Arc<dyn Fn(&RegexCacheKey, Option<M>, EvictionReason) + Send + Sync>;
+
+/// An invalid cache configuration.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum CacheError {
+	/// The cache can't hold any entry.
+	ZeroCapacity,
+}
 
-impl RegexCache {
-	/// Create a new LRU cache with the given size limit.
-	pub fn new(capacity: usize) -> RegexCache {
-		RegexCache(LruCache::new(capacity))
+impl fmt::Display for CacheError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			CacheError::ZeroCapacity =>
+				f.write_str("the capacity of a cache must not be zero"),
+		}
 	}
+}
 
-	/// Save the given regular expression in the cache.
+impl error::Error for CacheError { }
+
+/// Which entry to keep when merging caches that hold the same key, see
+/// `RegexCache::merge`.
+#[derive(Copy, Clone, Eq, PartialEq, Default, Debug)]
+pub enum Collision {
+	/// Keep the entry already in the cache being merged into, only marking it
+	/// as used.
+	#[default]
+	KeepExisting,
+
+	/// Replace the entry with the one from the cache being merged from.
+	PreferOther,
+}
+
+/// Why the cache dropped an entry on its own, see `RegexCache::set_on_evict`.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum EvictionReason {
+	/// The cache needed room, either for the number of entries or for the
+	/// memory budget.
+	Capacity,
+
+	/// The entry outlived the time-to-live.
+	Expired,
+
+	/// The entry wasn't used for too long, see `RegexCache::evict_idle`.
+	Idle,
+}
+
+/// Hit and miss statistics of a `RegexCache`.
+#[derive(Copy, Clone, Eq, PartialEq, Default, Debug)]
+pub struct CacheStats {
+	/// The number of lookups that found a compiled regular expression, or a
+	/// cached error.
+	pub hits: u64,
+
+	/// The number of lookups that had to compile a regular expression.
+	pub misses: u64,
+
+	/// The number of entries evicted to make room for new ones.
+	pub evictions: u64,
+
+	/// The shortest time spent compiling on a miss.
+	pub min_compile_time: Duration,
+
+	/// The longest time spent compiling on a miss.
+	pub max_compile_time: Duration,
+
+	/// The total time spent compiling on misses.
+	pub total_compile_time: Duration,
+}
+
+impl CacheStats {
+	/// Get the average time spent compiling on a miss, if there was any.
+	pub fn mean_compile_time(&self) -> Option<Duration> {
+		if self.misses == 0 {
+			return None;
+		}
+
+		Some(Duration::from_nanos((self.total_compile_time.as_nanos() / self.misses as u128) as u64))
+	}
+
+	/// Combine the statistics of two caches.
+	pub(crate) fn combine(self, other: CacheStats) -> CacheStats {
+		let min_compile_time = match (self.misses, other.misses) {
+			(0, _) => other.min_compile_time,
+			(_, 0) => self.min_compile_time,
+			_      => self.min_compile_time.min(other.min_compile_time),
+		};
+
+		CacheStats {
+			hits:               self.hits + other.hits,
+			misses:             self.misses + other.misses,
+			evictions:          self.evictions + other.evictions,
+			min_compile_time:   min_compile_time,
+			max_compile_time:   self.max_compile_time.max(other.max_compile_time),
+			total_compile_time: self.total_compile_time + other.total_compile_time,
+		}
+	}
+
+	/// Account for a compilation that took the given time, after counting
+	/// the miss.
+	fn record_compile(&mut self, elapsed: Duration) {
+		self.min_compile_time = if self.misses <= 1 {
+			elapsed
+		}
+		else {
+			self.min_compile_time.min(elapsed)
+		};
+
+		self.max_compile_time    = self.max_compile_time.max(elapsed);
+		self.total_compile_time += elapsed;
+	}
+}
+
+/// Usage statistics of a single entry of a `RegexCache`.
+///
+/// They're kept as long as the entry is in the cache, an entry that gets
+/// evicted and compiled again starts from scratch.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct EntryStats {
+	/// The number of lookups that found the entry.
+	pub hits: u64,
+
+	/// When the entry was inserted.
+	pub inserted: Instant,
+
+	/// When the entry was last looked up, or inserted if it never was.
+	pub last_access: Instant,
+}
+
+/// Bookkeeping kept alongside each entry.
+#[derive(Copy, Clone, Debug)]
+struct Meta {
+	inserted: Instant,
+	accessed: Instant,
+	hits:     u64,
+	weight:   u32,
+	size:     usize,
+	compiled: Option<Duration>,
+}
+
+/// How a miss in `GenericRegexCache::lookup` is accounted for.
+#[derive(Copy, Clone, Debug)]
+enum Miss {
+	/// The function compiles the regular expression, and is timed.
+	Compile,
+
+	/// The function hands back a regular expression compiled elsewhere in
+	/// the given time.
+	Compiled(Duration),
+
+	/// The function hands back an existing regular expression, which doesn't
+	/// count as a miss.
+	Insert,
+}
+
+/// The capacity of a cache created with `Default` or `with_default_capacity`.
+pub const DEFAULT_CAPACITY: usize = 256;
+
+/// Get the capacity to use for the given requested one, where `0` means
+/// unbounded.
+pub(crate) fn bounded(capacity: usize) -> usize {
+	if capacity == 0 {
+		usize::MAX
+	}
+	else {
+		capacity
+	}
+}
+
+impl<P: EvictionPolicy, S: BuildHasher + Default + Clone, M> GenericRegexCache<P, S, M> {
+	/// Create a new cache with the given size limit.
+	///
+	/// A capacity of `0` means the cache is unbounded, it's the same as
+	/// `usize::MAX`.
 	///
 	/// # Example
 	///
 	/// ```
-	/// # use regex_cache::{Regex, RegexCache};
-	/// let mut cache = RegexCache::new(100);
-	/// let     re    = Regex::new(r"^\d+$").unwrap();
-	///
-	/// // By saving the previously created regular expression further calls to
-	/// // `compile` won't actually compile the regular expression.
-	/// cache.save(re);
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(0);
 	///
 	/// assert!(cache.compile(r"^\d+$").unwrap().is_match("1234"));
-	/// assert!(!cache.compile(r"^\d+$").unwrap().is_match("abcd"));
+	/// assert_eq!(cache.capacity(), usize::MAX);
 	/// ```
-	pub fn save(&mut self, re: Regex) -> &Regex {
-		let source = re.as_str().to_owned();
+	pub fn new(capacity: usize) -> Self {
+		GenericRegexCache {
+			cache:    P::with_capacity(bounded(capacity)),
+			ttl:      None,
+			clock:    Arc::new(SystemClock),
+			budget:   None,
+			used:     0,
+			weighted: 0,
+			meta:     HashMap::default(),
+			groups:   HashMap::default(),
+			names:    HashMap::default(),
+			limits:   None,
+			stats:    CacheStats::default(),
+			scratch:  RegexCacheKey::new("", Options::default()),
+			defaults: Options::default(),
+			data:     HashMap::default(),
+			errors:   None,
+			on_evict: None,
+			hasher:   S::default(),
+		}
+	}
 
-		if !self.0.contains_key(re.as_str()) {
-			self.insert(source.clone(), re);
+	/// Create a new cache with the given size limit, rejecting a capacity of
+	/// `0`.
+	///
+	/// Unlike `new` a zero capacity is treated as a mistake rather than as
+	/// unbounded, use `usize::MAX` for an unbounded cache. Nothing is reserved
+	/// up front, so a huge capacity costs nothing until it's used.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::{RegexCache, CacheError};
+	/// assert_eq!(RegexCache::try_new(0).unwrap_err(), CacheError::ZeroCapacity);
+	/// assert_eq!(RegexCache::try_new(100).unwrap().capacity(), 100);
+	/// ```
+	pub fn try_new(capacity: usize) -> Result<Self, CacheError> {
+		if capacity == 0 {
+			return Err(CacheError::ZeroCapacity);
 		}
 
-		self.0.get_mut(&source).unwrap()
+		Ok(Self::new(capacity))
 	}
 
-	/// Create a new regular expression in the cache.
+	/// Create a new cache that compiles every regular expression with the
+	/// given size limit, in bytes.
+	///
+	/// Patterns that would compile to something bigger fail with an error
+	/// instead of using unbounded memory, which matters when the patterns come
+	/// from untrusted input. Without it the default limit of the `regex` crate
+	/// is used, see `Options::size_limit`.
 	///
 	/// # Example
 	///
 	/// ```
 	/// # use regex_cache::RegexCache;
-	/// let mut cache = RegexCache::new(100);
+	/// let mut cache = RegexCache::with_size_limit(64 * 1024);
 	///
 	/// assert!(cache.compile(r"^\d+$").unwrap().is_match("1234"));
-	/// assert!(!cache.compile(r"^\d+$").unwrap().is_match("abcd"));
+	/// assert!(cache.compile(r"\w{1000}").is_err());
 	/// ```
-	pub fn compile(&mut self, source: &str) -> Result<&Regex, Error> {
-		if !self.0.contains_key(source) {
-			self.0.insert(source.into(), Regex::new(source)?);
+	pub fn with_size_limit(bytes: usize) -> Self {
+		let mut cache = Self::with_default_capacity();
+		cache.defaults.size_limit = bytes;
+		cache
+	}
+
+	/// Check every pattern against the given limits before compiling it.
+	///
+	/// Patterns that break the limits fail with a syntax error without being
+	/// parsed in full, see `check_pattern`. Patterns already in the cache
+	/// aren't checked again.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::{RegexCache, PatternLimits};
+	/// let mut cache = RegexCache::new(100);
+	/// cache.set_pattern_limits(PatternLimits { max_len: Some(16), .. Default::default() });
+	///
+	/// assert!(cache.compile(r"^\d+$").is_ok());
+	/// assert!(cache.compile(&"a".repeat(17)).is_err());
+	/// ```
+	pub fn set_pattern_limits(&mut self, limits: PatternLimits) {
+		self.limits = Some(Arc::new(limits));
+
+		if let Some(ref mut errors) = self.errors {
+			errors.clear();
 		}
+	}
 
-		Ok(self.0.get_mut(source).unwrap())
+	/// Get the limits patterns are checked against, if any.
+	pub fn pattern_limits(&self) -> Option<&PatternLimits> {
+		self.limits.as_deref()
 	}
 
-	/// Configure a new regular expression.
+	/// Remember the patterns that failed to compile, so looking them up again
+	/// returns the same error without compiling them again.
+	///
+	/// At most as many errors as the capacity are kept, dropping the oldest
+	/// first, and they're forgotten on `clear` or when the pattern limits
+	/// change. A cached error counts as a hit.
 	///
 	/// # Example
 	///
 	/// ```
 	/// # use regex_cache::RegexCache;
 	/// let mut cache = RegexCache::new(100);
+	/// cache.set_cache_errors(true);
 	///
-	/// assert!(cache.configure(r"abc", |b| b.case_insensitive(true)).unwrap()
-	/// 	.is_match("ABC"));
-	///
-	/// assert!(!cache.configure(r"abc", |b| b.case_insensitive(true)).unwrap()
-	/// 	.is_match("123"));
+	/// assert!(cache.compile(r"^[a-z+$").is_err());
+	/// assert!(cache.compile(r"^[a-z+$").is_err());
+	/// assert_eq!((cache.stats().hits, cache.stats().misses), (1, 1));
 	/// ```
-	pub fn configure<F>(&mut self, source: &str, f: F) -> Result<&Regex, Error>
-		where F: FnOnce(&mut RegexBuilder) -> &mut RegexBuilder
-	{
-		if !self.0.contains_key(source) {
-			self.0.insert(source.into(), f(&mut RegexBuilder::new(source)).build()?);
+	pub fn set_cache_errors(&mut self, enabled: bool) {
+		self.errors = if enabled {
+			Some(LinkedHashMap::with_hasher(self.hasher.clone()))
 		}
-
-		Ok(self.0.get_mut(source).unwrap())
+		else {
+			None
+		};
 	}
-}
-
-impl Deref for RegexCache {
-	type Target = LruCache<String, Regex>;
 
-	fn deref(&self) -> &Self::Target {
-		&self.0
+	/// Check if the patterns that failed to compile are remembered.
+	pub fn caches_errors(&self) -> bool {
+		self.errors.is_some()
 	}
-}
 
-impl DerefMut for RegexCache {
-	fn deref_mut(&mut self) -> &mut Self::Target {
-		&mut self.0
+	/// Get the size limit used to compile regular expressions, in bytes.
+	pub fn size_limit(&self) -> usize {
+		self.defaults.size_limit
 	}
-}
-
-#[derive(Clone)]
-pub struct CachedRegex {
-	builder: CachedRegexBuilder,
-}
-
-macro_rules! regex {
-	($self:ident) => (
-		$self.builder.cache.lock().unwrap().configure(&$self.builder.source, |b|
-			$self.builder.options.define(b)).unwrap()
-	)
-}
-
-impl CachedRegex {
-	/// Create a new cached `Regex` for the given source, checking the syntax is
-	/// valid.
-	pub fn new(cache: Arc<Mutex<RegexCache>>, source: &str) -> Result<CachedRegex, Error> {
-		if let Err(err) = syntax::Parser::new().parse(source) {
-			return Err(Error::Syntax(err.to_string()));
-		}
 
-		Ok(CachedRegex::new_unchecked(cache, source))
+	/// Create a new cache with a capacity of `DEFAULT_CAPACITY`.
+	pub fn with_default_capacity() -> Self {
+		Self::new(DEFAULT_CAPACITY)
 	}
 
-	/// Create a new cached `Regex` for the given source, without checking if the 
-	/// syntax is valid.
-	/// 
-	/// Only use this if you know that the syntax is valid or you are ready to 
-	/// handle potential syntax errors later on.
-	pub fn new_unchecked(cache: Arc<Mutex<RegexCache>>, source: &str) -> CachedRegex {
-		CachedRegex::from(CachedRegexBuilder::new(cache, source))
+	/// Get the hit and miss statistics of the cache.
+	pub fn stats(&self) -> CacheStats {
+		self.stats
 	}
 
-	fn from(builder: CachedRegexBuilder) -> Self {
-		CachedRegex {
-			builder: builder,
-		}
+	/// Reset the hit and miss statistics of the cache.
+	///
+	/// The statistics of each entry are kept, see `entry_stats`.
+	pub fn reset_stats(&mut self) {
+		self.stats = CacheStats::default();
 	}
 
-	/// Refer to `Regex::is_match`.
-	pub fn is_match(&self, text: &str) -> bool {
-		regex!(self).is_match(text)
-	}
+	/// Get the usage statistics of the entry for the given source, if it's in
+	/// the cache.
+	///
+	/// This doesn't count as an access.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	/// cache.compile(r"^\d+$").unwrap();
+	/// cache.compile(r"^\d+$").unwrap();
+	///
+	/// assert_eq!(cache.entry_stats(r"^\d+$").unwrap().hits, 1);
+	/// assert!(cache.entry_stats(r"^[a-z]+$").is_none());
+	/// ```
+	pub fn entry_stats(&self, source: &str) -> Option<EntryStats> {
+		let key = RegexCacheKey::new(source, self.defaults);
 
-	/// Refer to `Regex::find`.
-	pub fn find<'t>(&self, text: &'t str) -> Option<Match<'t>> {
-		regex!(self).find(text)
+		self.meta.get(&key).filter(|_| !self.is_expired(&key)).map(|meta| EntryStats {
+			hits:        meta.hits,
+			inserted:    meta.inserted,
+			last_access: meta.accessed,
+		})
 	}
 
-	/// Refer to `Regex::captures`.
-	pub fn captures<'t>(&self, text: &'t str) -> Option<Captures<'t>> {
-		regex!(self).captures(text)
+	/// Get the time it took to compile each entry, in eviction order.
+	///
+	/// Entries that were inserted without being compiled by this cache, like
+	/// the ones merged from another cache without timings, are skipped.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	/// cache.compile(r"^\d+$").unwrap();
+	///
+	/// let times = cache.compile_times().collect::<Vec<_>>();
+	/// assert_eq!(times.len(), 1);
+	/// assert_eq!(times[0].0, r"^\d+$");
+	/// assert!(cache.stats().max_compile_time >= times[0].1);
+	/// ```
+	pub fn compile_times(&self) -> impl Iterator<Item = (&str, Duration)> {
+		self.cache.entries().filter_map(move |(key, _)|
+			self.meta.get(key).and_then(|meta| meta.compiled).map(|elapsed| (key.source.as_str(), elapsed)))
 	}
 
-	/// Refer to `Regex::replace`.
-	pub fn replace<'t, R: Replacer>(&self, text: &'t str, rep: R) -> Cow<'t, str> {
-		regex!(self).replace(text, rep)
-	}
+	/// Get the sources of the `n` entries with the most hits, with their hit
+	/// count.
+	///
+	/// Entries with the same number of hits are ordered from the most to the
+	/// least recently used. Entries compiled with different options are
+	/// reported separately, and expired entries are skipped.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	/// cache.warm(["a", "b", "a", "c", "a", "b"]);
+	///
+	/// assert_eq!(cache.most_used(2), [("a", 2), ("b", 1)]);
+	/// ```
+	pub fn most_used(&self, n: usize) -> Vec<(&str, u64)> {
+		let mut entries = self.meta.iter()
+			.filter(|&(key, _)| !self.is_expired(key))
+			.collect::<Vec<_>>();
 
-	/// Refer to `Regex::replace_all`.
-	pub fn replace_all<'t, R: Replacer>(&self, text: &'t str, rep: R) -> Cow<'t, str> {
-		regex!(self).replace_all(text, rep)
-	}
+		entries.sort_by(|(_, a), (_, b)| b.hits.cmp(&a.hits).then(b.accessed.cmp(&a.accessed)));
 
-	/// Refer to `Regex::shortest_match`.
-	pub fn shortest_match(&self, text: &str) -> Option<usize> {
-		regex!(self).shortest_match(text)
+		entries.into_iter().take(n)
+			.map(|(key, meta)| (key.source.as_str(), meta.hits))
+			.collect()
 	}
 
-	pub fn captures_len(&self) -> usize {
-		regex!(self).captures_len()
+	/// Get the number of entries in the cache.
+	pub fn len(&self) -> usize {
+		self.cache.len()
 	}
 
-	pub fn as_str(&self) -> &str {
-		&self.builder.source
+	/// Check if the cache is empty.
+	pub fn is_empty(&self) -> bool {
+		self.cache.is_empty()
 	}
-}
 
-impl fmt::Debug for CachedRegex {
-	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		fmt::Debug::fmt(regex!(self), f)
+	/// Get the maximum number of entries in the cache.
+	pub fn capacity(&self) -> usize {
+		self.cache.capacity()
 	}
-}
 
-impl fmt::Display for CachedRegex {
-	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		fmt::Display::fmt(regex!(self), f)
+	/// Check if a regular expression for the given source is in the cache.
+	///
+	/// This doesn't count as an access, it neither promotes the entry nor
+	/// changes the statistics, and expired entries are treated as missing.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	/// cache.compile(r"^\d+$").unwrap();
+	///
+	/// assert!(cache.contains(r"^\d+$"));
+	/// assert!(!cache.contains(r"^[a-z]+$"));
+	/// ```
+	pub fn contains(&self, source: &str) -> bool {
+		self.contains_key(&RegexCacheKey::new(source, self.defaults))
 	}
-}
 
-/// A configurable builder for a cached `Regex`.
-#[derive(Clone, Debug)]
-pub struct CachedRegexBuilder {
-	cache:   Arc<Mutex<RegexCache>>,
-	source:  String,
-	options: Options,
-}
+	/// Check if the given key is in the cache and not expired, without
+	/// counting as an access.
+	pub(crate) fn contains_key(&self, key: &RegexCacheKey) -> bool {
+		self.cache.peek(key).is_some() && !self.is_expired(key)
+	}
 
-impl CachedRegexBuilder {
-	/// Create a new regular expression builder with the given pattern.
+	/// Get the regular expression for the given source if it's in the cache,
+	/// without compiling it.
 	///
-	/// If the pattern is invalid, then an error will be returned when
-	/// `compile` is called.
-	pub fn new(cache: Arc<Mutex<RegexCache>>, source: &str) -> CachedRegexBuilder {
-		CachedRegexBuilder {
-			cache:   cache,
-			source:  source.to_owned(),
-			options: Default::default(),
+	/// Like `contains` this doesn't count as an access and only needs a shared
+	/// reference, so it can be used for read-only inspection.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	/// cache.compile(r"^\d+$").unwrap();
+	///
+	/// assert!(cache.peek(r"^\d+$").unwrap().is_match("1234"));
+	/// assert!(cache.peek(r"^[a-z]+$").is_none());
+	/// ```
+	pub fn peek(&self, source: &str) -> Option<&Regex> {
+		let key = RegexCacheKey::new(source, self.defaults);
+
+		if self.is_expired(&key) {
+			return None;
 		}
+
+		self.cache.peek(&key)
 	}
 
-	/// Consume the builder and compile the regular expression.
+	/// Iterate over the entries in the cache in eviction order, which is from
+	/// the least to the most recently used for `RegexCache`.
 	///
-	/// Note that calling `as_str` on the resulting `Regex` will produce the
-	/// pattern given to `new` verbatim. Notably, it will not incorporate any
-	/// of the flags set on this builder.
-	pub fn build(&self) -> Result<CachedRegex, Error> {
-		if let Err(err) = syntax::Parser::new().parse(&self.source) {
-			return Err(Error::Syntax(err.to_string()));
-		}
-
-		Ok(CachedRegex::from(self.clone()))
+	/// Each entry comes with the source and options it was compiled with.
+	/// This doesn't count as an access.
+	pub fn iter(&self) -> impl Iterator<Item = (&RegexCacheKey, &Regex)> {
+		self.cache.entries()
 	}
 
-	/// Consume the builder and compile the regular expression without checking 
-	/// if the syntax is valid.
-	/// 
-	/// Only use this if you know that the syntax is valid or you are ready to 
-	/// handle potential syntax errors later on.
+	/// Iterate over the sources and regular expressions from the next to be
+	/// evicted to the last, which is from the least to the most recently used
+	/// for `RegexCache`.
 	///
-	/// Note that calling `as_str` on the resulting `Regex` will produce the
-	/// pattern given to `new` verbatim. Notably, it will not incorporate any
-	/// of the flags set on this builder.
-	pub fn build_unchecked(&self) -> CachedRegex {
-		CachedRegex::from(self.clone())
+	/// This doesn't count as an access.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	/// cache.warm(["a", "b", "c"]);
+	/// cache.compile("a").unwrap();
+	///
+	/// assert_eq!(cache.iter_lru_order().map(|(source, _)| source).collect::<Vec<_>>(), ["b", "c", "a"]);
+	/// assert_eq!(cache.iter_mru_order().map(|(source, _)| source).collect::<Vec<_>>(), ["a", "c", "b"]);
+	/// ```
+	pub fn iter_lru_order(&self) -> impl Iterator<Item = (&str, &Regex)> {
+		self.cache.entries().map(|(key, re)| (key.source.as_str(), re))
 	}
 
-	/// Set the value for the case insensitive (`i`) flag.
-	pub fn case_insensitive(&mut self, yes: bool) -> &mut CachedRegexBuilder {
-		self.options.case_insensitive = yes;
-		self
+	/// Iterate over the sources and regular expressions from the last to be
+	/// evicted to the next, which is from the most to the least recently used
+	/// for `RegexCache`.
+	///
+	/// The order is the reverse of `iter_lru_order`, and this doesn't count as
+	/// an access either.
+	pub fn iter_mru_order(&self) -> impl Iterator<Item = (&str, &Regex)> {
+		self.iter_lru_order().collect::<Vec<_>>().into_iter().rev()
 	}
 
-	/// Set the value for the multi-line matching (`m`) flag.
-	pub fn multi_line(&mut self, yes: bool) -> &mut CachedRegexBuilder {
-		self.options.multi_line = yes;
-		self
+	/// Remove all the entries from the cache.
+	pub fn clear(&mut self) {
+		self.cache.clear();
+		self.meta.clear();
+		self.data.clear();
+		self.groups.clear();
+		self.used     = 0;
+		self.weighted = 0;
+
+		if let Some(ref mut errors) = self.errors {
+			errors.clear();
+		}
 	}
 
-	/// Set the value for the any character (`s`) flag, where in `.` matches
-	/// anything when `s` is set and matches anything except for new line when
-	/// it is not set (the default).
+	/// Change the number of entries the cache can hold, evicting entries if
+	/// it's shrinking.
 	///
-	/// N.B. "matches anything" means "any byte" for `regex::bytes::Regex`
+	/// A capacity of `0` means the cache is unbounded, see `new`.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(3);
+	/// cache.warm(["a", "b", "c"]);
+	///
+	/// cache.resize(1);
+	/// assert_eq!(cache.export_patterns(), ["c"]);
+	/// ```
+	pub fn resize(&mut self, capacity: usize) {
+		let capacity = bounded(capacity);
+
+		while self.cache.len() > capacity && self.evict() { }
+		self.cache.set_capacity(capacity);
+	}
+
+	/// Reduce the capacity to the number of entries, freeing the memory held
+	/// for entries that were removed.
+	///
+	/// An empty cache ends up with a capacity of `1`, since `0` means the cache
+	/// is unbounded.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	/// cache.warm(["a", "b", "c"]);
+	///
+	/// cache.shrink_to_fit();
+	/// assert_eq!(cache.capacity(), 3);
+	/// ```
+	pub fn shrink_to_fit(&mut self) {
+		self.shrink_to(self.len());
+	}
+
+	/// Reduce the capacity to at most the given one, evicting entries if
+	/// needed, and free the memory held for entries that were removed.
+	///
+	/// Unlike `resize` this never grows the cache, and a capacity of `0` is
+	/// treated as `1` instead of unbounded.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	/// cache.warm(["a", "b", "c"]);
+	///
+	/// cache.shrink_to(2);
+	/// assert_eq!(cache.export_patterns(), ["b", "c"]);
+	///
+	/// cache.shrink_to(10);
+	/// assert_eq!(cache.capacity(), 2);
+	/// ```
+	pub fn shrink_to(&mut self, capacity: usize) {
+		self.resize(capacity.clamp(1, self.capacity()));
+
+		self.cache.shrink_to_fit();
+		self.meta.shrink_to_fit();
+		self.data.shrink_to_fit();
+		self.groups.shrink_to_fit();
+	}
+
+	/// Create a new cache bounded by the approximate memory used by the
+	/// compiled regular expressions instead of their number.
+	///
+	/// The size of each entry is estimated from the structure of its pattern,
+	/// and as many entries as needed are evicted to make
+	/// room for a new one. An entry bigger than the whole budget is still
+	/// cached, evicting everything else.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::with_memory_budget(64 * 1024);
+	///
+	/// assert!(cache.compile(r"^\d+$").unwrap().is_match("1234"));
+	/// ```
+	pub fn with_memory_budget(bytes: usize) -> Self {
+		let mut cache = Self::new(usize::MAX);
+		cache.budget = Some(bytes);
+		cache
+	}
+
+	/// Get the memory budget of the cache, if any.
+	pub fn memory_budget(&self) -> Option<usize> {
+		self.budget
+	}
+
+	/// Get the approximate memory used by the cached regular expressions, in
+	/// bytes.
+	///
+	/// This is the estimate the memory budget is checked against, see
+	/// `with_memory_budget`. The `regex` crate doesn't expose the size of a
+	/// compiled program, so the number is derived from the structure of each
+	/// pattern, it's only good for comparisons and rough capacity planning.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	/// cache.compile(r"^\d+$").unwrap();
+	/// cache.compile(r"^\w{100}$").unwrap();
+	///
+	/// let (worst, _) = cache.entry_sizes().max_by_key(|&(_, size)| size).unwrap();
+	/// assert_eq!(worst.source, r"^\w{100}$");
+	/// assert!(cache.memory_usage() > 0);
+	/// ```
+	pub fn memory_usage(&self) -> usize {
+		self.used
+	}
+
+	/// Iterate over the approximate memory used by each cached regular
+	/// expression, in eviction order.
+	///
+	/// See `memory_usage`.
+	pub fn entry_sizes(&self) -> impl Iterator<Item = (&RegexCacheKey, usize)> {
+		self.cache.entries().map(move |(key, _)|
+			(key, self.meta.get(key).map_or(0, |meta| meta.size)))
+	}
+
+	/// Set the time-to-live for the entries in the cache.
+	///
+	/// Expiration is checked lazily, an expired entry is treated as a miss and
+	/// compiled again when it's next accessed, use `purge_expired` to sweep
+	/// them explicitly.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use std::time::Duration;
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	/// cache.set_ttl(Duration::from_secs(24 * 60 * 60));
+	///
+	/// assert!(cache.compile(r"^\d+$").unwrap().is_match("1234"));
+	/// ```
+	pub fn set_ttl(&mut self, ttl: Duration) {
+		self.ttl = Some(ttl);
+	}
+
+	/// Get the time-to-live for the entries in the cache, if any.
+	pub fn ttl(&self) -> Option<Duration> {
+		self.ttl
+	}
+
+	/// Set the clock used to timestamp and expire entries.
+	pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+		self.clock = Arc::new(clock);
+	}
+
+	/// Remove all the expired entries from the cache.
+	pub fn purge_expired(&mut self) {
+		let ttl = if let Some(ttl) = self.ttl { ttl } else { return };
+		let now = self.clock.now();
+
+		let expired = self.meta.iter()
+			.filter(|&(_, meta)| now.saturating_duration_since(meta.inserted) >= ttl)
+			.map(|(key, _)| key.clone())
+			.collect::<Vec<_>>();
+
+		for key in expired {
+			self.drop_entry(&key, EvictionReason::Expired);
+		}
+	}
+
+	/// Get the sources of the entries that weren't looked up for at least
+	/// `min_idle`, in eviction order.
+	///
+	/// Entries that were never looked up are idle since their insertion. The
+	/// time is read from the clock given to `set_clock`.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use std::time::Duration;
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	/// cache.compile(r"^\d+$").unwrap();
+	///
+	/// assert_eq!(cache.idle_entries(Duration::from_secs(0)), [r"^\d+$"]);
+	/// assert!(cache.idle_entries(Duration::from_secs(600)).is_empty());
+	/// ```
+	pub fn idle_entries(&self, min_idle: Duration) -> Vec<&str> {
+		let now = self.clock.now();
+
+		self.cache.entries()
+			.filter(|&(key, _)| self.meta.get(key).is_some_and(|meta|
+				now.saturating_duration_since(meta.accessed) >= min_idle))
+			.map(|(key, _)| key.source.as_str())
+			.collect()
+	}
+
+	/// Remove the entries that weren't looked up for at least `min_idle`,
+	/// returning how many were removed.
+	///
+	/// This is meant to be called periodically to release memory during quiet
+	/// periods, the removed entries don't count as evictions in the
+	/// statistics, and are handed to the eviction callback with
+	/// `EvictionReason::Idle`.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use std::time::Duration;
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	/// cache.compile(r"^\d+$").unwrap();
+	///
+	/// assert_eq!(cache.evict_idle(Duration::from_secs(600)), 0);
+	/// assert_eq!(cache.evict_idle(Duration::from_secs(0)), 1);
+	/// assert!(cache.is_empty());
+	/// ```
+	pub fn evict_idle(&mut self, min_idle: Duration) -> usize {
+		let now  = self.clock.now();
+		let idle = self.meta.iter()
+			.filter(|&(_, meta)| now.saturating_duration_since(meta.accessed) >= min_idle)
+			.map(|(key, _)| key.clone())
+			.collect::<Vec<_>>();
+
+		for key in &idle {
+			self.drop_entry(key, EvictionReason::Idle);
+		}
+
+		idle.len()
+	}
+
+	/// Create a new regular expression in the cache, attaching the given
+	/// metadata to its entry and replacing any previous one.
+	///
+	/// The metadata stays with the entry as long as it's in the cache, and is
+	/// handed to the eviction callback when the cache drops the entry, see
+	/// `set_on_evict`. Nothing is attached if the compilation fails.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::MetaRegexCache;
+	/// let mut cache = MetaRegexCache::new(100);
+	/// cache.compile_with_meta(r"password=\w+", ("R42", "high")).unwrap();
+	///
+	/// assert_eq!(cache.get_meta(r"password=\w+"), Some(&("R42", "high")));
+	/// assert_eq!(cache.get_meta(r"token=\w+"), None);
+	/// ```
+	pub fn compile_with_meta(&mut self, source: &str, meta: M) -> Result<&Regex, Error> {
+		let options = self.defaults;
+		self.compile_with(source, &options)?;
+
+		self.data.insert(RegexCacheKey::new(source, options), meta);
+		Ok(self.most_recent())
+	}
+
+	/// Get the metadata attached to the entry for the given source, without
+	/// counting as an access.
+	pub fn get_meta(&self, source: &str) -> Option<&M> {
+		let key = RegexCacheKey::new(source, self.defaults);
+
+		if self.is_expired(&key) {
+			return None;
+		}
+
+		self.data.get(&key)
+	}
+
+	/// Get a mutable reference to the metadata attached to the entry for the
+	/// given source, without counting as an access.
+	pub fn meta_mut(&mut self, source: &str) -> Option<&mut M> {
+		let key = RegexCacheKey::new(source, self.defaults);
+
+		if self.is_expired(&key) {
+			return None;
+		}
+
+		self.data.get_mut(&key)
+	}
+
+	/// Set the function called for every entry the cache drops on its own,
+	/// with its metadata, if any, and the reason it was dropped.
+	///
+	/// Entries removed explicitly, like with `clear` or `pop_lru`, don't call
+	/// it.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use std::sync::{Arc, Mutex};
+	/// # use regex_cache::{MetaRegexCache, EvictionReason};
+	/// let evicted = Arc::new(Mutex::new(Vec::new()));
+	/// let mut cache = MetaRegexCache::new(1);
+	///
+	/// let sink = evicted.clone();
+	/// cache.set_on_evict(move |key, rule, reason| sink.lock().unwrap().push((key.source.clone(), rule, reason)));
+	///
+	/// cache.compile_with_meta("a", 1).unwrap();
+	/// cache.compile_with_meta("b", 2).unwrap();
+	///
+	/// assert_eq!(*evicted.lock().unwrap(), [("a".to_owned(), Some(1), EvictionReason::Capacity)]);
+	/// ```
+	pub fn set_on_evict<F>(&mut self, f: F)
+		where F: Fn(&RegexCacheKey, Option<M>, EvictionReason) + Send + Sync + 'static
+	{
+		self.on_evict = Some(Arc::new(f));
+	}
+
+	/// Save the given regular expression in the cache.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::{Regex, RegexCache};
+	/// let mut cache = RegexCache::new(100);
+	/// let     re    = Regex::new(r"^\d+$").unwrap();
+	///
+	/// // By saving the previously created regular expression further calls to
+	/// // `compile` won't actually compile the regular expression.
+	/// cache.save(re);
+	///
+	/// assert!(cache.compile(r"^\d+$").unwrap().is_match("1234"));
+	/// assert!(!cache.compile(r"^\d+$").unwrap().is_match("abcd"));
+	/// ```
+	pub fn save(&mut self, re: Regex) -> &Regex {
+		let options = self.defaults;
+		let key     = self.key(re.as_str(), &options);
+
+		match self.fetch(key, None, Miss::Insert, || Ok(re)) {
+			Ok(re) =>
+				re,
+
+			Err(_) =>
+				unreachable!(),
+		}
+	}
+
+	/// Create a new regular expression in the cache.
+	///
+	/// The regular expression is only compiled if it's not in the cache
+	/// already, otherwise the cached one is returned as is, see `recompile` to
+	/// always compile it. This is the same as `compile_with` with the default
+	/// options, apart from the size limit given to `with_size_limit`.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	///
+	/// assert!(cache.compile(r"^\d+$").unwrap().is_match("1234"));
+	/// assert!(!cache.compile(r"^\d+$").unwrap().is_match("abcd"));
+	/// ```
+	pub fn compile(&mut self, source: &str) -> Result<&Regex, Error> {
+		let options = self.defaults;
+		self.compile_with(source, &options)
+	}
+
+	/// Create a new regular expression in the cache with the given options.
+	///
+	/// Entries are keyed by both the source and the options, so the same
+	/// source can be cached with different options at the same time.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::{RegexCache, Options};
+	/// let mut cache = RegexCache::new(100);
+	/// let     ci    = Options { case_insensitive: true, .. Default::default() };
+	///
+	/// assert!(cache.compile_with("abc", &ci).unwrap().is_match("ABC"));
+	/// assert!(!cache.compile("abc").unwrap().is_match("ABC"));
+	/// assert_eq!(cache.len(), 2);
+	/// ```
+	pub fn compile_with(&mut self, source: &str, options: &Options) -> Result<&Regex, Error> {
+		let key    = self.key(source, options);
+		let limits = self.limits.clone();
+
+		self.fetch(key, None, Miss::Compile, || {
+			guard(source, limits.as_deref())?;
+			options.define(&mut RegexBuilder::new(source)).build()
+		})
+	}
+
+	/// Create a new case insensitive regular expression in the cache.
+	///
+	/// This is the same as `compile_flags(source, "i")`.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	///
+	/// assert!(cache.compile_ci("abc").unwrap().is_match("ABC"));
+	/// assert!(!cache.compile("abc").unwrap().is_match("ABC"));
+	/// ```
+	pub fn compile_ci(&mut self, source: &str) -> Result<&Regex, Error> {
+		let options = self.defaults.with_flags(RegexFlags::default() | RegexFlags::CASE_INSENSITIVE);
+		self.compile_with(source, &options)
+	}
+
+	/// Create a new regular expression in the cache with the flags written
+	/// like inline flags, as in `"imsxU"`.
+	///
+	/// The flags replace the default ones, apart from Unicode which stays
+	/// enabled unless the flags contain `-u`. Unknown flags are reported as
+	/// `Error::Syntax`.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	///
+	/// assert!(cache.compile_flags("abc", "i").unwrap().is_match("ABC"));
+	/// assert!(cache.compile_flags("abc", "q").is_err());
+	/// ```
+	pub fn compile_flags(&mut self, source: &str, flags: &str) -> Result<&Regex, Error> {
+		let options = self.defaults.with_flags(flags.parse()?);
+		self.compile_with(source, &options)
+	}
+
+	/// Create a new regular expression in the cache, caching the given
+	/// fallback under the source instead if it fails to compile.
+	///
+	/// Later lookups of the invalid source return the fallback without trying
+	/// to compile it again.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::{Regex, RegexCache};
+	/// let mut cache = RegexCache::new(100);
+	/// let     never = Regex::new(r"[^\s\S]").unwrap();
+	///
+	/// assert!(cache.compile_or_default(r"^\d+$", never.clone()).is_match("1234"));
+	/// assert!(!cache.compile_or_default(r"^\d+(", never.clone()).is_match("1234"));
+	/// assert_eq!(cache.compile(r"^\d+(").unwrap().as_str(), never.as_str());
+	/// ```
+	pub fn compile_or_default(&mut self, source: &str, default: Regex) -> &Regex {
+		let options = self.defaults;
+		let key     = self.key(source, &options);
+		let limits  = self.limits.clone();
+
+		// The fallback takes the place of the error.
+		if let Some(ref mut errors) = self.errors {
+			errors.remove(&key);
+		}
+
+		let compiled = self.fetch(key, None, Miss::Compile, || {
+			let compiled = guard(source, limits.as_deref()).and_then(|_|
+				options.define(&mut RegexBuilder::new(source)).build());
+
+			Ok(compiled.unwrap_or(default))
+		});
+
+		match compiled {
+			Ok(re) =>
+				re,
+
+			Err(_) =>
+				unreachable!(),
+		}
+	}
+
+	/// Create a new regular expression in the cache, for patterns known to be
+	/// valid like string literals.
+	///
+	/// # Panics
+	///
+	/// If the pattern fails to compile, use `compile` for patterns that may
+	/// be invalid.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	///
+	/// assert!(cache.get_or_insert_default(r"^\d+$").is_match("1234"));
+	/// ```
+	pub fn get_or_insert_default(&mut self, source: &str) -> &Regex {
+		match self.compile(source) {
+			Ok(re) =>
+				re,
+
+			Err(err) =>
+				panic!("invalid pattern {:?}: {}", source, err),
+		}
+	}
+
+	/// Create a new regular expression in the cache from a borrowed or owned
+	/// source.
+	///
+	/// This is the same as `compile`, but an owned source is moved into the
+	/// cache on a miss instead of being copied.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache  = RegexCache::new(100);
+	/// let     tenant = format!("^{}-\\d+$", "acme");
+	///
+	/// assert!(cache.compile_cow(tenant).unwrap().is_match("acme-42"));
+	/// assert!(cache.compile_cow(r"^acme-\d+$").unwrap().is_match("acme-7"));
+	/// assert_eq!(cache.stats().hits, 1);
+	/// ```
+	pub fn compile_cow<'a, T: Into<Cow<'a, str>>>(&mut self, source: T) -> Result<&Regex, Error> {
+		let source = match source.into() {
+			Cow::Borrowed(source) =>
+				return self.compile(source),
+
+			Cow::Owned(source) =>
+				source,
+		};
+
+		let options = self.defaults;
+		let key     = RegexCacheKey { source: source, options: options };
+		let limits  = self.limits.clone();
+
+		let miss = self.lookup(&key, None, Miss::Compile, || {
+			guard(&key.source, limits.as_deref())?;
+			options.define(&mut RegexBuilder::new(&key.source)).build()
+		})?;
+
+		if let Some((re, elapsed)) = miss {
+			self.put(key, re, 0, elapsed);
+		}
+
+		Ok(self.most_recent())
+	}
+
+	/// Create a new regular expression in the cache, returning an owned clone
+	/// of it.
+	///
+	/// Cloning a `Regex` is cheap, and the result doesn't keep the cache
+	/// borrowed.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache  = RegexCache::new(100);
+	/// let     digits = cache.compile_owned(r"^\d+$").unwrap();
+	/// let     alpha  = cache.compile_owned(r"^[a-z]+$").unwrap();
+	///
+	/// assert!(digits.is_match("1234"));
+	/// assert!(alpha.is_match("abcd"));
+	/// ```
+	pub fn compile_owned(&mut self, source: &str) -> Result<Regex, Error> {
+		self.compile(source).cloned()
+	}
+
+	/// Compile (or fetch) a regular expression and hand it to the given
+	/// function, returning its result.
+	///
+	/// The borrow of the cache ends with the call, so the result can be kept
+	/// while using the cache for other patterns.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	/// let     year  = cache.with(r"\d{4}", |re| re.find("in 2017").map(|m| m.start())).unwrap();
+	/// let     word  = cache.with(r"[a-z]+", |re| re.is_match("in 2017")).unwrap();
+	///
+	/// assert_eq!(year, Some(3));
+	/// assert!(word);
+	/// ```
+	pub fn with<R, F: FnOnce(&Regex) -> R>(&mut self, source: &str, f: F) -> Result<R, Error> {
+		self.compile(source).map(f)
+	}
+
+	/// Compile (or fetch) a regular expression and check if it matches `text`
+	/// in one call.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	///
+	/// assert!(cache.is_match(r"^\d+$", "1234").unwrap());
+	/// assert!(!cache.is_match(r"^\d+$", "abcd").unwrap());
+	/// ```
+	pub fn is_match(&mut self, source: &str, text: &str) -> Result<bool, Error> {
+		Ok(self.compile(source)?.is_match(text))
+	}
+
+	/// Compile (or fetch) a regular expression and find the first match in
+	/// `text`, returning its byte offsets.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	///
+	/// assert_eq!(cache.find(r"\d+", "abc 123").unwrap(), Some((4, 7)));
+	/// ```
+	pub fn find(&mut self, source: &str, text: &str) -> Result<Option<(usize, usize)>, Error> {
+		Ok(self.compile(source)?.find(text).map(|m| (m.start(), m.end())))
+	}
+
+	/// Compile (or fetch) a regular expression and find all the
+	/// non-overlapping matches in `text`, returning their byte offsets.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	///
+	/// assert_eq!(cache.find_all(r"\d+", "1 22 333").unwrap(), [(0, 1), (2, 4), (5, 8)]);
+	/// ```
+	pub fn find_all(&mut self, source: &str, text: &str) -> Result<Vec<(usize, usize)>, Error> {
+		Ok(self.compile(source)?.find_iter(text).map(|m| (m.start(), m.end())).collect())
+	}
+
+	/// Compile (or fetch) a regular expression and get the capture groups of
+	/// the first match in `text`.
+	///
+	/// The result doesn't borrow from the cache nor from `text`.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	/// let     caps  = cache.captures(r"(?P<key>\w+)=(\w+)", "a=b").unwrap().unwrap();
+	///
+	/// assert_eq!(caps.name("key").unwrap().as_str(), "a");
+	/// assert_eq!(caps.get(2).unwrap().as_str(), "b");
+	/// ```
+	pub fn captures(&mut self, source: &str, text: &str) -> Result<Option<OwnedCaptures>, Error> {
+		let re = self.compile(source)?;
+		Ok(re.captures(text).map(|caps| OwnedCaptures::new(re, &caps)))
+	}
+
+	/// Compile (or fetch) a regular expression and replace the first match in
+	/// `text`.
+	///
+	/// Refer to `Regex::replace` for the supported replacements.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	///
+	/// assert_eq!(cache.replace(r"(?P<n>\d+)", "a1 b2", "<$n>").unwrap(), "a<1> b2");
+	/// ```
+	pub fn replace<R: Replacer>(&mut self, source: &str, text: &str, rep: R) -> Result<String, Error> {
+		Ok(self.compile(source)?.replace(text, rep).into_owned())
+	}
+
+	/// Compile (or fetch) a regular expression and replace all the matches in
+	/// `text`.
+	///
+	/// Refer to `Regex::replace_all` for the supported replacements.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	///
+	/// assert_eq!(cache.replace_all(r"\d", "a1 b2", "#").unwrap(), "a# b#");
+	/// ```
+	pub fn replace_all<R: Replacer>(&mut self, source: &str, text: &str, rep: R) -> Result<String, Error> {
+		Ok(self.compile(source)?.replace_all(text, rep).into_owned())
+	}
+
+	/// Compile (or fetch) a regular expression and replace at most `limit`
+	/// matches in `text`, all of them if `limit` is `0`.
+	///
+	/// Refer to `Regex::replacen` for the supported replacements.
+	pub fn replacen<R: Replacer>(&mut self, source: &str, text: &str, limit: usize, rep: R) -> Result<String, Error> {
+		Ok(self.compile(source)?.replacen(text, limit, rep).into_owned())
+	}
+
+	/// Compile (or fetch) a regular expression and split `text` by its
+	/// matches, like `Regex::split`.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	///
+	/// assert_eq!(cache.split(r"\s*,\s*", "a, b ,c").unwrap(), ["a", "b", "c"]);
+	/// ```
+	pub fn split(&mut self, source: &str, text: &str) -> Result<Vec<String>, Error> {
+		Ok(self.compile(source)?.split(text).map(String::from).collect())
+	}
+
+	/// Compile (or fetch) a regular expression and split `text` by its
+	/// matches into at most `limit` pieces, like `Regex::splitn`.
+	pub fn splitn(&mut self, source: &str, text: &str, limit: usize) -> Result<Vec<String>, Error> {
+		Ok(self.compile(source)?.splitn(text, limit).map(String::from).collect())
+	}
+
+	/// Compile a regular expression, replacing any cached one for the same
+	/// source.
+	///
+	/// The existing entry is left untouched if the compilation fails.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	/// cache.configure(r"abc", |b| b.case_insensitive(true)).unwrap();
+	///
+	/// assert!(cache.compile(r"abc").unwrap().is_match("ABC"));
+	/// assert!(!cache.recompile(r"abc").unwrap().is_match("ABC"));
+	/// ```
+	pub fn recompile(&mut self, source: &str) -> Result<&Regex, Error> {
+		guard(source, self.limits.as_deref())?;
+
+		let options = self.defaults;
+		let start   = Instant::now();
+		let re      = options.define(&mut RegexBuilder::new(source)).build()?;
+		let elapsed = start.elapsed();
+		let key     = RegexCacheKey::new(source, options);
+		let weight  = self.meta.get(&key).map_or(0, |m| m.weight);
+
+		let data = self.discard(&key);
+		self.stats.misses += 1;
+		self.stats.record_compile(elapsed);
+		self.put(key.clone(), re, weight, Some(elapsed));
+
+		if let Some(data) = data {
+			self.data.insert(key, data);
+		}
+
+		Ok(self.most_recent())
+	}
+
+	/// Create a new regular expression in the cache with the given access
+	/// weight.
+	///
+	/// When the cache is full the entry with the lowest weight is evicted,
+	/// and the least recently used among those with the same weight, entries
+	/// created without a hint have a weight of `0`. Giving an entry a high
+	/// weight effectively pins it against eviction by lighter entries.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(2);
+	///
+	/// cache.compile_with_hint(r"^\d+$", 10).unwrap();
+	/// cache.compile(r"^[a-z]+$").unwrap();
+	/// cache.compile(r"^[A-Z]+$").unwrap();
+	///
+	/// assert!(cache.contains(r"^\d+$"));
+	/// ```
+	pub fn compile_with_hint(&mut self, source: &str, access_weight: u32) -> Result<&Regex, Error> {
+		let options = self.defaults;
+		let key     = self.key(source, &options);
+		let limits  = self.limits.clone();
+
+		self.fetch(key, Some(access_weight), Miss::Compile, || {
+			guard(source, limits.as_deref())?;
+			options.define(&mut RegexBuilder::new(source)).build()
+		})
+	}
+
+	/// Configure a new regular expression.
+	///
+	/// The builder can't be part of the key, so the entry is cached under the
+	/// source with the default options, the same as `compile`. Use
+	/// `compile_with` to cache the same source with different options.
+	///
+	/// The builder starts with the default options of the cache, including
+	/// the size limit given to `with_size_limit`.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	///
+	/// assert!(cache.configure(r"abc", |b| b.case_insensitive(true)).unwrap()
+	/// 	.is_match("ABC"));
+	///
+	/// assert!(!cache.configure(r"abc", |b| b.case_insensitive(true)).unwrap()
+	/// 	.is_match("123"));
+	/// ```
+	pub fn configure<F>(&mut self, source: &str, f: F) -> Result<&Regex, Error>
+		where F: FnOnce(&mut RegexBuilder) -> &mut RegexBuilder
+	{
+		let options = self.defaults;
+		let key     = self.key(source, &options);
+		let limits  = self.limits.clone();
+
+		self.fetch(key, None, Miss::Compile, || {
+			guard(source, limits.as_deref())?;
+			f(options.define(&mut RegexBuilder::new(source))).build()
+		})
+	}
+
+	/// Configure a new regular expression, returning an owned clone of it.
+	///
+	/// See `compile_owned`.
+	pub fn configure_owned<F>(&mut self, source: &str, f: F) -> Result<Regex, Error>
+		where F: FnOnce(&mut RegexBuilder) -> &mut RegexBuilder
+	{
+		self.configure(source, f).cloned()
+	}
+
+	/// Get the regular expression cached under the given key, creating it with
+	/// the given function if it's missing.
+	///
+	/// The key is chosen by the caller and doesn't have to be the source of
+	/// the regular expression. The function is only called on a miss, and
+	/// nothing is inserted if it fails.
+	///
+	/// The function isn't necessarily compiling anything, so calling it
+	/// doesn't count as a miss or in the compile times of the statistics.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::{Regex, RegexCache};
+	/// let mut cache = RegexCache::new(100);
+	/// let     re    = cache.get_or_insert_with("digits", || Regex::new(r"(?x) ^ \d+ $")).unwrap();
+	///
+	/// assert!(re.is_match("1234"));
+	/// ```
+	pub fn get_or_insert_with<F>(&mut self, key: &str, f: F) -> Result<&Regex, Error>
+		where F: FnOnce() -> Result<Regex, Error>
+	{
+		let options = self.defaults;
+		let key     = self.key(key, &options);
+
+		self.fetch(key, None, Miss::Insert, f)
+	}
+
+	/// Get the regular expression for the given source and options, compiling
+	/// it if it's missing.
+	///
+	/// This is the same as `compile_with`.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::{RegexCache, RegexCacheKey, RegexFlags};
+	/// let mut cache = RegexCache::new(100);
+	/// let     plain = RegexCacheKey::new("abc", RegexFlags::default());
+	/// let     ci    = RegexCacheKey::new("abc", RegexFlags::default() | RegexFlags::CASE_INSENSITIVE);
+	///
+	/// assert!(!cache.get_or_compile_with_key(&plain).unwrap().is_match("ABC"));
+	/// assert!(cache.get_or_compile_with_key(&ci).unwrap().is_match("ABC"));
+	/// assert_eq!(cache.len(), 2);
+	/// ```
+	pub fn get_or_compile_with_key(&mut self, key: &RegexCacheKey) -> Result<&Regex, Error> {
+		self.compile_with(&key.source, &key.options)
+	}
+
+	/// Compile the given patterns, returning the outcome for each of them in
+	/// the same order.
+	///
+	/// Each distinct pattern is compiled at most once, even if it appears
+	/// several times and gets evicted in between because the batch doesn't fit
+	/// in the cache.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache   = RegexCache::new(100);
+	/// let     results = cache.compile_many(vec![r"^\d+$", r"^[a-z+$", r"^\d+$"]);
+	///
+	/// assert!(results[0].is_ok() && results[2].is_ok());
+	/// assert!(results[1].is_err());
+	/// assert_eq!(cache.stats().misses, 2);
+	/// ```
+	pub fn compile_many<'a, I>(&mut self, sources: I) -> Vec<Result<(), Error>>
+		where I: IntoIterator<Item = &'a str>
+	{
+		self.compile_many_owned(sources).into_iter()
+			.map(|result| result.map(|_| ()))
+			.collect()
+	}
+
+	/// Compile the given patterns, returning an owned clone of each of them,
+	/// see `compile_many`.
+	pub fn compile_many_owned<'a, I>(&mut self, sources: I) -> Vec<Result<Regex, Error>>
+		where I: IntoIterator<Item = &'a str>
+	{
+		let mut seen = HashMap::<&str, Result<Regex, Error>>::new();
+
+		sources.into_iter().map(|source| {
+			let result = match seen.get(source) {
+				Some(Ok(re)) =>
+					self.get_or_insert_with(source, || Ok(re.clone())).cloned(),
+
+				Some(Err(err)) =>
+					Err(err.clone()),
+
+				None =>
+					self.compile(source).cloned(),
+			};
+
+			seen.insert(source, result.clone());
+			result
+		}).collect()
+	}
+
+	/// Compile the given patterns ahead of time, reporting the outcome for each
+	/// of them.
+	///
+	/// Invalid patterns don't stop the rest of the batch. Every valid pattern
+	/// is compiled and inserted like `compile` would, so if there are more
+	/// patterns than fit in the cache the earlier ones get evicted by the later
+	/// ones; put the most important patterns last.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache  = RegexCache::new(100);
+	/// let     report = cache.warm(&[r"^\d+$", r"^[a-z+$"]);
+	///
+	/// assert!(report[0].1.is_ok());
+	/// assert!(report[1].1.is_err());
+	/// assert_eq!(cache.len(), 1);
+	/// ```
+	pub fn warm<I, T>(&mut self, patterns: I) -> Vec<(String, Result<(), Error>)>
+		where I: IntoIterator<Item = T>,
+		      T: AsRef<str>
+	{
+		patterns.into_iter().map(|source| {
+			let source = source.as_ref();
+			let result = self.compile(source).map(|_| ());
+
+			(source.to_owned(), result)
+		}).collect()
+	}
+
+	/// Compile the given patterns with their options ahead of time, reporting
+	/// the outcome for each of them.
+	///
+	/// Behaves like `warm`, each pattern is compiled like `compile_with` would.
+	pub fn warm_with<I, T>(&mut self, patterns: I) -> Vec<(String, Result<(), Error>)>
+		where I: IntoIterator<Item = (T, Options)>,
+		      T: AsRef<str>
+	{
+		patterns.into_iter().map(|(source, options)| {
+			let source = source.as_ref();
+			let result = self.compile_with(source, &options).map(|_| ());
+
+			(source.to_owned(), result)
+		}).collect()
+	}
+
+	/// Compile the given patterns in parallel on as many threads as there are
+	/// CPUs, then insert them in order, reporting the outcome for each of them.
+	///
+	/// The result is the same as `warm`, only the compilation is spread across
+	/// threads, the insertion happens on the calling thread in one pass.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache  = RegexCache::new(100);
+	/// let     report = cache.warm_parallel(vec![r"^\d+$".to_owned(), r"^[a-z+$".to_owned()]);
+	///
+	/// assert!(report[0].1.is_ok());
+	/// assert!(report[1].1.is_err());
+	/// assert_eq!(cache.len(), 1);
+	/// ```
+	pub fn warm_parallel<I: IntoIterator<Item = String>>(&mut self, patterns: I) -> Vec<(String, Result<(), Error>)> {
+		let patterns = patterns.into_iter().collect::<Vec<_>>();
+		let sources  = patterns.iter().map(String::as_str).collect::<Vec<_>>();
+		let compiled = compile_parallel(&sources, &self.defaults, self.limits.as_deref());
+
+		patterns.into_iter().zip(compiled).map(|(source, (result, elapsed))| {
+			let result = self.insert_compiled(&source, result, elapsed).map(|_| ());
+			(source, result)
+		}).collect()
+	}
+
+	/// Insert the result of compiling the given source elsewhere in the given
+	/// time, unless it's already cached, accounting for it like a miss in
+	/// `compile`.
+	pub(crate) fn insert_compiled(&mut self, source: &str, result: Result<Regex, Error>, elapsed: Duration) -> Result<&Regex, Error> {
+		let options = self.defaults;
+		let key     = self.key(source, &options);
+
+		self.fetch(key, None, Miss::Compiled(elapsed), || result)
+	}
+
+	/// Get the options every compilation starts from.
+	pub(crate) fn defaults(&self) -> Options {
+		self.defaults
+	}
+
+	/// Create a cache holding the given patterns, compiling them in parallel
+	/// on as many threads as there are CPUs.
+	///
+	/// The cache is big enough for all the patterns, with room for at least
+	/// one entry, and the ones that fail to compile are returned with their
+	/// error.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let (cache, errors) = RegexCache::par_compile_all(&[r"^\d+$", r"^[a-z+$", r"^\w+$"]);
+	///
+	/// assert_eq!(cache.len(), 2);
+	/// assert_eq!(errors[0].0, r"^[a-z+$");
+	/// ```
+	pub fn par_compile_all<'a>(patterns: &[&'a str]) -> (Self, Vec<(&'a str, Error)>) {
+		let compiled   = compile_parallel(patterns, &Options::default(), None);
+		let mut cache  = Self::new(patterns.len().max(1));
+		let mut errors = Vec::new();
+
+		for (&source, (result, elapsed)) in patterns.iter().zip(compiled) {
+			if let Err(err) = cache.insert_compiled(source, result, elapsed) {
+				errors.push((source, err));
+			}
+		}
+
+		(cache, errors)
+	}
+
+	/// Compile the given patterns and tag them with the given group, so they
+	/// can be removed together with `evict_group`.
+	///
+	/// Entries are still looked up by their source and options alone, and one
+	/// entry can belong to several groups. The patterns that fail to compile are not
+	/// tagged, and their errors are returned.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	/// cache.compile_group("dates", &[r"^\d{4}$", r"^\d{2}-\d{2}$"]).unwrap();
+	/// cache.compile(r"^[a-z]+$").unwrap();
+	///
+	/// cache.evict_group("dates");
+	/// assert_eq!(cache.export_patterns(), [r"^[a-z]+$"]);
+	/// ```
+	pub fn compile_group(&mut self, group: &str, sources: &[&str]) -> Result<(), Vec<Error>> {
+		let mut errors = Vec::new();
+
+		for &source in sources {
+			match self.compile(source) {
+				Ok(_) => {
+					let key = RegexCacheKey::new(source, self.defaults);
+					let hasher = &self.hasher;
+					self.groups.entry(group.to_owned())
+						.or_insert_with(|| HashSet::with_hasher(hasher.clone()))
+						.insert(key);
+				}
+
+				Err(err) =>
+					errors.push(err),
+			}
+		}
+
+		if errors.is_empty() {
+			Ok(())
+		}
+		else {
+			Err(errors)
+		}
+	}
+
+	/// Remove all the entries tagged with the given group.
+	pub fn evict_group(&mut self, group: &str) {
+		if let Some(keys) = self.groups.remove(group) {
+			for key in keys {
+				self.discard(&key);
+			}
+		}
+	}
+
+	/// Get the entry that would be evicted next, without removing it.
+	///
+	/// For `RegexCache` that's the least recently used entry among the ones
+	/// with the lowest access weight, see `compile_with_hint`. This doesn't
+	/// count as an access.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	/// cache.warm(["a", "b", "a"]);
+	///
+	/// assert_eq!(cache.peek_lru().unwrap().0.source, "b");
+	/// ```
+	pub fn peek_lru(&self) -> Option<(&RegexCacheKey, &Regex)> {
+		self.victim()
+	}
+
+	/// Remove the entry that would be evicted next and return it, see
+	/// `peek_lru`.
+	///
+	/// This is meant to free memory under pressure without clearing the
+	/// whole cache, it doesn't count as an eviction in the statistics.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	/// cache.warm(["a", "b", "a"]);
+	///
+	/// let (key, re) = cache.pop_lru().unwrap();
+	/// assert_eq!(key.source, "b");
+	/// assert!(re.is_match("b"));
+	/// assert_eq!(cache.export_patterns(), ["a"]);
+	/// ```
+	pub fn pop_lru(&mut self) -> Option<(RegexCacheKey, Regex)> {
+		let key = self.victim()?.0.clone();
+		let re  = self.cache.peek(&key)?.clone();
+
+		self.discard(&key);
+		Some((key, re))
+	}
+
+	/// Register a name for the given source, returning the source it
+	/// previously referred to.
+	///
+	/// Names are only an indirection, the compiled regular expressions are
+	/// still cached by their source, so several names can share the same
+	/// entry, and pointing a name to another source doesn't affect the
+	/// entry of the old one. The names are kept when the cache is cleared.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(100);
+	/// cache.insert_named("digits", r"^\d+$");
+	///
+	/// assert!(cache.get_named("digits").is_none());
+	/// assert!(cache.compile_named("digits").unwrap().unwrap().is_match("1234"));
+	/// assert!(cache.get_named("digits").unwrap().is_match("1234"));
+	/// assert!(cache.compile_named("letters").is_none());
+	/// ```
+	pub fn insert_named(&mut self, name: &str, source: &str) -> Option<String> {
+		self.names.insert(name.to_owned(), source.to_owned())
+	}
+
+	/// Forget the given name, returning the source it referred to.
+	pub fn remove_named(&mut self, name: &str) -> Option<String> {
+		self.names.remove(name)
+	}
+
+	/// Get the source the given name refers to.
+	pub fn named_source(&self, name: &str) -> Option<&str> {
+		self.names.get(name).map(String::as_str)
+	}
+
+	/// Get the cached regular expression for the given name, without
+	/// compiling it if it's missing.
+	///
+	/// Returns `None` if the name isn't registered or its source isn't in
+	/// the cache. A found entry counts as a hit and is marked as used, a
+	/// missing one doesn't count as a miss.
+	pub fn get_named(&mut self, name: &str) -> Option<&Regex> {
+		let source  = self.names.get(name)?.clone();
+		let options = self.defaults;
+		let key     = RegexCacheKey::new(&source, options);
+
+		if !self.contains(&source) {
+			return None;
+		}
+
+		self.touch(&key);
+		Some(self.most_recent())
+	}
+
+	/// Get the regular expression for the given name, compiling its source if
+	/// it's missing.
+	///
+	/// Returns `None` if the name isn't registered, see `insert_named`.
+	pub fn compile_named(&mut self, name: &str) -> Option<Result<&Regex, Error>> {
+		let source = self.names.get(name)?.clone();
+		Some(self.compile(&source))
+	}
+
+	/// Get the patterns in the cache in eviction order, which is from the
+	/// least to the most recently used for `RegexCache`.
+	///
+	/// Only the sources are exported, any option given to `compile_with` or
+	/// `configure` is lost, see `export_keys`.
+	pub fn export_patterns(&self) -> Vec<String> {
+		self.cache.entries().map(|(key, _)| key.source.clone()).collect()
+	}
+
+	/// Get the keys in the cache in eviction order, which is from the least to
+	/// the most recently used for `RegexCache`.
+	///
+	/// Unlike `export_patterns` the options are kept, so compiling the keys
+	/// with `get_or_compile_with_key` restores the same entries.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::{RegexCache, Options};
+	/// let mut cache = RegexCache::new(100);
+	/// cache.compile_with("abc", &Options { case_insensitive: true, .. Default::default() }).unwrap();
+	///
+	/// let keys = cache.export_keys();
+	/// assert_eq!(keys[0].source, "abc");
+	/// assert!(keys[0].options.case_insensitive);
+	/// ```
+	pub fn export_keys(&self) -> Vec<RegexCacheKey> {
+		self.cache.entries().map(|(key, _)| key.clone()).collect()
+	}
+
+	/// Compile and insert the given patterns in order, skipping the invalid
+	/// ones.
+	///
+	/// Importing the result of `export_patterns` restores the same recency
+	/// order for `RegexCache`.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut old = RegexCache::new(100);
+	/// old.compile(r"^\d+$").unwrap();
+	/// old.compile(r"^[a-z]+$").unwrap();
+	///
+	/// let mut new = RegexCache::new(100);
+	/// new.import_patterns(old.export_patterns());
+	///
+	/// assert_eq!(new.export_patterns(), old.export_patterns());
+	/// ```
+	pub fn import_patterns<I: IntoIterator<Item = String>>(&mut self, patterns: I) {
+		for source in patterns {
+			self.import(source).ok();
+		}
+	}
+
+	/// Take a snapshot of the keys in the cache and their order.
+	pub fn snapshot(&self) -> CacheSnapshot {
+		CacheSnapshot {
+			keys: self.export_keys(),
+		}
+	}
+
+	/// Compile and insert the keys from the given snapshot in order, skipping
+	/// the invalid ones.
+	pub fn import_snapshot(&mut self, snapshot: CacheSnapshot) {
+		for key in snapshot.keys {
+			self.get_or_compile_with_key(&key).ok();
+		}
+	}
+
+	/// Reset the cache to the entries and order of the given snapshot.
+	///
+	/// Entries still in the cache are reused with their statistics, groups
+	/// and timestamps, and only the missing ones are compiled again. Restoring
+	/// doesn't change the statistics of the cache, and the entries compiled
+	/// again count as just inserted for the time to live.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::RegexCache;
+	/// let mut cache = RegexCache::new(3);
+	/// cache.warm(["a", "b"]);
+	///
+	/// let snapshot = cache.snapshot();
+	/// cache.warm(["c", "a", "d"]);
+	///
+	/// cache.restore(snapshot);
+	/// assert_eq!(cache.export_patterns(), ["a", "b"]);
+	/// ```
+	pub fn restore(&mut self, snapshot: CacheSnapshot) {
+		let     stats    = self.stats;
+		let mut data     = mem::take(&mut self.data);
+		let mut groups   = mem::take(&mut self.groups);
+		let mut compiled = self.cache.entries()
+			.map(|(key, re)| (key.clone(), (re.clone(), self.meta.get(key).copied())))
+			.collect::<HashMap<_, _>>();
+
+		self.clear();
+
+		for key in snapshot.keys {
+			if let Some((re, meta)) = compiled.remove(&key) {
+				self.put(key.clone(), re, meta.map_or(0, |m| m.weight), meta.and_then(|m| m.compiled));
+
+				if let Some(meta) = meta {
+					self.meta.insert(key.clone(), meta);
+				}
+
+				if let Some(data) = data.remove(&key) {
+					self.data.insert(key, data);
+				}
+			}
+			else {
+				self.get_or_compile_with_key(&key).ok();
+			}
+		}
+
+		groups.retain(|_, keys| {
+			keys.retain(|key| self.cache.peek(key).is_some());
+			!keys.is_empty()
+		});
+
+		self.groups = groups;
+		self.stats  = stats;
+	}
+
+	/// Insert all the entries of the given cache, keeping the existing entry
+	/// when both caches have the same key.
+	///
+	/// See `merge`.
+	pub fn extend(&mut self, other: Self) {
+		self.merge(&other, Collision::KeepExisting)
+	}
+
+	/// Insert clones of all the entries of the given cache, keeping the
+	/// existing entry when both caches have the same key.
+	///
+	/// See `merge`.
+	pub fn merge_from(&mut self, other: &Self) {
+		self.merge(other, Collision::KeepExisting)
+	}
+
+	/// Insert clones of all the entries of the given cache.
+	///
+	/// The entries are inserted in the eviction order of the other cache, so
+	/// its most recently used entries end up the most recently used here,
+	/// and the capacity and eviction policy of this cache apply as if they
+	/// were compiled here. Expired entries are skipped, and cloning a `Regex`
+	/// is cheap, nothing is compiled again.
+	///
+	/// Metadata isn't merged, and entries replaced because of
+	/// `Collision::PreferOther` lose theirs.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::{RegexCache, Collision};
+	/// let mut global = RegexCache::new(2);
+	/// global.warm(["a", "b"]);
+	///
+	/// let mut tenant = RegexCache::new(10);
+	/// tenant.warm(["c", "a"]);
+	///
+	/// global.merge(&tenant, Collision::KeepExisting);
+	/// assert_eq!(global.export_patterns(), ["c", "a"]);
+	/// ```
+	pub fn merge(&mut self, other: &Self, collision: Collision) {
+		for (key, re) in other.cache.entries() {
+			if other.is_expired(key) {
+				continue;
+			}
+
+			self.expire(key);
+
+			if self.cache.peek(key).is_some() {
+				match collision {
+					Collision::KeepExisting => {
+						self.cache.get(key);
+						continue;
+					}
+
+					Collision::PreferOther => {
+						self.discard(key);
+					}
+				}
+			}
+
+			let meta = other.meta.get(key);
+			self.put(key.clone(), re.clone(), meta.map_or(0, |m| m.weight), meta.and_then(|m| m.compiled));
+		}
+	}
+
+	/// Compile and insert an owned pattern.
+	fn import(&mut self, source: String) -> Result<(), Error> {
+		self.compile(&source).map(|_| ())
+	}
+
+	/// Build the key for the given source and options, reusing the buffer of
+	/// the previous lookup so hits don't allocate.
+	///
+	/// The key must be handed back to `fetch`.
+	fn key(&mut self, source: &str, options: &Options) -> RegexCacheKey {
+		let mut key = mem::replace(&mut self.scratch, RegexCacheKey {
+			source:  String::new(),
+			options: *options,
+		});
+
+		key.source.clear();
+		key.source.push_str(source);
+		key.options = *options;
+		key
+	}
+
+	/// Look up the entry for the given key, inserting the result of `f` if
+	/// it's missing or expired.
+	///
+	/// The access weight of the entry is updated if one is given.
+	fn fetch<F>(&mut self, key: RegexCacheKey, weight: Option<u32>, miss: Miss, f: F) -> Result<&Regex, Error>
+		where F: FnOnce() -> Result<Regex, Error>
+	{
+		let result = self.lookup(&key, weight, miss, f).map(|miss|
+			if let Some((re, elapsed)) = miss {
+				self.put(key.clone(), re, weight.unwrap_or(0), elapsed);
+			});
+
+		self.scratch = key;
+		result.map(move |_| self.most_recent())
+	}
+
+	/// Look up the entry for the given key, creating a regular expression with
+	/// `f` if it's missing or expired.
+	///
+	/// On a miss the result and the time it took to compile, if any, are
+	/// returned, and it's up to the caller to insert it.
+	fn lookup<F>(&mut self, key: &RegexCacheKey, weight: Option<u32>, miss: Miss, f: F) -> Result<Option<(Regex, Option<Duration>)>, Error>
+		where F: FnOnce() -> Result<Regex, Error>
+	{
+		self.expire(key);
+
+		if self.touch(key) {
+			if let (Some(weight), Some(meta)) = (weight, self.meta.get_mut(key)) {
+				self.weighted = self.weighted + (weight > 0) as usize - (meta.weight > 0) as usize;
+				meta.weight   = weight;
+			}
+
+			return Ok(None);
+		}
+
+		let (result, elapsed) = match miss {
+			Miss::Compile => {
+				if let Some(err) = self.errors.as_mut().and_then(|errors| errors.get_refresh(key)) {
+					let err = err.clone();
+					self.stats.hits += 1;
+					return Err(err);
+				}
+
+				let start  = Instant::now();
+				let result = f();
+				(result, start.elapsed())
+			}
+
+			Miss::Compiled(elapsed) =>
+				(f(), elapsed),
+
+			Miss::Insert =>
+				return f().map(|re| Some((re, None))),
+		};
+
+		self.stats.misses += 1;
+		self.stats.record_compile(elapsed);
+
+		if let (Err(err), Some(errors)) = (&result, self.errors.as_mut()) {
+			while errors.len() >= self.cache.capacity() && errors.pop_front().is_some() { }
+			errors.insert(key.clone(), err.clone());
+		}
+
+		result.map(|re| Some((re, Some(elapsed))))
+	}
+
+	/// Mark the entry for the given key as the most recently used, returning
+	/// whether it's present.
+	///
+	/// A hit is counted, but not a miss, which is up to the caller.
+	fn touch(&mut self, key: &RegexCacheKey) -> bool {
+		if self.cache.get(key).is_some() {
+			self.stats.hits += 1;
+
+			if let Some(meta) = self.meta.get_mut(key) {
+				meta.hits    += 1;
+				meta.accessed = self.clock.now();
+			}
+
+			true
+		}
+		else {
+			false
+		}
+	}
+
+	/// Get the most recently used entry, which is the one that was just
+	/// looked up or inserted.
+	///
+	/// This avoids a second hash lookup after `touch` or `put`.
+	fn most_recent(&self) -> &Regex {
+		self.cache.most_recent().unwrap()
+	}
+
+	/// Insert a compiled regular expression, timestamping it and making room
+	/// for it if needed.
+	fn put(&mut self, key: RegexCacheKey, re: Regex, weight: u32, compiled: Option<Duration>) {
+		let size = approximate_size(&re);
+
+		while self.is_full(size) && self.evict() { }
+
+		let now = self.clock.now();
+
+		let previous = self.meta.insert(key.clone(), Meta {
+			inserted: now,
+			accessed: now,
+			hits:     0,
+			weight:   weight,
+			size:     size,
+			compiled: compiled,
+		});
+
+		if let Some(previous) = previous {
+			self.used     -= previous.size;
+			self.weighted -= (previous.weight > 0) as usize;
+		}
+
+		self.used     += size;
+		self.weighted += (weight > 0) as usize;
+
+		if let Some(ref mut errors) = self.errors {
+			errors.remove(&key);
+		}
+		self.cache.insert(key, re);
+	}
+
+	/// Check if an entry of the given size would not fit in the cache.
+	fn is_full(&self, size: usize) -> bool {
+		self.cache.len() >= self.cache.capacity() ||
+			self.budget.is_some_and(|budget| self.used.saturating_add(size) > budget)
+	}
+
+	/// Get the first entry in eviction order among the ones with the lowest
+	/// weight.
+	///
+	/// The entries are only scanned if some have a weight, otherwise it's the
+	/// first one.
+	fn victim(&self) -> Option<(&RegexCacheKey, &Regex)> {
+		if self.weighted == 0 {
+			return self.cache.first();
+		}
+
+		self.cache.entries()
+			.min_by_key(|&(key, _)| self.meta.get(key).map_or(0, |m| m.weight))
+	}
+
+	/// Evict the next entry in eviction order, returning whether anything was
+	/// evicted.
+	fn evict(&mut self) -> bool {
+		let victim = self.victim().map(|(key, _)| key.clone());
+
+		if let Some(victim) = victim {
+			self.drop_entry(&victim, EvictionReason::Capacity);
+			self.stats.evictions += 1;
+			true
+		}
+		else {
+			false
+		}
+	}
+
+	/// Hash the keys of every map with the given hasher, the cache must be
+	/// empty.
+	fn set_hasher(&mut self, hasher: S) {
+		self.meta   = HashMap::with_hasher(hasher.clone());
+		self.groups = HashMap::with_hasher(hasher.clone());
+		self.names  = HashMap::with_hasher(hasher.clone());
+		self.data   = HashMap::with_hasher(hasher.clone());
+		self.errors = self.errors.as_ref().map(|_| LinkedHashMap::with_hasher(hasher.clone()));
+		self.hasher = hasher;
+	}
+
+	/// Remove the entry for the given key and its bookkeeping, returning its
+	/// metadata.
+	fn discard(&mut self, key: &RegexCacheKey) -> Option<M> {
+		self.cache.remove(key);
+
+		if let Some(meta) = self.meta.remove(key) {
+			self.used     -= meta.size;
+			self.weighted -= (meta.weight > 0) as usize;
+		}
+
+		if !self.groups.is_empty() {
+			self.groups.retain(|_, keys| {
+				keys.remove(key);
+				!keys.is_empty()
+			});
+		}
+
+		self.data.remove(key)
+	}
+
+	/// Remove the entry for the given key on behalf of the cache, handing its
+	/// metadata to the eviction callback.
+	fn drop_entry(&mut self, key: &RegexCacheKey, reason: EvictionReason) {
+		let meta = self.discard(key);
+
+		if let Some(ref on_evict) = self.on_evict {
+			on_evict(key, meta, reason);
+		}
+	}
+
+	/// Get the cached keys in a canonical order.
+	fn sorted_patterns(&self) -> Vec<&RegexCacheKey> {
+		let mut keys = self.cache.entries().map(|(key, _)| key)
+			.collect::<Vec<_>>();
+
+		keys.sort_unstable_by_key(|key| (&key.source, RegexFlags::from(key.options).bits(),
+			key.options.line_terminator, key.options.nest_limit, key.options.size_limit,
+			key.options.dfa_size_limit));
+		keys
+	}
+
+	/// Remove the entry for the given key if it outlived the time-to-live.
+	fn expire(&mut self, key: &RegexCacheKey) {
+		if self.is_expired(key) {
+			self.drop_entry(key, EvictionReason::Expired);
+		}
+	}
+
+	/// Check if the entry for the given key outlived the time-to-live.
+	fn is_expired(&self, key: &RegexCacheKey) -> bool {
+		let ttl = if let Some(ttl) = self.ttl { ttl } else { return false };
+
+		self.meta.get(key).is_some_and(|meta|
+			self.clock.now().saturating_duration_since(meta.inserted) >= ttl)
+	}
+}
+
+impl<S: BuildHasher + Default + Clone> GenericRegexCache<LruPolicy<S>, S> {
+	/// Create a new LRU cache with the given size limit, hashing the keys
+	/// with the given hasher.
+	///
+	/// Every map the cache keeps is built with it, including the ones for
+	/// metadata, groups, names and cached errors.
+	///
+	/// The default hasher is resistant to collision attacks but not the
+	/// fastest for short keys, a faster one can be used when the patterns
+	/// aren't controlled by an attacker.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use std::hash::BuildHasherDefault;
+	/// # use std::collections::hash_map::DefaultHasher;
+	/// # use regex_cache::GenericRegexCache;
+	/// let mut cache = GenericRegexCache::with_hasher(100, BuildHasherDefault::<DefaultHasher>::default());
+	///
+	/// assert!(cache.compile(r"^\d+$").unwrap().is_match("1234"));
+	/// ```
+	pub fn with_hasher(capacity: usize, hasher: S) -> Self {
+		let mut cache = Self::new(capacity);
+		cache.cache = LruPolicy::with_hasher(bounded(capacity), hasher.clone());
+		cache.set_hasher(hasher);
+		cache
+	}
+}
+
+/// A configurable builder for a `RegexCache`.
+///
+/// The defaults are the same as `RegexCache::new(DEFAULT_CAPACITY)`.
+///
+/// # Example
+///
+/// ```
+/// # use std::time::Duration;
+/// # use regex_cache::RegexCacheBuilder;
+/// let mut cache = RegexCacheBuilder::new()
+/// 	.capacity(100)
+/// 	.ttl(Duration::from_secs(60 * 60))
+/// 	.memory_budget(64 * 1024)
+/// 	.build();
+///
+/// assert!(cache.compile(r"^\d+$").unwrap().is_match("1234"));
+/// ```
+#[derive(Clone)]
+pub struct RegexCacheBuilder<S = RandomState> {
+	capacity: usize,
+	ttl:      Option<Duration>,
+	budget:   Option<usize>,
+	clock:    Arc<dyn Clock>,
+	limit:    Option<usize>,
+	patterns: Option<PatternLimits>,
+	errors:   bool,
+	hasher:   S,
+	on_evict: Option<OnEvict<()>>,
+}
+
+impl RegexCacheBuilder {
+	/// Create a new builder with the default configuration.
+	pub fn new() -> RegexCacheBuilder {
+		RegexCacheBuilder {
+			capacity: DEFAULT_CAPACITY,
+			ttl:      None,
+			budget:   None,
+			clock:    Arc::new(SystemClock),
+			limit:    None,
+			patterns: None,
+			errors:   false,
+			hasher:   RandomState::new(),
+			on_evict: None,
+		}
+	}
+
+	/// Build the cache wrapped in a thread-safe handle.
+	pub fn build_shared(&self) -> SharedRegexCache {
+		SharedRegexCache::from(self.build())
+	}
+}
+
+impl<S: BuildHasher + Default + Clone> RegexCacheBuilder<S> {
+	/// Build the cache.
+	pub fn build(&self) -> GenericRegexCache<LruPolicy<S>, S> {
+		let mut cache = self.build_with_policy::<LruPolicy<S>>();
+		cache.cache = LruPolicy::with_hasher(bounded(self.capacity), self.hasher.clone());
+		cache
+	}
+
+	/// Build the cache, rejecting a capacity of `0`.
+	///
+	/// See `RegexCache::try_new`.
+	pub fn try_build(&self) -> Result<GenericRegexCache<LruPolicy<S>, S>, CacheError> {
+		if self.capacity == 0 {
+			return Err(CacheError::ZeroCapacity);
+		}
+
+		Ok(self.build())
+	}
+
+	/// Build the cache with the given eviction policy.
+	pub fn build_with_policy<P: EvictionPolicy>(&self) -> GenericRegexCache<P, S> {
+		let mut cache = GenericRegexCache::new(self.capacity);
+		cache.set_hasher(self.hasher.clone());
+		cache.ttl      = self.ttl;
+		cache.budget   = self.budget;
+		cache.clock    = self.clock.clone();
+		cache.on_evict = self.on_evict.clone();
+
+		if let Some(limit) = self.limit {
+			cache.defaults.size_limit = limit;
+		}
+
+		if let Some(ref limits) = self.patterns {
+			cache.set_pattern_limits(limits.clone());
+		}
+
+		cache.set_cache_errors(self.errors);
+
+		cache
+	}
+
+	/// Set the maximum number of entries, `0` meaning unbounded.
+	///
+	/// See `RegexCache::new`.
+	pub fn capacity(&mut self, capacity: usize) -> &mut Self {
+		self.capacity = capacity;
+		self
+	}
+
+	/// Set the time-to-live for the entries.
+	///
+	/// See `RegexCache::set_ttl`.
+	pub fn ttl(&mut self, ttl: Duration) -> &mut Self {
+		self.ttl = Some(ttl);
+		self
+	}
+
+	/// Set the approximate memory the compiled regular expressions can use,
+	/// on top of the limit on the number of entries.
+	///
+	/// See `RegexCache::with_memory_budget`.
+	pub fn memory_budget(&mut self, bytes: usize) -> &mut Self {
+		self.budget = Some(bytes);
+		self
+	}
+
+	/// Set the clock used to timestamp and expire entries.
+	///
+	/// See `RegexCache::set_clock`.
+	pub fn clock<C: Clock + 'static>(&mut self, clock: C) -> &mut Self {
+		self.clock = Arc::new(clock);
+		self
+	}
+
+	/// Set the size limit used to compile regular expressions, in bytes.
+	///
+	/// See `RegexCache::with_size_limit`.
+	pub fn size_limit(&mut self, bytes: usize) -> &mut Self {
+		self.limit = Some(bytes);
+		self
+	}
+
+	/// Set the limits every pattern is checked against before compiling it.
+	///
+	/// See `RegexCache::set_pattern_limits`.
+	pub fn pattern_limits(&mut self, limits: PatternLimits) -> &mut Self {
+		self.patterns = Some(limits);
+		self
+	}
+
+	/// Remember the patterns that failed to compile.
+	///
+	/// See `RegexCache::set_cache_errors`.
+	pub fn cache_errors(&mut self, enabled: bool) -> &mut Self {
+		self.errors = enabled;
+		self
+	}
+
+	/// Set the function called for every entry the cache drops on its own,
+	/// with the reason it was dropped.
+	///
+	/// See `RegexCache::set_on_evict`.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use std::sync::{Arc, Mutex};
+	/// # use regex_cache::{RegexCacheBuilder, EvictionReason};
+	/// let evicted = Arc::new(Mutex::new(Vec::new()));
+	/// let sink    = evicted.clone();
+	///
+	/// let mut cache = RegexCacheBuilder::new()
+	/// 	.capacity(1)
+	/// 	.on_evict(move |key, reason| sink.lock().unwrap().push((key.source.clone(), reason)))
+	/// 	.build();
+	///
+	/// cache.compile("a").unwrap();
+	/// cache.compile("b").unwrap();
+	///
+	/// assert_eq!(*evicted.lock().unwrap(), [("a".to_owned(), EvictionReason::Capacity)]);
+	/// ```
+	pub fn on_evict<F>(&mut self, f: F) -> &mut Self
+		where F: Fn(&RegexCacheKey, EvictionReason) + Send + Sync + 'static
+	{
+		self.on_evict = Some(Arc::new(move |key, _, reason| f(key, reason)));
+		self
+	}
+
+	/// Set the hasher used for the keys.
+	///
+	/// See `RegexCache::with_hasher`.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use std::hash::BuildHasherDefault;
+	/// # use std::collections::hash_map::DefaultHasher;
+	/// # use regex_cache::RegexCacheBuilder;
+	/// let mut cache = RegexCacheBuilder::new()
+	/// 	.capacity(100)
+	/// 	.hasher(BuildHasherDefault::<DefaultHasher>::default())
+	/// 	.build();
+	///
+	/// assert!(cache.compile(r"^\d+$").unwrap().is_match("1234"));
+	/// ```
+	pub fn hasher<T: BuildHasher + Default + Clone>(&self, hasher: T) -> RegexCacheBuilder<T> {
+		RegexCacheBuilder {
+			capacity: self.capacity,
+			ttl:      self.ttl,
+			budget:   self.budget,
+			clock:    self.clock.clone(),
+			limit:    self.limit,
+			patterns: self.patterns.clone(),
+			errors:   self.errors,
+			hasher,
+			on_evict: self.on_evict.clone(),
+		}
+	}
+}
+
+impl<S> fmt::Debug for RegexCacheBuilder<S> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("RegexCacheBuilder")
+			.field("capacity", &self.capacity)
+			.field("ttl", &self.ttl)
+			.field("budget", &self.budget)
+			.field("clock", &self.clock)
+			.field("limit", &self.limit)
+			.field("patterns", &self.patterns)
+			.field("errors", &self.errors)
+			.field("on_evict", &self.on_evict.is_some())
+			.finish()
+	}
+}
+
+impl Default for RegexCacheBuilder {
+	fn default() -> Self {
+		RegexCacheBuilder::new()
+	}
+}
+
+/// A pattern together with the options it's compiled with, which is what
+/// entries in a `RegexCache` are keyed by.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct RegexCacheKey {
+	/// The source of the regular expression.
+	pub source: String,
+
+	/// The options the regular expression is compiled with.
+	pub options: Options,
+}
+
+impl RegexCacheKey {
+	/// Create a new key for the given source and options, or flags.
+	pub fn new<O: Into<Options>>(source: &str, options: O) -> RegexCacheKey {
+		RegexCacheKey {
+			source:  source.to_owned(),
+			options: options.into(),
+		}
+	}
+
+	/// Get the pattern that behaves like the source compiled with the flags
+	/// of the options, which is the source itself for the default flags.
+	///
+	/// The size limits and line terminator can't be set inline, so they're
+	/// not part of the pattern.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::{RegexCacheKey, RegexFlags};
+	/// let key = RegexCacheKey::new("abc", RegexFlags::default() | RegexFlags::CASE_INSENSITIVE);
+	/// assert_eq!(key.pattern(), "(?i:abc)");
+	/// ```
+	pub fn pattern(&self) -> Cow<'_, str> {
+		let flags = RegexFlags::from(self.options);
+
+		if flags == RegexFlags::default() {
+			self.source.as_str().into()
+		}
+		else {
+			flags.inline(&self.source).into()
+		}
+	}
+}
+
+/// The keys held by a `RegexCache`, used to persist the hot set of regular
+/// expressions across restarts, or to reset a cache between tests.
+///
+/// The keys are ordered from the least to the most recently used.
+#[derive(Clone, Eq, PartialEq, Default, Debug)]
+pub struct CacheSnapshot {
+	/// The cached keys.
+	pub keys: Vec<RegexCacheKey>,
+}
+
+/// Compile the given patterns with the given options and limits on as many
+/// threads as there are CPUs, keeping their order.
+pub(crate) fn compile_parallel(patterns: &[&str], options: &Options, limits: Option<&PatternLimits>) -> Vec<(Result<Regex, Error>, Duration)> {
+	let threads = thread::available_parallelism().map_or(1, |n| n.get());
+	let chunk   = patterns.len().div_ceil(threads).max(1);
+
+	thread::scope(|scope| {
+		let handles = patterns.chunks(chunk).map(|chunk| scope.spawn(move ||
+			chunk.iter().map(|source| {
+				let start  = Instant::now();
+				let result = guard(source, limits).and_then(|_|
+					options.define(&mut RegexBuilder::new(source)).build());
+
+				(result, start.elapsed())
+			}).collect::<Vec<_>>()))
+			.collect::<Vec<_>>();
+
+		handles.into_iter().flat_map(|handle| handle.join().unwrap())
+			.collect()
+	})
+}
+
+/// Approximate number of bytes taken by a single compiled instruction.
+const INSTRUCTION_SIZE: usize = 16;
+
+/// Estimate the memory used by a compiled regular expression.
+///
+/// The `regex` crate doesn't expose the size of its compiled programs, so the
+/// estimate is derived from the number of literals, class ranges and
+/// repetitions in the pattern, it's only meant to tell small and huge
+/// regular expressions apart.
+fn approximate_size(re: &Regex) -> usize {
+	use crate::syntax::hir::{Hir, HirKind, Class, RepetitionKind, RepetitionRange};
+
+	fn instructions(hir: &Hir) -> usize {
+		match *hir.kind() {
+			HirKind::Empty | HirKind::Literal(_) | HirKind::Anchor(_) | HirKind::WordBoundary(_) =>
+				1,
+
+			HirKind::Class(Class::Unicode(ref class)) =>
+				class.ranges().len(),
+
+			HirKind::Class(Class::Bytes(ref class)) =>
+				class.ranges().len(),
+
+			HirKind::Repetition(ref rep) => {
+				let times = match rep.kind {
+					RepetitionKind::ZeroOrOne | RepetitionKind::ZeroOrMore | RepetitionKind::OneOrMore =>
+						1,
+
+					RepetitionKind::Range(RepetitionRange::Exactly(n)) |
+					RepetitionKind::Range(RepetitionRange::AtLeast(n)) |
+					RepetitionKind::Range(RepetitionRange::Bounded(_, n)) =>
+						n.max(1) as usize,
+				};
+
+				instructions(&rep.hir).saturating_mul(times).saturating_add(1)
+			}
+
+			HirKind::Group(ref group) =>
+				instructions(&group.hir).saturating_add(2),
+
+			HirKind::Concat(ref hirs) | HirKind::Alternation(ref hirs) =>
+				hirs.iter().fold(hirs.len(), |acc, hir| acc.saturating_add(instructions(hir))),
+		}
+	}
+
+	let source = re.as_str();
+	let count  = syntax::Parser::new().parse(source)
+		.map(|hir| instructions(&hir))
+		.unwrap_or_else(|_| source.len());
+
+	source.len().saturating_add(count.saturating_mul(INSTRUCTION_SIZE))
+}
+
+impl<P: EvictionPolicy, S: BuildHasher + Default + Clone, M> Default for GenericRegexCache<P, S, M> {
+	fn default() -> Self {
+		Self::with_default_capacity()
+	}
+}
+
+/// Two caches are equal when they have the same capacity and hold the same
+/// patterns, regardless of their recency order.
+impl<P: EvictionPolicy, S: BuildHasher + Default + Clone, M> PartialEq for GenericRegexCache<P, S, M> {
+	fn eq(&self, other: &Self) -> bool {
+		self.cache.capacity() == other.cache.capacity() &&
+			self.sorted_patterns() == other.sorted_patterns()
+	}
+}
+
+impl<P: EvictionPolicy, S: BuildHasher + Default + Clone, M> Eq for GenericRegexCache<P, S, M> { }
+
+impl<P: EvictionPolicy, S: BuildHasher + Default + Clone, M> Hash for GenericRegexCache<P, S, M> {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.cache.capacity().hash(state);
+		self.sorted_patterns().hash(state);
+	}
+}
+
+/// The number of patterns shown by the `Debug` output of a cache.
+const DEBUG_PATTERNS: usize = 8;
+
+/// Shows the capacity, the statistics and the most recently used patterns,
+/// most recent first, without the compiled programs.
+impl<P: EvictionPolicy, S: BuildHasher + Default + Clone, M> fmt::Debug for GenericRegexCache<P, S, M> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		struct Recent<'a>(Vec<&'a str>);
+
+		impl fmt::Debug for Recent<'_> {
+			fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+				let mut list = f.debug_list();
+				list.entries(self.0.iter().rev().take(DEBUG_PATTERNS));
+
+				if self.0.len() > DEBUG_PATTERNS {
+					list.entry(&format_args!("..."));
+				}
+
+				list.finish()
+			}
+		}
+
+		f.debug_struct("RegexCache")
+			.field("capacity", &self.capacity())
+			.field("len", &self.len())
+			.field("stats", &self.stats)
+			.field("recent", &Recent(self.cache.entries().map(|(key, _)| key.source.as_str()).collect()))
+			.finish()
+	}
+}
+
+/// A `Regex` compiled on demand through a shared `RegexCache`.
+///
+/// The regular expression is fetched from the cache when it's created, or at
+/// the first use for the unchecked constructors, and kept by the instance, so
+/// later calls don't lock the cache. It stays usable once evicted from the
+/// cache, and clones share it too once it's fetched.
+#[derive(Clone)]
+pub struct CachedRegex {
+	builder: CachedRegexBuilder,
+	memo:    OnceLock<Regex>,
+}
+
+/// Get the memoized regular expression, which can only fail to compile for
+/// the unchecked constructors.
+macro_rules! regex {
+	($self:ident) => (
+		match $self.compiled() {
+			Ok(re) =>
+				re,
+
+			Err(err) =>
+				panic!("invalid pattern {:?}: {}", $self.builder.source, err),
+		}
+	)
+}
+
+impl CachedRegex {
+	/// Create a new cached `Regex` for the given source, compiling it through
+	/// the cache so any error is returned here.
+	pub fn new(cache: Arc<Mutex<RegexCache>>, source: &str) -> Result<CachedRegex, Error> {
+		CachedRegexBuilder::new(cache, source).build()
+	}
+
+	/// Create a new cached `Regex` for the given source, compiled at the first
+	/// use.
+	///
+	/// Only use this if you know that the pattern is valid or you are ready to
+	/// handle errors later on with `compiled`, the other methods panic if the
+	/// pattern fails to compile.
+	pub fn new_unchecked(cache: Arc<Mutex<RegexCache>>, source: &str) -> CachedRegex {
+		CachedRegex::from(CachedRegexBuilder::new(cache, source))
+	}
+
+	fn from(builder: CachedRegexBuilder) -> Self {
+		CachedRegex {
+			builder: builder,
+			memo:    OnceLock::new(),
+		}
+	}
+
+	/// Get the compiled regular expression, fetching it from the cache at the
+	/// first use.
+	///
+	/// Only the unchecked constructors can leave an error to report here,
+	/// the others compile the regular expression up front.
+	pub fn compiled(&self) -> Result<&Regex, Error> {
+		if let Some(re) = self.memo.get() {
+			return Ok(re);
+		}
+
+		let re = self.builder.cache.lock().unwrap()
+			.compile_with(&self.builder.source, &self.builder.options)?.clone();
+
+		Ok(self.memo.get_or_init(|| re))
+	}
+
+	/// Refer to `Regex::is_match`.
+	pub fn is_match(&self, text: &str) -> bool {
+		regex!(self).is_match(text)
+	}
+
+	/// Refer to `Regex::find`.
+	pub fn find<'t>(&self, text: &'t str) -> Option<Match<'t>> {
+		regex!(self).find(text)
+	}
+
+	/// Refer to `Regex::captures`.
+	pub fn captures<'t>(&self, text: &'t str) -> Option<Captures<'t>> {
+		regex!(self).captures(text)
+	}
+
+	/// Get all the non-overlapping matches in `text`, like `Regex::find_iter`.
+	///
+	/// Like every other method, this only locks the cache if the regular
+	/// expression wasn't fetched yet, no lock is taken after the first use.
+	/// The matches are copied out along with their text, so they don't borrow
+	/// `text`, at the cost of one allocation per match.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use std::sync::{Arc, Mutex};
+	/// # use regex_cache::{RegexCache, CachedRegex};
+	/// let cache = Arc::new(Mutex::new(RegexCache::new(100)));
+	/// let re    = CachedRegex::new(cache, r"\d+").unwrap();
+	///
+	/// let found = re.find_iter_owned("a1 b22");
+	/// assert_eq!(found.iter().map(|m| m.as_str()).collect::<Vec<_>>(), ["1", "22"]);
+	/// assert_eq!(found[1].range(), 4 .. 6);
+	/// ```
+	pub fn find_iter_owned(&self, text: &str) -> Vec<OwnedMatch> {
+		regex!(self).find_iter(text).map(OwnedMatch::from).collect()
+	}
+
+	/// Get the capture groups of all the non-overlapping matches in `text`,
+	/// like `Regex::captures_iter`.
+	///
+	/// The cost is the same as for `find_iter_owned`, plus the copy of every
+	/// group and of the group names for each match.
+	pub fn captures_iter_owned(&self, text: &str) -> Vec<OwnedCaptures> {
+		let re = regex!(self);
+		re.captures_iter(text).map(|caps| OwnedCaptures::new(re, &caps)).collect()
+	}
+
+	/// Refer to `Regex::replace`.
+	pub fn replace<'t, R: Replacer>(&self, text: &'t str, rep: R) -> Cow<'t, str> {
+		regex!(self).replace(text, rep)
+	}
+
+	/// Refer to `Regex::replace_all`.
+	pub fn replace_all<'t, R: Replacer>(&self, text: &'t str, rep: R) -> Cow<'t, str> {
+		regex!(self).replace_all(text, rep)
+	}
+
+	/// Refer to `Regex::split`.
+	pub fn split<'r, 't>(&'r self, text: &'t str) -> Split<'r, 't> {
+		regex!(self).split(text)
+	}
+
+	/// Refer to `Regex::replacen`.
+	pub fn replacen<'t, R: Replacer>(&self, text: &'t str, limit: usize, rep: R) -> Cow<'t, str> {
+		regex!(self).replacen(text, limit, rep)
+	}
+
+	/// Refer to `Regex::splitn`.
+	pub fn splitn<'r, 't>(&'r self, text: &'t str, limit: usize) -> SplitN<'r, 't> {
+		regex!(self).splitn(text, limit)
+	}
+
+	/// Refer to `Regex::shortest_match`.
+	pub fn shortest_match(&self, text: &str) -> Option<usize> {
+		regex!(self).shortest_match(text)
+	}
+
+	/// Refer to `Regex::captures_len`.
+	pub fn captures_len(&self) -> usize {
+		regex!(self).captures_len()
+	}
+
+	/// Refer to `Regex::capture_names`.
+	pub fn capture_names(&self) -> CaptureNames<'_> {
+		regex!(self).capture_names()
+	}
+
+	/// Get a handle to the cache the regular expression is compiled in.
+	///
+	/// The handle shares the cache, so it can be used to check the statistics
+	/// or to create other cached regular expressions in the same cache.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use std::sync::{Arc, Mutex};
+	/// # use regex_cache::{RegexCache, CachedRegex};
+	/// let re    = CachedRegex::new(Arc::new(Mutex::new(RegexCache::new(100))), "a+").unwrap();
+	/// let other = CachedRegex::new(re.cache().into(), "b+").unwrap();
+	///
+	/// assert!(re.same_cache(&other));
+	/// ```
+	pub fn cache(&self) -> SharedRegexCache {
+		SharedRegexCache::from(self.builder.cache.clone())
+	}
+
+	/// Check if both regular expressions are compiled in the same cache.
+	pub fn same_cache(&self, other: &CachedRegex) -> bool {
+		Arc::ptr_eq(&self.builder.cache, &other.builder.cache)
+	}
+
+	/// Get the cache shared by the whole process, used by `FromStr`.
+	///
+	/// It holds up to `DEFAULT_CAPACITY` entries and is created at the first
+	/// call.
+	pub fn default_cache() -> Arc<Mutex<RegexCache>> {
+		static CACHE: OnceLock<Arc<Mutex<RegexCache>>> = OnceLock::new();
+		CACHE.get_or_init(|| Arc::new(Mutex::new(RegexCache::new(DEFAULT_CAPACITY)))).clone()
+	}
+
+	/// Get the source given to `new`.
+	pub fn as_str(&self) -> &str {
+		&self.builder.source
+	}
+}
+
+/// Create a regular expression in `CachedRegex::default_cache`.
+///
+/// # Example
+///
+/// ```
+/// # use regex_cache::CachedRegex;
+/// let re = r"^\d+$".parse::<CachedRegex>().unwrap();
+///
+/// assert!(re.is_match("1234"));
+/// ```
+impl str::FromStr for CachedRegex {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<CachedRegex, Error> {
+		CachedRegex::new(CachedRegex::default_cache(), s)
+	}
+}
+
+/// Two cached regular expressions are equal when they have the same source
+/// and options, whatever cache they use.
+///
+/// Different patterns matching the same texts are not equal.
+impl PartialEq for CachedRegex {
+	fn eq(&self, other: &CachedRegex) -> bool {
+		self.builder.source == other.builder.source &&
+			self.builder.options == other.builder.options
+	}
+}
+
+impl Eq for CachedRegex { }
+
+impl Hash for CachedRegex {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.builder.source.hash(state);
+		self.builder.options.hash(state);
+	}
+}
+
+/// Shows the source, the options and whether the regular expression is in the
+/// cache, which is `None` if the cache is locked or poisoned.
+///
+/// The cache is never waited on and nothing gets compiled, so formatting is
+/// safe from a panic handler.
+impl fmt::Debug for CachedRegex {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let key    = RegexCacheKey::new(&self.builder.source, self.builder.options);
+		let cached = self.builder.cache.try_lock().ok().map(|cache| cache.contains_key(&key));
+
+		f.debug_struct("CachedRegex")
+			.field("source", &self.builder.source)
+			.field("options", &self.builder.options)
+			.field("cached", &cached)
+			.finish()
+	}
+}
+
+/// Shows the source, without locking the cache.
+impl fmt::Display for CachedRegex {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(&self.builder.source)
+	}
+}
+
+/// A configurable builder for a cached `Regex`.
+#[derive(Clone, Debug)]
+pub struct CachedRegexBuilder {
+	cache:   Arc<Mutex<RegexCache>>,
+	source:  String,
+	options: Options,
+}
+
+impl CachedRegexBuilder {
+	/// Create a new regular expression builder with the given pattern.
+	///
+	/// If the pattern is invalid, then an error will be returned when
+	/// `compile` is called.
+	pub fn new(cache: Arc<Mutex<RegexCache>>, source: &str) -> CachedRegexBuilder {
+		CachedRegexBuilder {
+			cache:   cache,
+			source:  source.to_owned(),
+			options: Default::default(),
+		}
+	}
+
+	/// Consume the builder and compile the regular expression through the
+	/// cache, so syntax errors and exceeded limits are returned here.
+	///
+	/// Note that calling `as_str` on the resulting `Regex` will produce the
+	/// pattern given to `new` verbatim. Notably, it will not incorporate any
+	/// of the flags set on this builder.
+	pub fn build(&self) -> Result<CachedRegex, Error> {
+		let re = CachedRegex::from(self.clone());
+		re.compiled()?;
+
+		Ok(re)
+	}
+
+	/// Consume the builder and create the regular expression, compiled at the
+	/// first use.
+	///
+	/// Only use this if you know that the pattern is valid or you are ready to
+	/// handle errors later on, see `CachedRegex::compiled`.
+	///
+	/// Note that calling `as_str` on the resulting `Regex` will produce the
+	/// pattern given to `new` verbatim. Notably, it will not incorporate any
+	/// of the flags set on this builder.
+	pub fn build_unchecked(&self) -> CachedRegex {
+		CachedRegex::from(self.clone())
+	}
+
+	/// Set the value for the case insensitive (`i`) flag.
+	pub fn case_insensitive(&mut self, yes: bool) -> &mut CachedRegexBuilder {
+		self.options.case_insensitive = yes;
+		self
+	}
+
+	/// Set the value for the multi-line matching (`m`) flag.
+	pub fn multi_line(&mut self, yes: bool) -> &mut CachedRegexBuilder {
+		self.options.multi_line = yes;
+		self
+	}
+
+	/// Set the value for the any character (`s`) flag, where in `.` matches
+	/// anything when `s` is set and matches anything except for new line when
+	/// it is not set (the default).
+	///
+	/// N.B. "matches anything" means "any byte" for `regex::bytes::Regex`
 	/// expressions and means "any Unicode scalar value" for `regex::Regex`
 	/// expressions.
 	pub fn dot_matches_new_line(&mut self, yes: bool) -> &mut CachedRegexBuilder {
@@ -284,72 +3039,1547 @@ impl CachedRegexBuilder {
 		self
 	}
 
-	/// Set the value for the greedy swap (`U`) flag.
-	pub fn swap_greed(&mut self, yes: bool) -> &mut CachedRegexBuilder {
-		self.options.swap_greed = yes;
-		self
+	/// Set the value for the greedy swap (`U`) flag.
+	pub fn swap_greed(&mut self, yes: bool) -> &mut CachedRegexBuilder {
+		self.options.swap_greed = yes;
+		self
+	}
+
+	/// Set the value for the ignore whitespace (`x`) flag.
+	pub fn ignore_whitespace(&mut self, yes: bool) -> &mut CachedRegexBuilder {
+		self.options.ignore_whitespace = yes;
+		self
+	}
+
+	/// Set the value for the Unicode (`u`) flag.
+	pub fn unicode(&mut self, yes: bool) -> &mut CachedRegexBuilder {
+		self.options.unicode = yes;
+		self
+	}
+
+	/// Set the line terminator used by `^` and `$` in multi-line mode, the
+	/// default is `\n`.
+	pub fn line_terminator(&mut self, byte: u8) -> &mut CachedRegexBuilder {
+		self.options.line_terminator = byte;
+		self
+	}
+
+	/// Set the maximum nesting depth of the pattern, the default is `250`.
+	///
+	/// Both the syntax check in `build` and the compilation use this limit.
+	pub fn nest_limit(&mut self, limit: u32) -> &mut CachedRegexBuilder {
+		self.options.nest_limit = limit;
+		self
+	}
+
+	/// Set the approximate size limit of the compiled regular expression.
+	///
+	/// This roughly corresponds to the number of bytes occupied by a single
+	/// compiled program. If the program exceeds this number, then a
+	/// compilation error is returned.
+	pub fn size_limit(&mut self, limit: usize) -> &mut CachedRegexBuilder {
+		self.options.size_limit = limit;
+		self
+	}
+
+	/// Set the approximate size of the cache used by the DFA.
+	///
+	/// This roughly corresponds to the number of bytes that the DFA will
+	/// use while searching.
+	///
+	/// Note that this is a *per thread* limit. There is no way to set a global
+	/// limit. In particular, if a regex is used from multiple threads
+	/// simulanteously, then each thread may use up to the number of bytes
+	/// specified here.
+	pub fn dfa_size_limit(&mut self, limit: usize) -> &mut CachedRegexBuilder {
+		self.options.dfa_size_limit = limit;
+		self
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::sync::{Arc, Mutex};
+	use std::time::{Duration, Instant};
+	use regex::{Regex, Error};
+	use crate::cache::{RegexCache, RegexCacheBuilder, RegexCacheKey, CachedRegex, CachedRegexBuilder};
+	use crate::clock::Clock;
+	use crate::options::{Options, RegexFlags};
+
+	#[derive(Clone, Debug)]
+	struct MockClock(Arc<Mutex<Instant>>);
+
+	impl MockClock {
+		fn new() -> Self {
+			MockClock(Arc::new(Mutex::new(Instant::now())))
+		}
+
+		fn advance(&self, by: Duration) {
+			*self.0.lock().unwrap() += by;
+		}
+	}
+
+	impl Clock for MockClock {
+		fn now(&self) -> Instant {
+			*self.0.lock().unwrap()
+		}
+	}
+
+	#[test]
+	fn respects_limit() {
+		let mut cache = RegexCache::new(2);
+
+		cache.compile("[01]2").unwrap();
+		cache.compile("[21]0").unwrap();
+
+		assert_eq!(cache.len(), 2);
+		cache.compile("[21]3").unwrap();
+		assert_eq!(cache.len(), 2);
+	}
+
+	#[test]
+	fn cached_regex() {
+		let cache = Arc::new(Mutex::new(RegexCache::new(100)));
+		let re = CachedRegex::new(cache.clone(), r"^\d+$").unwrap();
+
+		assert!(re.is_match("123"));
+		assert!(!re.is_match("abc"));
+	}
+
+	#[test]
+	fn ttl_expires_on_access() {
+		let clock = MockClock::new();
+		let mut cache = RegexCache::new(10);
+		cache.set_clock(clock.clone());
+		cache.set_ttl(Duration::from_secs(60));
+
+		cache.compile("abc").unwrap();
+		clock.advance(Duration::from_secs(30));
+		assert!(!cache.configure("abc", |b| b.case_insensitive(true)).unwrap()
+			.is_match("ABC"));
+
+		clock.advance(Duration::from_secs(30));
+		cache.compile("b+").unwrap();
+		assert_eq!(cache.len(), 2);
+
+		assert!(cache.configure("abc", |b| b.case_insensitive(true)).unwrap()
+			.is_match("ABC"));
+	}
+
+	#[test]
+	fn ttl_purge() {
+		let clock = MockClock::new();
+		let mut cache = RegexCache::new(10);
+		cache.set_clock(clock.clone());
+		cache.set_ttl(Duration::from_secs(60));
+
+		cache.compile("a+").unwrap();
+		clock.advance(Duration::from_secs(45));
+		cache.compile("b+").unwrap();
+		clock.advance(Duration::from_secs(15));
+
+		cache.purge_expired();
+		assert_eq!(cache.len(), 1);
+		assert!(cache.iter().all(|(k, _)| k.source == "b+"));
+
+		clock.advance(Duration::from_secs(45));
+		cache.purge_expired();
+		assert!(cache.is_empty());
+	}
+
+	#[test]
+	fn weighted_eviction() {
+		let mut cache = RegexCache::new(3);
+
+		cache.compile_with_hint("a", 5).unwrap();
+		cache.compile_with_hint("b", 1).unwrap();
+		cache.compile("c").unwrap();
+
+		cache.compile("d").unwrap();
+		assert!(!cache.iter().any(|(k, _)| k.source == "c"));
+
+		cache.compile("e").unwrap();
+		assert!(!cache.iter().any(|(k, _)| k.source == "d"));
+
+		cache.compile_with_hint("f", 2).unwrap();
+		let keys = cache.iter().map(|(k, _)| k.source.as_str()).collect::<Vec<_>>();
+		assert_eq!(keys, ["a", "b", "f"]);
+		assert_eq!(cache.weighted, 3);
+
+		// Dropping the weights brings back plain eviction order.
+		cache.compile_with_hint("a", 0).unwrap();
+		cache.compile_with_hint("b", 0).unwrap();
+		cache.compile_with_hint("f", 0).unwrap();
+		assert_eq!(cache.weighted, 0);
+
+		cache.compile("g").unwrap();
+		assert_eq!(cache.export_patterns(), ["b", "f", "g"]);
+	}
+
+	#[test]
+	fn memory_budget() {
+		let mut cache = RegexCache::with_memory_budget(4096);
+
+		for source in &["a", "b", "c", "d", "e"] {
+			cache.compile(source).unwrap();
+		}
+
+		assert_eq!(cache.len(), 5);
+		cache.compile(r"\w{5}").unwrap();
+		assert_eq!(cache.len(), 1);
+
+		cache.compile("f").unwrap();
+		let keys = cache.iter().map(|(k, _)| k.source.as_str()).collect::<Vec<_>>();
+		assert_eq!(keys, ["f"]);
+	}
+
+	#[test]
+	fn memory_budget_evicts_lru() {
+		let mut cache = RegexCache::with_memory_budget(80);
+
+		cache.compile("a").unwrap();
+		cache.compile("b").unwrap();
+		cache.compile("c").unwrap();
+		cache.compile("a").unwrap();
+		cache.compile("[a-z]{2}").unwrap();
+
+		let keys = cache.iter().map(|(k, _)| k.source.as_str()).collect::<Vec<_>>();
+		assert_eq!(keys, ["a", "[a-z]{2}"]);
+	}
+
+	#[test]
+	fn warm() {
+		let mut cache  = RegexCache::new(2);
+		let     report = cache.warm(vec!["a+", "(b", "c+", "d+"]);
+
+		assert_eq!(report.len(), 4);
+		assert_eq!(report[1].0, "(b");
+		assert!(report[1].1.is_err());
+		assert!(report.iter().filter(|&(_, r)| r.is_ok()).count() == 3);
+
+		let keys = cache.iter().map(|(k, _)| k.source.as_str()).collect::<Vec<_>>();
+		assert_eq!(keys, ["c+", "d+"]);
+	}
+
+	#[test]
+	fn warm_with() {
+		let options = Options { case_insensitive: true, .. Default::default() };
+
+		let mut cache  = RegexCache::new(10);
+		let     report = cache.warm_with(vec![("abc", options), ("[", options)]);
+
+		assert!(report[0].1.is_ok());
+		assert!(report[1].1.is_err());
+		assert!(cache.compile_with("abc", &options).unwrap().is_match("ABC"));
+		assert!(!cache.compile("abc").unwrap().is_match("ABC"));
+	}
+
+	#[test]
+	fn compile_with() {
+		let mut cache = RegexCache::new(10);
+		let     ci    = Options { case_insensitive: true, .. Default::default() };
+		let     small = Options { size_limit: 1 << 16, .. Default::default() };
+
+		assert!(cache.compile_with("abc", &ci).unwrap().is_match("ABC"));
+		assert!(!cache.compile("abc").unwrap().is_match("ABC"));
+		assert!(!cache.compile_with("abc", &small).unwrap().is_match("ABC"));
+		assert_eq!(cache.len(), 3);
+
+		assert!(cache.compile_with("abc", &ci).unwrap().is_match("ABC"));
+		assert_eq!(cache.stats().hits, 1);
+
+		let keys = cache.export_keys();
+		assert_eq!(keys.len(), 3);
+		assert!(keys.iter().all(|k| k.source == "abc"));
+		assert_eq!(keys[2].options, ci);
+
+		assert!(cache.contains("abc"));
+		cache.clear();
+		assert!(cache.compile_with("(", &ci).is_err());
+		assert!(cache.is_empty());
+	}
+
+	#[test]
+	fn export_import() {
+		let mut cache = RegexCache::new(10);
+		cache.compile("a+").unwrap();
+		cache.compile("b+").unwrap();
+		cache.compile("c+").unwrap();
+		cache.compile("a+").unwrap();
+
+		let snapshot = cache.snapshot();
+		assert_eq!(snapshot.keys.iter().map(|key| &key.source).collect::<Vec<_>>(), ["b+", "c+", "a+"]);
+
+		let mut restored = RegexCache::new(10);
+		restored.import_snapshot(snapshot.clone());
+		assert_eq!(restored.snapshot(), snapshot);
+	}
+
+	#[test]
+	fn compile_times() {
+		let mut cache = RegexCache::new(10);
+		assert_eq!(cache.stats().mean_compile_time(), None);
+
+		cache.compile(r"^\w+@\w+\.com$").unwrap();
+		cache.compile(r"^\d{4}-\d{2}-\d{2}$").unwrap();
+		cache.compile(r"^\d{4}-\d{2}-\d{2}$").unwrap();
+		assert!(cache.compile("(").is_err());
+
+		let times = cache.compile_times().collect::<Vec<_>>();
+		assert_eq!(times.len(), 2);
+		assert!(times.iter().all(|&(_, elapsed)| !elapsed.is_zero()));
+
+		let stats = cache.stats();
+		assert_eq!(stats.misses, 3);
+		assert!(stats.min_compile_time <= stats.mean_compile_time().unwrap());
+		assert!(stats.mean_compile_time().unwrap() <= stats.max_compile_time);
+		assert!(stats.total_compile_time >= times[0].1 + times[1].1);
+
+		// Hits don't change the timings.
+		cache.compile(r"^\w+@\w+\.com$").unwrap();
+		assert_eq!(cache.stats().total_compile_time, stats.total_compile_time);
+		assert_eq!(cache.compile_times().collect::<Vec<_>>().len(), 2);
+
+		// Inserting regexes that were already compiled doesn't either.
+		let stats = cache.stats();
+		cache.save(Regex::new("x").unwrap());
+		cache.get_or_insert_with("y", || Regex::new("y")).unwrap();
+		assert_eq!(cache.stats(), stats);
+		assert_eq!(cache.compile_times().collect::<Vec<_>>().len(), 2);
+
+		// Patterns compiled in parallel are timed where they're compiled.
+		cache.warm_parallel(vec![r"^\w{50}$".to_owned()]);
+		assert_eq!(cache.stats().misses, 4);
+		assert_eq!(cache.compile_times().collect::<Vec<_>>().len(), 3);
+	}
+
+	#[test]
+	fn shrink() {
+		let mut cache = RegexCache::new(100);
+		cache.warm(["a", "b", "c", "d"]);
+		cache.compile("a").unwrap();
+
+		cache.shrink_to(3);
+		assert_eq!(cache.export_patterns(), ["c", "d", "a"]);
+		assert_eq!(cache.entry_stats("a").unwrap().hits, 1);
+
+		cache.shrink_to_fit();
+		assert_eq!(cache.capacity(), 3);
+
+		cache.clear();
+		cache.shrink_to_fit();
+		assert_eq!(cache.capacity(), 1);
+
+		let mut unbounded = RegexCache::new(0);
+		unbounded.warm(["a", "b"]);
+		unbounded.shrink_to(5);
+		assert_eq!(unbounded.capacity(), 5);
+	}
+
+	#[test]
+	fn idle() {
+		let clock = MockClock::new();
+		let mut cache = RegexCache::new(10);
+		cache.set_clock(clock.clone());
+		cache.warm(["a", "b", "c"]);
+
+		clock.advance(Duration::from_secs(300));
+		cache.compile("b").unwrap();
+		clock.advance(Duration::from_secs(300));
+		cache.compile("d").unwrap();
+
+		let ten_minutes = Duration::from_secs(600);
+		assert_eq!(cache.idle_entries(ten_minutes), ["a", "c"]);
+		assert_eq!(cache.idle_entries(Duration::from_secs(300)), ["a", "c", "b"]);
+
+		assert_eq!(cache.evict_idle(ten_minutes), 2);
+		assert_eq!(cache.export_patterns(), ["b", "d"]);
+		assert_eq!(cache.stats().evictions, 0);
+		assert_eq!(cache.evict_idle(ten_minutes), 0);
+	}
+
+	#[test]
+	fn metadata() {
+		use crate::cache::{MetaRegexCache, EvictionReason};
+
+		let clock   = MockClock::new();
+		let evicted = Arc::new(Mutex::new(Vec::new()));
+		let sink    = evicted.clone();
+
+		let mut cache = MetaRegexCache::new(2);
+		cache.set_clock(clock.clone());
+		cache.set_on_evict(move |key, meta, reason| sink.lock().unwrap().push((key.source.clone(), meta, reason)));
+
+		cache.compile_with_meta("a", "rule-a").unwrap();
+		cache.compile_with_meta("b", "rule-b").unwrap();
+
+		// Promoting "a" keeps its metadata, so "b" is evicted with its own.
+		cache.compile("a").unwrap();
+		*cache.meta_mut("a").unwrap() = "rule-a2";
+		cache.compile("c").unwrap();
+
+		assert_eq!(*evicted.lock().unwrap(), [("b".to_owned(), Some("rule-b"), EvictionReason::Capacity)]);
+		assert_eq!(cache.get_meta("a"), Some(&"rule-a2"));
+		assert_eq!(cache.get_meta("b"), None);
+		assert_eq!(cache.get_meta("c"), None);
+
+		cache.recompile("a").unwrap();
+		assert_eq!(cache.get_meta("a"), Some(&"rule-a2"));
+
+		clock.advance(Duration::from_secs(60));
+		assert_eq!(cache.evict_idle(Duration::from_secs(60)), 2);
+		assert!(evicted.lock().unwrap().contains(&("a".to_owned(), Some("rule-a2"), EvictionReason::Idle)));
+		assert!(evicted.lock().unwrap().contains(&("c".to_owned(), None, EvictionReason::Idle)));
+		assert_eq!(evicted.lock().unwrap().len(), 3);
+
+		cache.compile_with_meta("d", "rule-d").unwrap();
+		cache.clear();
+		assert_eq!(evicted.lock().unwrap().len(), 3);
+		assert!(cache.compile_with_meta("(", "broken").is_err());
+		assert!(cache.is_empty());
+	}
+
+	#[test]
+	fn compile_or_default() {
+		let mut cache = RegexCache::new(10);
+		let     never = Regex::new(r"[^\s\S]").unwrap();
+
+		assert!(!cache.compile_or_default("(", never.clone()).is_match("("));
+		assert!(!cache.compile_or_default("(", Regex::new("").unwrap()).is_match("("));
+		assert!(cache.compile_or_default("a", never).is_match("a"));
+
+		let stats = cache.stats();
+		assert_eq!((stats.hits, stats.misses), (1, 2));
+		assert_eq!(cache.len(), 2);
+	}
+
+	#[test]
+	fn cached_regex_parity() {
+		let cache  = Arc::new(Mutex::new(RegexCache::new(10)));
+		let source = r"(?P<key>\w+)=(\d+)?";
+		let cached = CachedRegex::new(cache, source).unwrap();
+		let plain  = Regex::new(source).unwrap();
+		let text   = "a=1 b= c=3 d=4";
+
+		assert_eq!(cached.replacen(text, 2, "$key"), plain.replacen(text, 2, "$key"));
+		assert_eq!(cached.replacen(text, 0, "$2"), plain.replacen(text, 0, "$2"));
+		assert!(cached.splitn(text, 3).eq(plain.splitn(text, 3)));
+		assert_eq!(cached.shortest_match(text), plain.shortest_match(text));
+		assert_eq!(cached.captures_len(), plain.captures_len());
+		assert!(cached.capture_names().eq(plain.capture_names()));
+	}
+
+	#[test]
+	fn with() {
+		let mut cache = RegexCache::new(10);
+		let     text  = "2017-01-02 abc";
+
+		let date = cache.with(r"(\d+)-(\d+)-(\d+)", |re| re.captures(text).map(|c| c[1].to_owned())).unwrap();
+		let word = cache.with(r"[a-z]+", |re| re.find(text).map(|m| m.as_str())).unwrap();
+		let more = cache.with(r"(\d+)-(\d+)-(\d+)", |re| re.find_iter(text).count()).unwrap();
+
+		assert_eq!(date.as_deref(), Some("2017"));
+		assert_eq!(word, Some("abc"));
+		assert_eq!(more, 1);
+		assert_eq!(cache.stats().hits, 1);
+		assert!(cache.with("(", |_| ()).is_err());
+	}
+
+	#[test]
+	fn restore() {
+		let mut cache = RegexCache::new(4);
+		let     ci    = Options { case_insensitive: true, .. Default::default() };
+		cache.warm(["a", "b"]);
+		cache.compile_with("c", &ci).unwrap();
+		cache.compile("a").unwrap();
+
+		let snapshot = cache.snapshot();
+		let stats    = cache.stats();
+
+		cache.warm(["d", "e", "f", "g"]);
+		cache.compile("b").unwrap();
+		cache.resize(8);
+
+		cache.restore(snapshot.clone());
+		assert_eq!(cache.snapshot(), snapshot);
+		assert_eq!(cache.export_patterns(), ["b", "c", "a"]);
+		assert!(cache.compile_with("c", &ci).unwrap().is_match("C"));
+		assert_eq!(cache.stats().misses, stats.misses + 5);
+
+		let mut fresh = RegexCache::new(4);
+		fresh.restore(snapshot.clone());
+		assert_eq!(fresh.snapshot(), snapshot);
+	}
+
+	#[test]
+	fn restore_keeps_groups() {
+		let mut cache = RegexCache::new(8);
+		cache.compile_group("dates", &["a", "b"]).unwrap();
+		cache.compile_group("times", &["c"]).unwrap();
+		cache.compile("a").unwrap();
+		cache.insert_named("first", "a");
+
+		let snapshot = cache.snapshot();
+		let before   = cache.entry_stats("a").unwrap();
+
+		cache.evict_group("times");
+		cache.warm(["d", "e"]);
+		cache.restore(snapshot);
+
+		let after = cache.entry_stats("a").unwrap();
+		assert_eq!((after.hits, after.inserted, after.last_access), (before.hits, before.inserted, before.last_access));
+		assert!(cache.get_named("first").unwrap().is_match("a"));
+
+		cache.evict_group("dates");
+		assert_eq!(cache.export_patterns(), ["c"]);
+
+		// "c" was compiled again, outside of its group.
+		cache.evict_group("times");
+		assert_eq!(cache.export_patterns(), ["c"]);
+	}
+
+	#[test]
+	fn import_skips_invalid() {
+		let mut cache = RegexCache::new(10);
+		cache.import_patterns(vec!["a+".to_owned(), "(b".to_owned(), "c+".to_owned()]);
+
+		assert_eq!(cache.export_patterns(), ["a+", "c+"]);
+	}
+
+	#[test]
+	fn equality() {
+		use std::collections::hash_map::DefaultHasher;
+		use std::hash::{Hash, Hasher};
+
+		fn hash(cache: &RegexCache) -> u64 {
+			let mut hasher = DefaultHasher::new();
+			cache.hash(&mut hasher);
+			hasher.finish()
+		}
+
+		let mut a = RegexCache::new(10);
+		a.warm(["a+", "b+", "c+"]);
+
+		let mut b = RegexCache::new(10);
+		b.warm(["c+", "a+", "b+"]);
+
+		assert_eq!(a, b);
+		assert_eq!(hash(&a), hash(&b));
+
+		b.compile("d+").unwrap();
+		assert_ne!(a, b);
+
+		let mut c = RegexCache::new(20);
+		c.warm(["a+", "b+", "c+"]);
+		assert_ne!(a, c);
+	}
+
+	#[test]
+	fn get_or_insert_with() {
+		let mut cache  = RegexCache::new(10);
+		let mut calls  = 0;
+		let mut digits = || {
+			calls += 1;
+			Regex::new(r"^\d+$")
+		};
+
+		for _ in 0 .. 3 {
+			assert!(cache.get_or_insert_with("digits", &mut digits).unwrap()
+				.is_match("123"));
+		}
+
+		assert_eq!(calls, 1);
+
+		let broken = String::from("(");
+		assert!(cache.get_or_insert_with("broken", || Regex::new(&broken)).is_err());
+		assert!(!cache.iter().any(|(k, _)| k.source == "broken"));
+		assert!(cache.get_or_insert_with("broken", || Regex::new("a")).is_ok());
+	}
+
+	#[test]
+	fn stats() {
+		let mut cache = RegexCache::new(2);
+		cache.compile("a").unwrap();
+		cache.compile("a").unwrap();
+		cache.compile("b").unwrap();
+		cache.compile("c").unwrap();
+
+		let stats = cache.stats();
+		assert_eq!((stats.hits, stats.misses, stats.evictions), (1, 3, 1));
+
+		cache.reset_stats();
+		assert_eq!(cache.stats(), Default::default());
+	}
+
+	#[test]
+	fn composite_key() {
+		let mut cache = RegexCache::new(10);
+		let     flags = RegexFlags::default() | RegexFlags::CASE_INSENSITIVE | RegexFlags::IGNORE_WHITESPACE;
+		let     key   = RegexCacheKey::new("a b c # comment", flags);
+
+		assert!(cache.get_or_compile_with_key(&key).unwrap().is_match("ABC"));
+		assert!(!cache.compile("a b c # comment").unwrap().is_match("ABC"));
+		assert!(cache.get_or_compile_with_key(&key).unwrap().is_match("abc"));
+
+		let stats = cache.stats();
+		assert_eq!((stats.hits, stats.misses), (1, 2));
+
+		let key = RegexCacheKey::new(r"\w", RegexFlags::empty());
+		assert!(!cache.get_or_compile_with_key(&key).unwrap().is_match("é"));
+	}
+
+	#[test]
+	fn recompile() {
+		let mut cache = RegexCache::new(2);
+		cache.configure("abc", |b| b.case_insensitive(true)).unwrap();
+		cache.compile("def").unwrap();
+
+		assert!(cache.compile("abc").unwrap().is_match("ABC"));
+		assert!(!cache.recompile("abc").unwrap().is_match("ABC"));
+		assert!(!cache.compile("abc").unwrap().is_match("ABC"));
+		assert_eq!(cache.len(), 2);
+
+		let broken = String::from("(");
+		assert!(cache.recompile(&broken).is_err());
+		assert_eq!(cache.export_patterns(), ["def", "abc"]);
+	}
+
+	#[test]
+	fn owned() {
+		let mut cache = RegexCache::new(2);
+
+		let a = cache.compile_owned("^a+$").unwrap();
+		let b = cache.configure_owned("^b+$", |b| b.case_insensitive(true)).unwrap();
+		let c = cache.compile_owned("^c+$").unwrap();
+
+		assert!(!cache.iter().any(|(k, _)| k.source == "^a+$"));
+		assert!(a.is_match("aaa"));
+		assert!(b.is_match("BBB"));
+		assert!(c.is_match("ccc"));
+
+		cache.clear();
+		assert!(c.is_match("ccc"));
+	}
+
+	#[test]
+	fn custom_policy() {
+		use crate::cache::GenericRegexCache;
+		use crate::policy::EvictionPolicy;
+
+		#[derive(Debug)]
+		struct Fifo(Vec<(RegexCacheKey, Regex)>, usize, usize);
+
+		impl EvictionPolicy for Fifo {
+			fn with_capacity(capacity: usize) -> Self {
+				Fifo(Vec::new(), capacity, 0)
+			}
+
+			fn insert(&mut self, key: RegexCacheKey, re: Regex) {
+				self.remove(&key);
+				self.0.push((key, re));
+				self.2 = self.0.len() - 1;
+			}
+
+			fn get(&mut self, key: &RegexCacheKey) -> Option<&Regex> {
+				let index = self.0.iter().position(|(k, _)| k == key)?;
+				self.2 = index;
+				Some(&self.0[index].1)
+			}
+
+			fn peek(&self, key: &RegexCacheKey) -> Option<&Regex> {
+				self.0.iter().find(|(k, _)| k == key).map(|(_, re)| re)
+			}
+
+			fn most_recent(&self) -> Option<&Regex> {
+				self.0.get(self.2).map(|(_, re)| re)
+			}
+
+			fn remove(&mut self, key: &RegexCacheKey) -> Option<Regex> {
+				let index = self.0.iter().position(|(k, _)| k == key)?;
+				Some(self.0.remove(index).1)
+			}
+
+			fn len(&self) -> usize {
+				self.0.len()
+			}
+
+			fn capacity(&self) -> usize {
+				self.1
+			}
+
+			fn set_capacity(&mut self, capacity: usize) {
+				self.1 = capacity;
+			}
+
+			fn clear(&mut self) {
+				self.0.clear();
+			}
+
+			fn entries(&self) -> Box<dyn Iterator<Item = (&RegexCacheKey, &Regex)> + '_> {
+				Box::new(self.0.iter().map(|(k, re)| (k, re)))
+			}
+		}
+
+		let mut cache = GenericRegexCache::<Fifo>::new(2);
+		cache.compile("a").unwrap();
+		cache.compile("b").unwrap();
+		assert!(cache.compile("a").unwrap().is_match("a"));
+		cache.compile("c").unwrap();
+
+		assert_eq!(cache.export_patterns(), ["b", "c"]);
+	}
+
+	#[test]
+	fn find() {
+		let mut cache = RegexCache::new(10);
+
+		assert_eq!(cache.find(r"\d+", "abc").unwrap(), None);
+		assert_eq!(cache.find_all(r"\d+", "abc").unwrap(), []);
+
+		assert_eq!(cache.find(r"\d+", "a1b22").unwrap(), Some((1, 2)));
+		assert_eq!(cache.find_all(r"\d+", "a1b22").unwrap(), [(1, 2), (3, 5)]);
+
+		assert_eq!(cache.find("a*", "bbb").unwrap(), Some((0, 0)));
+		assert_eq!(cache.find_all("a*", "bbb").unwrap(), [(0, 0), (1, 1), (2, 2), (3, 3)]);
+
+		assert!(cache.find_all(&String::from("("), "abc").is_err());
+	}
+
+	#[test]
+	fn captures() {
+		let mut cache = RegexCache::new(10);
+		let     re    = r"(?P<first>\w+)(?: (\w+))?(?: (?P<last>\d+))?";
+
+		assert!(cache.captures(re, "!!").unwrap().is_none());
+
+		let caps = cache.captures(re, "héllo wörld").unwrap().unwrap();
+		assert_eq!(caps.len(), 4);
+		assert_eq!(caps.get(0).unwrap().range(), 0 .. 13);
+		assert_eq!(caps.name("first").unwrap().as_str(), "héllo");
+		assert_eq!(caps.get(2).unwrap().range(), 7 .. 13);
+		assert_eq!(caps.get(2).unwrap().as_str(), "wörld");
+		assert!(caps.name("last").is_none());
+		assert!(caps.name("missing").is_none());
+		assert!(caps.get(4).is_none());
+
+		let groups = caps.iter().map(|m| m.map(|m| m.as_str())).collect::<Vec<_>>();
+		assert_eq!(groups, [Some("héllo wörld"), Some("héllo"), Some("wörld"), None]);
+	}
+
+	#[test]
+	fn replace() {
+		let mut cache = RegexCache::new(10);
+
+		assert_eq!(cache.replace_all(r"\d", "abc", "#").unwrap(), "abc");
+		assert_eq!(cache.replace(r"\d", "a1b2", "#").unwrap(), "a#b2");
+		assert_eq!(cache.replacen(r"\d", "1234", 2, "#").unwrap(), "##34");
+
+		assert_eq!(cache.replace_all(r"(?P<k>\w+)=(?P<v>\w+)", "a=1 b=2", "$v=$k").unwrap(),
+			"1=a 2=b");
+
+		assert_eq!(cache.replace_all(r"\d+", "1 22", |caps: &regex::Captures| {
+			caps[0].len().to_string()
+		}).unwrap(), "1 2");
+
+		assert!(cache.replace_all(&String::from("("), "abc", "").is_err());
+	}
+
+	#[test]
+	fn par_compile_all() {
+		let patterns = (0 .. 50).map(|i| format!("^{}$", i)).collect::<Vec<_>>();
+		let mut patterns = patterns.iter().map(String::as_str).collect::<Vec<_>>();
+		patterns.insert(10, "(");
+
+		let (mut cache, errors) = RegexCache::par_compile_all(&patterns);
+		assert_eq!(cache.len(), 50);
+		assert_eq!(errors.len(), 1);
+		assert_eq!(errors[0].0, "(");
+		assert!(cache.compile("^42$").unwrap().is_match("42"));
+
+		let (cache, errors) = RegexCache::par_compile_all(&[]);
+		assert!(cache.is_empty() && errors.is_empty());
+		assert_eq!(cache.capacity(), 1);
+	}
+
+	#[test]
+	fn split() {
+		let mut cache = RegexCache::new(10);
+
+		assert_eq!(cache.split(",", ",a,,b,").unwrap(), ["", "a", "", "b", ""]);
+		assert_eq!(cache.split(",", "").unwrap(), [""]);
+		assert_eq!(cache.split("", "abc").unwrap(), ["", "a", "b", "c", ""]);
+		assert_eq!(cache.splitn(",", "a,b,c", 2).unwrap(), ["a", "b,c"]);
+		assert_eq!(cache.splitn(",", "a,b,c", 0).unwrap(), Vec::<String>::new());
+
+		let shared = Arc::new(Mutex::new(cache));
+		let re     = CachedRegex::new(shared, ",").unwrap();
+		assert_eq!(re.split(",a,,b,").collect::<Vec<_>>(), ["", "a", "", "b", ""]);
+	}
+
+	#[test]
+	fn default_capacity() {
+		use crate::cache::DEFAULT_CAPACITY;
+
+		assert_eq!(RegexCache::default().capacity(), DEFAULT_CAPACITY);
+		assert_eq!(RegexCache::with_default_capacity(), RegexCache::default());
+
+		let mut cache = RegexCache::new(0);
+		for i in 0 .. DEFAULT_CAPACITY * 2 {
+			cache.compile(&format!("^{}$", i)).unwrap();
+		}
+
+		assert_eq!(cache.len(), DEFAULT_CAPACITY * 2);
+
+		cache.resize(0);
+		assert_eq!(cache.len(), DEFAULT_CAPACITY * 2);
+		assert!(cache.compile("^a$").unwrap().is_match("a"));
+	}
+
+	#[test]
+	fn builder() {
+		use crate::cache::DEFAULT_CAPACITY;
+
+		let cache = RegexCacheBuilder::new().build();
+		assert_eq!(cache.capacity(), DEFAULT_CAPACITY);
+		assert_eq!((cache.ttl(), cache.memory_budget()), (None, None));
+
+		let mut cache = RegexCacheBuilder::new().capacity(2).memory_budget(4096).build();
+		cache.warm(["a", "b", "c"]);
+		assert_eq!(cache.export_patterns(), ["b", "c"]);
+		cache.compile(r"\w{5}").unwrap();
+		assert_eq!(cache.len(), 1);
+
+		let clock = MockClock::new();
+		let mut cache = RegexCacheBuilder::new()
+			.ttl(Duration::from_secs(60))
+			.clock(clock.clone())
+			.build();
+
+		cache.compile("a").unwrap();
+		clock.advance(Duration::from_secs(60));
+		cache.purge_expired();
+		assert!(cache.is_empty());
+
+		let shared = RegexCacheBuilder::new().capacity(10).build_shared();
+		assert!(shared.compile("a").is_ok());
+		assert_eq!(shared.capacity(), 10);
+
+		let mut cache = RegexCacheBuilder::new().cache_errors(true).build();
+		assert!(cache.caches_errors());
+		assert!(cache.compile("(").is_err());
+		assert!(cache.compile("(").is_err());
+		assert_eq!(cache.stats().misses, 1);
+		assert!(!RegexCacheBuilder::new().build().caches_errors());
+	}
+
+	#[test]
+	fn cache_errors() {
+		use crate::guard::PatternLimits;
+
+		let mut cache = RegexCache::new(2);
+		cache.set_cache_errors(true);
+
+		let error = cache.compile("(").unwrap_err();
+		assert_eq!(cache.compile("(").unwrap_err(), error);
+		assert!(cache.compile_ci("(").is_err());
+		assert_eq!((cache.stats().hits, cache.stats().misses), (1, 2));
+
+		// Errors don't take room from the compiled entries.
+		cache.compile("a").unwrap();
+		cache.compile("b").unwrap();
+		assert_eq!(cache.len(), 2);
+
+		// At most as many errors as the capacity are kept, so ")" pushes out
+		// the oldest one.
+		assert!(cache.compile(")").is_err());
+		assert!(cache.compile(")").is_err());
+		assert!(cache.compile("(").is_err());
+		assert_eq!(cache.stats().misses, 6);
+
+		// The fallback replaces the error.
+		let never = Regex::new(r"[^\s\S]").unwrap();
+		cache.compile_or_default(")", never.clone());
+		assert_eq!(cache.compile(")").unwrap().as_str(), never.as_str());
+
+		// Changing the limits forgets the errors they caused.
+		cache.set_pattern_limits(PatternLimits { max_len: Some(1), .. Default::default() });
+		assert!(cache.compile("ab").is_err());
+		cache.set_pattern_limits(PatternLimits::default());
+		assert!(cache.compile("ab").is_ok());
+
+		cache.clear();
+		assert!(cache.compile("(").is_err());
+		assert_eq!(cache.stats().misses, 10);
+
+		cache.set_cache_errors(false);
+		assert!(cache.compile("(").is_err());
+		assert_eq!(cache.stats().misses, 11);
 	}
 
-	/// Set the value for the ignore whitespace (`x`) flag.
-	pub fn ignore_whitespace(&mut self, yes: bool) -> &mut CachedRegexBuilder {
-		self.options.ignore_whitespace = yes;
-		self
+	#[test]
+	fn accessors() {
+		let mut cache = RegexCache::new(2);
+		assert!(cache.is_empty());
+		assert_eq!(cache.capacity(), 2);
+
+		cache.warm(["a", "b"]);
+		assert_eq!(cache.len(), 2);
+		assert!(!cache.is_empty());
+
+		let before = cache.stats();
+		assert!(cache.contains("a"));
+		assert!(!cache.contains("c"));
+		assert_eq!(cache.stats(), before);
+
+		let keys = cache.iter().map(|(k, re)| {
+			assert_eq!(k.source, re.as_str());
+			k.source.as_str()
+		}).collect::<Vec<_>>();
+		assert_eq!(keys, ["a", "b"]);
+
+		cache.compile("c").unwrap();
+		assert_eq!(cache.export_patterns(), ["b", "c"]);
+
+		cache.clear();
+		assert!(cache.is_empty());
+		assert!(!cache.contains("c"));
 	}
 
-	/// Set the value for the Unicode (`u`) flag.
-	pub fn unicode(&mut self, yes: bool) -> &mut CachedRegexBuilder {
-		self.options.unicode = yes;
-		self
+	#[test]
+	fn contains_expired() {
+		let clock = MockClock::new();
+		let mut cache = RegexCache::new(10);
+		cache.set_clock(clock.clone());
+		cache.set_ttl(Duration::from_secs(60));
+
+		cache.compile("a").unwrap();
+		assert!(cache.contains("a"));
+
+		clock.advance(Duration::from_secs(60));
+		assert!(!cache.contains("a"));
 	}
 
-	/// Set the approximate size limit of the compiled regular expression.
-	///
-	/// This roughly corresponds to the number of bytes occupied by a single
-	/// compiled program. If the program exceeds this number, then a
-	/// compilation error is returned.
-	pub fn size_limit(&mut self, limit: usize) -> &mut CachedRegexBuilder {
-		self.options.size_limit = limit;
-		self
+	#[test]
+	fn groups() {
+		let mut cache = RegexCache::new(3);
+
+		let errors = cache.compile_group("a", &["a1", "(", "a2"]).unwrap_err();
+		assert_eq!(errors.len(), 1);
+		cache.compile_group("b", &["b1", "a2"]).unwrap();
+
+		cache.evict_group("a");
+		assert_eq!(cache.export_patterns(), ["b1"]);
+
+		cache.evict_group("a");
+		cache.evict_group("missing");
+		assert_eq!(cache.len(), 1);
+
+		cache.warm(["c1", "c2", "c3"]);
+		cache.compile_group("b", &["b2"]).unwrap();
+		cache.evict_group("b");
+		assert_eq!(cache.export_patterns(), ["c2", "c3"]);
 	}
 
-	/// Set the approximate size of the cache used by the DFA.
-	///
-	/// This roughly corresponds to the number of bytes that the DFA will
-	/// use while searching.
-	///
-	/// Note that this is a *per thread* limit. There is no way to set a global
-	/// limit. In particular, if a regex is used from multiple threads
-	/// simulanteously, then each thread may use up to the number of bytes
-	/// specified here.
-	pub fn dfa_size_limit(&mut self, limit: usize) -> &mut CachedRegexBuilder {
-		self.options.dfa_size_limit = limit;
-		self
+	#[test]
+	fn memory_usage() {
+		let mut cache = RegexCache::new(10);
+		assert_eq!(cache.memory_usage(), 0);
+
+		cache.compile("a").unwrap();
+		let one = cache.memory_usage();
+		assert!(one > 0);
+
+		cache.compile(r"[a-z]{10}").unwrap();
+		let two = cache.memory_usage();
+		assert!(two > one);
+
+		assert_eq!(cache.entry_sizes().map(|(_, size)| size).sum::<usize>(), two);
+		assert!(cache.entry_sizes().all(|(_, size)| size > 0));
+
+		cache.resize(1);
+		assert!(cache.memory_usage() < two);
+
+		cache.clear();
+		assert_eq!(cache.memory_usage(), 0);
 	}
-}
 
-#[cfg(test)]
-mod test {
-	use std::sync::{Arc, Mutex};
-	use crate::cache::{RegexCache, CachedRegex};
+	#[test]
+	fn size_limit() {
+		let mut cache = RegexCache::with_size_limit(10 * 1024);
+		let     huge  = r"[a-z]{2000}";
+
+		assert!(cache.compile(huge).is_err());
+		assert!(cache.configure(huge, |b| b.case_insensitive(true)).is_err());
+		assert!(cache.compile_with_hint(huge, 1).is_err());
+		assert!(cache.recompile(huge).is_err());
+		assert!(cache.is_empty());
+
+		assert!(cache.compile("a+").unwrap().is_match("aaa"));
+		assert!(cache.contains("a+"));
+		assert_eq!(cache.size_limit(), 10 * 1024);
+
+		assert!(RegexCache::new(10).compile(huge).is_ok());
+
+		let mut cache = RegexCacheBuilder::new().size_limit(10 * 1024).build();
+		assert!(cache.compile(huge).is_err());
+	}
 
 	#[test]
-	fn respects_limit() {
+	fn warm_parallel() {
+		let patterns = (0 .. 50).map(|i| format!("^{}+$", i))
+			.chain(vec!["(".to_owned(), "^1+$".to_owned()])
+			.collect::<Vec<_>>();
+
+		let mut serial   = RegexCache::new(20);
+		let mut parallel = RegexCache::new(20);
+
+		let expected = serial.warm(&patterns);
+		let report   = parallel.warm_parallel(patterns.clone());
+
+		assert_eq!(report.len(), expected.len());
+		for ((a, x), (b, y)) in report.iter().zip(&expected) {
+			assert_eq!(a, b);
+			assert_eq!(x.is_ok(), y.is_ok());
+		}
+
+		assert_eq!(parallel.export_patterns(), serial.export_patterns());
+		let (x, y) = (parallel.stats(), serial.stats());
+		assert_eq!((x.hits, x.misses, x.evictions), (y.hits, y.misses, y.evictions));
+	}
+
+	#[test]
+	fn merge() {
+		use crate::cache::Collision;
+
+		let mut global = RegexCache::new(3);
+		global.configure("a", |b| b.case_insensitive(true)).unwrap();
+		global.compile("b").unwrap();
+
+		let mut tenant = RegexCache::new(10);
+		tenant.warm(["c", "a", "d"]);
+		tenant.compile_with_hint("e", 5).unwrap();
+
+		let mut keep = global.clone();
+		keep.merge_from(&tenant);
+		assert_eq!(keep.export_patterns(), ["a", "d", "e"]);
+		assert_eq!((keep.stats().hits, keep.stats().misses, keep.stats().evictions), (0, 2, 2));
+		assert!(keep.compile("a").unwrap().is_match("A"));
+
+		let mut prefer = global.clone();
+		prefer.merge(&tenant, Collision::PreferOther);
+		assert!(!prefer.compile("a").unwrap().is_match("A"));
+
+		let mut small = RegexCache::new(1);
+		small.extend(tenant);
+		assert_eq!(small.export_patterns(), ["e"]);
+		assert_eq!(small.stats().evictions, 3);
+	}
+
+	#[test]
+	fn clone() {
+		let mut cache = RegexCache::new(3);
+		cache.set_ttl(Duration::from_secs(60));
+		cache.warm(["a", "b", "c"]);
+
+		let mut clone = cache.clone();
+		assert_eq!(clone.export_patterns(), cache.export_patterns());
+		assert_eq!(clone.stats(), cache.stats());
+		assert_eq!(clone.ttl(), cache.ttl());
+
+		clone.warm(["d", "a"]);
+		clone.reset_stats();
+		assert_eq!(clone.export_patterns(), ["c", "d", "a"]);
+
+		assert_eq!(cache.export_patterns(), ["a", "b", "c"]);
+		assert_eq!(cache.stats().misses, 3);
+	}
+
+	#[test]
+	fn debug() {
+		let mut cache = RegexCache::new(20);
+		cache.warm((0 .. 10).map(|i| format!("p{}", i)));
+		cache.compile("p3").unwrap();
+
+		let output = format!("{:?}", cache);
+		assert!(output.starts_with("RegexCache { capacity: 20, len: 10, stats: CacheStats { hits: 1, misses: 10,"));
+		assert!(output.ends_with(r#"recent: ["p3", "p9", "p8", "p7", "p6", "p5", "p4", "p2", ...] }"#));
+	}
+
+	#[test]
+	fn entry_stats() {
+		let clock     = MockClock::new();
+		let mut cache = RegexCache::new(3);
+		cache.set_clock(clock.clone());
+
+		cache.compile("a").unwrap();
+		clock.advance(Duration::from_secs(1));
+		cache.warm(["b", "c", "b", "a", "b"]);
+		clock.advance(Duration::from_secs(1));
+		cache.compile("c").unwrap();
+
+		let stats = cache.entry_stats("b").unwrap();
+		assert_eq!(stats.hits, 2);
+		assert_eq!(stats.last_access, stats.inserted);
+
+		let stats = cache.entry_stats("a").unwrap();
+		assert_eq!(stats.hits, 1);
+		assert_eq!(stats.last_access - stats.inserted, Duration::from_secs(1));
+
+		assert_eq!(cache.most_used(2), [("b", 2), ("c", 1)]);
+		assert_eq!(cache.most_used(10).len(), 3);
+
+		// Evicting resets the counters.
+		cache.compile("d").unwrap();
+		assert!(cache.entry_stats("a").is_none());
+		cache.compile("a").unwrap();
+		assert_eq!(cache.entry_stats("a").unwrap().hits, 0);
+		assert_eq!(cache.most_used(1), [("c", 1)]);
+	}
+
+	#[test]
+	fn most_used_skips_expired() {
+		let clock = MockClock::new();
+		let mut cache = RegexCache::new(10);
+		cache.set_clock(clock.clone());
+		cache.set_ttl(Duration::from_secs(60));
+
+		cache.warm(["a", "a", "a"]);
+		clock.advance(Duration::from_secs(30));
+		cache.warm(["b", "b"]);
+		assert_eq!(cache.most_used(2), [("a", 2), ("b", 1)]);
+
+		clock.advance(Duration::from_secs(30));
+		assert!(!cache.contains("a"));
+		assert_eq!(cache.most_used(2), [("b", 1)]);
+	}
+
+	#[test]
+	fn with_hasher() {
+		use std::hash::{BuildHasherDefault, Hasher};
+		use crate::cache::GenericRegexCache;
+
+		#[derive(Default)]
+		struct Fnv(u64);
+
+		impl Hasher for Fnv {
+			fn finish(&self) -> u64 {
+				self.0
+			}
+
+			fn write(&mut self, bytes: &[u8]) {
+				for &byte in bytes {
+					self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x100000001b3);
+				}
+			}
+		}
+
+		let mut cache = GenericRegexCache::with_hasher(2, BuildHasherDefault::<Fnv>::default());
+		cache.warm(["a", "b", "a", "c"]);
+
+		assert_eq!(cache.export_patterns(), ["a", "c"]);
+		assert_eq!(cache.entry_stats("a").unwrap().hits, 1);
+		assert_eq!(cache.capacity(), 2);
+
+		cache.set_cache_errors(true);
+		cache.compile_group("letters", &["d"]).unwrap();
+		cache.insert_named("fourth", "d");
+		assert!(cache.compile("(").is_err());
+		assert!(cache.compile("(").is_err());
+		assert!(cache.get_named("fourth").unwrap().is_match("d"));
+
+		cache.evict_group("letters");
+		assert_eq!(cache.export_patterns(), ["c"]);
+		assert_eq!(cache.stats().misses, 5);
+	}
+
+	#[test]
+	fn try_new() {
+		use crate::cache::CacheError;
+
+		assert_eq!(RegexCache::try_new(0).unwrap_err(), CacheError::ZeroCapacity);
+		assert_eq!(RegexCacheBuilder::new().capacity(0).try_build().unwrap_err(), CacheError::ZeroCapacity);
+
+		let mut one = RegexCache::try_new(1).unwrap();
+		one.warm(["a", "b"]);
+		assert_eq!(one.export_patterns(), ["b"]);
+
+		let mut huge = RegexCache::try_new(usize::MAX).unwrap();
+		assert_eq!(huge.capacity(), usize::MAX);
+		huge.warm(["a", "b"]);
+		assert_eq!(huge.len(), 2);
+
+		assert_eq!(RegexCacheBuilder::new().capacity(5).try_build().unwrap().capacity(), 5);
+	}
+
+	#[test]
+	fn contains_does_not_promote() {
 		let mut cache = RegexCache::new(2);
+		cache.warm(["a", "b"]);
 
-		cache.compile("[01]2").unwrap();
-		cache.compile("[21]0").unwrap();
+		let cache_ref = &cache;
+		assert!(cache_ref.contains("a"));
 
+		cache.compile("c").unwrap();
+		assert!(!cache.contains("a"));
+		assert_eq!(cache.export_patterns(), ["b", "c"]);
+		assert_eq!(cache.entry_stats("b").unwrap().hits, 0);
+	}
+
+	#[test]
+	fn peek() {
+		let clock = MockClock::new();
+		let mut cache = RegexCache::new(2);
+		cache.set_clock(clock.clone());
+		cache.set_ttl(Duration::from_secs(60));
+		cache.warm(["a", "b"]);
+
+		let cache_ref = &cache;
+		assert!(cache_ref.peek("a").unwrap().is_match("a"));
+		assert!(cache_ref.peek("c").is_none());
+		assert_eq!(cache.stats().hits, 0);
+
+		cache.compile("c").unwrap();
+		assert!(cache.peek("a").is_none());
+
+		clock.advance(Duration::from_secs(60));
+		assert!(cache.peek("b").is_none());
+	}
+
+	#[test]
+	fn named() {
+		let mut cache = RegexCache::new(2);
+		cache.insert_named("ipv4", r"^\d+\.\d+\.\d+\.\d+$");
+		cache.insert_named("address", r"^\d+\.\d+\.\d+\.\d+$");
+
+		assert!(cache.compile_named("ipv4").unwrap().unwrap().is_match("10.0.0.1"));
+		assert!(cache.get_named("address").unwrap().is_match("10.0.0.1"));
+		assert_eq!(cache.len(), 1);
+		assert_eq!((cache.stats().hits, cache.stats().misses), (1, 1));
+
+		// Pointing a name elsewhere leaves the other alias alone.
+		assert_eq!(cache.insert_named("address", r"^[a-z]+$").as_deref(), Some(r"^\d+\.\d+\.\d+\.\d+$"));
+		assert!(cache.get_named("address").is_none());
+		assert!(cache.compile_named("address").unwrap().unwrap().is_match("host"));
+		assert!(cache.get_named("ipv4").unwrap().is_match("10.0.0.1"));
 		assert_eq!(cache.len(), 2);
-		cache.compile("[21]3").unwrap();
-		assert_eq!(cache.len(), 2);
+
+		cache.insert_named("broken", "(");
+		assert!(cache.compile_named("broken").unwrap().is_err());
+		assert!(cache.compile_named("missing").is_none());
+
+		assert_eq!(cache.remove_named("ipv4").as_deref(), Some(r"^\d+\.\d+\.\d+\.\d+$"));
+		assert!(cache.get_named("ipv4").is_none());
+		assert_eq!(cache.named_source("address"), Some(r"^[a-z]+$"));
 	}
 
 	#[test]
-	fn cached_regex() {
+	fn compile_many() {
+		let mut cache   = RegexCache::new(2);
+		let     results = cache.compile_many(vec!["a", "(", "b", "c", "a", "(", "c"]);
+
+		assert_eq!(results.len(), 7);
+		assert_eq!(results.iter().map(Result::is_ok).collect::<Vec<_>>(),
+			[true, false, true, true, true, false, true]);
+
+		// "a" was evicted by "c" but isn't compiled again.
+		assert_eq!(cache.stats().misses, 4);
+		assert_eq!(cache.export_patterns(), ["a", "c"]);
+
+		let owned = cache.compile_many_owned(vec!["c", "d"]);
+		assert_eq!(owned[0].as_ref().unwrap().as_str(), "c");
+		assert!(owned[1].as_ref().unwrap().is_match("d"));
+	}
+
+	#[test]
+	fn pattern_limits() {
+		use crate::guard::PatternLimits;
+
+		let limits = PatternLimits {
+			max_len:    Some(64),
+			nest_limit: Some(3),
+			blocked:    vec![r"\p".to_owned()],
+		};
+
+		let mut cache = RegexCacheBuilder::new().pattern_limits(limits.clone()).build();
+		let     deep  = "((((a))))";
+
+		assert!(cache.compile(&"a".repeat(65)).is_err());
+		assert!(cache.configure(r"\p{Greek}", |b| b).is_err());
+		assert!(cache.compile_with_hint(deep, 1).is_err());
+		assert!(cache.recompile(deep).is_err());
+		assert!(cache.warm_parallel(vec![deep.to_owned()])[0].1.is_err());
+		assert!(cache.is_empty());
+
+		assert!(cache.compile("(a)").is_ok());
+		assert_eq!(cache.pattern_limits(), Some(&limits));
+
+		match cache.compile(deep) {
+			Err(regex::Error::Syntax(message)) =>
+				assert_eq!(message, "pattern is nested deeper than 3"),
+
+			_ =>
+				panic!("expected a syntax error"),
+		}
+	}
+
+	#[test]
+	fn pop_lru() {
+		let mut cache = RegexCache::new(10);
+		assert!(cache.peek_lru().is_none());
+		assert!(cache.pop_lru().is_none());
+
+		cache.compile_with_hint("pinned", 5).unwrap();
+		cache.warm(["a", "b", "a"]);
+
+		assert_eq!(cache.peek_lru().unwrap().0.source, "b");
+		assert_eq!(cache.peek_lru().unwrap().0.source, "b");
+
+		let order = std::iter::from_fn(|| cache.pop_lru())
+			.map(|(key, _)| key.source)
+			.collect::<Vec<_>>();
+
+		assert_eq!(order, ["b", "a", "pinned"]);
+		assert!(cache.is_empty());
+		assert_eq!(cache.memory_usage(), 0);
+		assert_eq!(cache.stats().evictions, 0);
+	}
+
+	#[test]
+	fn compile_flags() {
+		let mut cache = RegexCache::with_size_limit(1 << 20);
+
+		assert!(cache.compile_ci("abc").unwrap().is_match("ABC"));
+		assert!(cache.compile_flags("abc", "i").unwrap().is_match("xAbC"));
+		assert!(cache.compile_flags("^a.b$", "sm").unwrap().is_match("x\na\nb"));
+		assert!(!cache.compile("abc").unwrap().is_match("ABC"));
+		assert_eq!(cache.len(), 3);
+		assert_eq!(cache.stats().hits, 1);
+
+		assert!(cache.iter().all(|(key, _)| key.options.size_limit == 1 << 20));
+		assert!(matches!(cache.compile_flags("abc", "iq"), Err(Error::Syntax(_))));
+		assert_eq!(cache.len(), 3);
+	}
+
+	#[test]
+	fn owned_iter() {
 		let cache = Arc::new(Mutex::new(RegexCache::new(100)));
-		let re = CachedRegex::new(cache.clone(), r"^\d+$").unwrap();
+		let re    = CachedRegex::new(cache.clone(), r"(?P<key>\w+)=(\d+)").unwrap();
+		let text  = "a=1, b=22, c=x, d=333";
 
-		assert!(re.is_match("123"));
-		assert!(!re.is_match("abc"));
+		let found = re.find_iter_owned(text);
+		assert_eq!(found.iter().map(|m| m.as_str()).collect::<Vec<_>>(), ["a=1", "b=22", "d=333"]);
+
+		let caps = re.captures_iter_owned(text);
+		assert_eq!(caps.len(), 3);
+		assert_eq!(caps[1].name("key").unwrap().as_str(), "b");
+		assert_eq!(caps[2].get(2).unwrap().range(), 18 .. 21);
+
+		// The cache is free while the results are used, so another thread can
+		// go through it in the meantime.
+		for m in &found {
+			let cache  = cache.clone();
+			let source = format!("^{}$", regex::escape(m.as_str()));
+			let text   = m.as_str().to_owned();
+
+			assert!(std::thread::spawn(move || {
+				cache.lock().unwrap().is_match(&source, &text).unwrap()
+			}).join().unwrap());
+		}
+
+		assert_eq!(cache.lock().unwrap().len(), 4);
+	}
+
+	#[test]
+	fn cached_regex_options() {
+		let cache   = Arc::new(Mutex::new(RegexCache::new(100)));
+		let large   = CachedRegexBuilder::new(cache.clone(), r"\w{10}").size_limit(1 << 20).build().unwrap();
+		let limited = CachedRegexBuilder::new(cache.clone(), r"\w{10}").size_limit(100).clone();
+
+		// The limited one doesn't get the entry cached for the other limit,
+		// and reports the error instead of panicking at the first match.
+		assert!(matches!(limited.build(), Err(Error::CompiledTooBig(100))));
+		assert!(limited.build_unchecked().compiled().is_err());
+		assert!(large.is_match("abcdefghij"));
+		assert_eq!(cache.lock().unwrap().len(), 1);
+
+		let crlf = CachedRegexBuilder::new(cache.clone(), "^b$")
+			.multi_line(true)
+			.line_terminator(b'\r')
+			.build().unwrap();
+		assert!(crlf.is_match("a\rb\rc"));
+
+		assert!(CachedRegexBuilder::new(cache.clone(), "((a))").nest_limit(1).build().is_err());
+		assert!(CachedRegexBuilder::new(cache, "((a))").nest_limit(2).build().is_ok());
+	}
+
+	#[test]
+	fn from_str() {
+		fn parse<T: std::str::FromStr>(values: &[&str]) -> Result<Vec<T>, T::Err> {
+			values.iter().map(|value| value.parse()).collect()
+		}
+
+		let res = parse::<CachedRegex>(&[r"^\d+$", r"^[a-z]+$"]).unwrap();
+		assert!(res[0].is_match("1234") && res[1].is_match("abc"));
+		assert!(parse::<CachedRegex>(&["a", "("]).is_err());
+
+		assert!(CachedRegex::default_cache().lock().unwrap().contains(r"^\d+$"));
+		assert!("(?-u)\\xFF".parse::<crate::bytes::CachedRegex>().unwrap().is_match(b"\xFF"));
+	}
+
+	#[test]
+	fn get_or_insert_default() {
+		let mut cache = RegexCache::new(100);
+		assert!(cache.get_or_insert_default("a+").is_match("aa"));
+		assert!(cache.get_or_insert_default("a+").is_match("a"));
+		assert_eq!((cache.stats().hits, cache.stats().misses), (1, 1));
+	}
+
+	#[test]
+	#[should_panic(expected = "invalid pattern")]
+	fn get_or_insert_default_invalid() {
+		RegexCache::new(100).get_or_insert_default("(");
+	}
+
+	#[test]
+	fn cached_regex_eq() {
+		use std::collections::HashSet;
+
+		let a = Arc::new(Mutex::new(RegexCache::new(100)));
+		let b = Arc::new(Mutex::new(RegexCache::new(10)));
+
+		let rules = [
+			CachedRegex::new(a.clone(), "abc").unwrap(),
+			CachedRegex::new(b.clone(), "abc").unwrap(),
+			CachedRegexBuilder::new(a.clone(), "abc").case_insensitive(true).build().unwrap(),
+			CachedRegex::new(b, "(?i)abc").unwrap(),
+		];
+
+		assert_eq!(rules[0], rules[1]);
+		assert_ne!(rules[0], rules[2]);
+		assert_ne!(rules[2], rules[3]);
+		assert_eq!(rules.iter().collect::<HashSet<_>>().len(), 3);
+	}
+
+	#[test]
+	fn cached_regex_fmt() {
+		let cache = Arc::new(Mutex::new(RegexCache::new(100)));
+		let re    = CachedRegex::new_unchecked(cache.clone(), r"^\d+$");
+
+		assert!(format!("{:?}", re).ends_with("cached: Some(false) }"));
+		assert!(re.is_match("1"));
+		assert!(format!("{:?}", re).ends_with("cached: Some(true) }"));
+
+		let guard  = cache.lock().unwrap();
+		let shown  = std::thread::spawn(move || (re.to_string(), format!("{:?}", re))).join().unwrap();
+		drop(guard);
+
+		assert_eq!(shown.0, r"^\d+$");
+		assert!(shown.1.starts_with(r#"CachedRegex { source: "^\\d+$""#));
+		assert!(shown.1.ends_with("cached: None }"));
+	}
+
+	#[test]
+	fn cached_regex_cache() {
+		let re = CachedRegex::new(Arc::new(Mutex::new(RegexCache::new(100))), r"^\d+$").unwrap();
+
+		assert!(re.is_match("1") && re.is_match("2"));
+		assert_eq!((re.cache().stats().hits, re.cache().stats().misses), (0, 1));
+
+		let other = CachedRegex::new(re.cache().into(), r"^\d+$").unwrap();
+		assert!(other.is_match("3"));
+		assert_eq!(re.cache().stats().hits, 1);
+		assert!(re.same_cache(&other));
+
+		let apart = CachedRegex::new(Arc::new(Mutex::new(RegexCache::new(100))), r"^\d+$").unwrap();
+		assert!(!re.same_cache(&apart));
+		assert_eq!(re, apart);
+	}
+
+	#[test]
+	fn cached_regex_memo() {
+		let cache = Arc::new(Mutex::new(RegexCache::new(1)));
+		let re    = CachedRegex::new(cache.clone(), r"^\d+$").unwrap();
+
+		std::thread::scope(|scope| {
+			for _ in 0 .. 4 {
+				scope.spawn(|| {
+					for i in 0 .. 1000 {
+						assert!(re.is_match(&i.to_string()));
+					}
+				});
+			}
+		});
+
+		let stats = cache.lock().unwrap().stats();
+		assert_eq!(stats.hits + stats.misses, 1);
+
+		// Eviction doesn't take the regular expression away from the instance.
+		cache.lock().unwrap().compile("other").unwrap();
+		assert!(!cache.lock().unwrap().contains(r"^\d+$"));
+		assert!(re.is_match("1234"));
+		assert!(re.clone().is_match("5678"));
+		assert_eq!(cache.lock().unwrap().stats().misses, 2);
+	}
+
+	#[test]
+	fn cached_regex_flags_in_key() {
+		for &insensitive_first in &[true, false] {
+			let cache = Arc::new(Mutex::new(RegexCache::new(100)));
+			let build = |insensitive: bool| if insensitive {
+				CachedRegexBuilder::new(cache.clone(), "abc").case_insensitive(true).build().unwrap()
+			}
+			else {
+				CachedRegex::new(cache.clone(), "abc").unwrap()
+			};
+
+			let first  = build(insensitive_first);
+			assert_eq!(first.is_match("ABC"), insensitive_first);
+
+			let second = build(!insensitive_first);
+			assert_eq!(second.is_match("ABC"), !insensitive_first);
+
+			assert!(first.is_match("abc") && second.is_match("abc"));
+			assert_eq!(cache.lock().unwrap().len(), 2);
+		}
+	}
+
+	#[test]
+	fn builder_on_evict() {
+		use std::hash::BuildHasherDefault;
+		use std::collections::hash_map::DefaultHasher;
+		use crate::cache::EvictionReason;
+
+		let clock   = MockClock::new();
+		let evicted = Arc::new(Mutex::new(Vec::new()));
+		let sink    = evicted.clone();
+
+		let mut cache = RegexCacheBuilder::new()
+			.capacity(2)
+			.ttl(Duration::from_secs(60))
+			.clock(clock.clone())
+			.on_evict(move |key, reason| sink.lock().unwrap().push((key.source.clone(), reason)))
+			.hasher(BuildHasherDefault::<DefaultHasher>::default())
+			.build();
+
+		cache.compile("a").unwrap();
+		cache.compile("b").unwrap();
+		cache.compile("c").unwrap();
+
+		clock.advance(Duration::from_secs(30));
+		cache.compile("d").unwrap();
+
+		clock.advance(Duration::from_secs(30));
+		cache.purge_expired();
+
+		clock.advance(Duration::from_secs(10));
+		cache.evict_idle(Duration::from_secs(10));
+
+		assert_eq!(*evicted.lock().unwrap(), [
+			("a".to_owned(), EvictionReason::Capacity),
+			("b".to_owned(), EvictionReason::Capacity),
+			("c".to_owned(), EvictionReason::Expired),
+			("d".to_owned(), EvictionReason::Idle),
+		]);
+		assert!(cache.is_empty());
 	}
 }
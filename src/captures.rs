@@ -0,0 +1,125 @@
+// Copyright 2017 1aim GmbH
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is furnished to do
+// so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use std::ops::Range;
+
+use regex::{Regex, Captures, Match};
+
+/// A single capture group match that owns the matched text.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct OwnedMatch {
+	start: usize,
+	end:   usize,
+	text:  String,
+}
+
+impl OwnedMatch {
+	/// Get the byte offset of the start of the match in the haystack.
+	pub fn start(&self) -> usize {
+		self.start
+	}
+
+	/// Get the byte offset of the end of the match in the haystack.
+	pub fn end(&self) -> usize {
+		self.end
+	}
+
+	/// Get the byte range of the match in the haystack.
+	pub fn range(&self) -> Range<usize> {
+		self.start .. self.end
+	}
+
+	/// Get the matched text.
+	pub fn as_str(&self) -> &str {
+		&self.text
+	}
+}
+
+impl<'t> From<Match<'t>> for OwnedMatch {
+	fn from(m: Match<'t>) -> OwnedMatch {
+		OwnedMatch {
+			start: m.start(),
+			end:   m.end(),
+			text:  m.as_str().to_owned(),
+		}
+	}
+}
+
+/// The capture groups of a match, independent from both the regular
+/// expression and the haystack.
+///
+/// # Example
+///
+/// ```
+/// # use regex_cache::{Regex, OwnedCaptures};
+/// let re   = Regex::new(r"(?P<year>\d{4})-(\d{2})").unwrap();
+/// let caps = OwnedCaptures::new(&re, &re.captures("on 2017-05").unwrap());
+///
+/// assert_eq!(caps.name("year").unwrap().as_str(), "2017");
+/// assert_eq!(caps.get(2).unwrap().range(), 8 .. 10);
+/// ```
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct OwnedCaptures {
+	groups: Vec<Option<OwnedMatch>>,
+	names:  Vec<Option<String>>,
+}
+
+impl OwnedCaptures {
+	/// Copy the given captures, which must come from the given regular
+	/// expression.
+	pub fn new(re: &Regex, caps: &Captures<'_>) -> OwnedCaptures {
+		OwnedCaptures {
+			groups: caps.iter().map(|m| m.map(OwnedMatch::from)).collect(),
+
+			names: re.capture_names().map(|name| name.map(String::from)).collect(),
+		}
+	}
+
+	/// Get the match for the group at the given index, the whole match being
+	/// at index `0`.
+	///
+	/// Returns `None` if there's no such group or if it didn't participate in
+	/// the match.
+	pub fn get(&self, index: usize) -> Option<&OwnedMatch> {
+		self.groups.get(index).and_then(Option::as_ref)
+	}
+
+	/// Get the match for the group with the given name.
+	pub fn name(&self, name: &str) -> Option<&OwnedMatch> {
+		self.names.iter().position(|n| n.as_deref() == Some(name))
+			.and_then(|index| self.get(index))
+	}
+
+	/// Iterate over all the groups in order, including the ones that didn't
+	/// participate in the match.
+	pub fn iter(&self) -> impl Iterator<Item = Option<&OwnedMatch>> {
+		self.groups.iter().map(Option::as_ref)
+	}
+
+	/// Get the number of groups, including the whole match.
+	pub fn len(&self) -> usize {
+		self.groups.len()
+	}
+
+	/// Check if there are no groups, which is never the case.
+	pub fn is_empty(&self) -> bool {
+		self.groups.is_empty()
+	}
+}
@@ -0,0 +1,155 @@
+// Copyright 2017 1aim GmbH
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is furnished to do
+// so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! A C API for `LazyRegex`, enabled with the `ffi` feature.
+//!
+//! Regular expressions are handed to C as opaque pointers, which have to be
+//! released with `lazy_regex_free`. The layout of `LazyRegex` isn't part of
+//! the API, so it must never be allocated or inspected from C.
+//!
+//! Functions returning a status return `0` on success and a negative error
+//! code otherwise, see the `LAZY_REGEX_*` constants.
+
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::slice;
+use std::str;
+
+use crate::lazy::LazyRegex;
+
+/// A null pointer was given.
+pub const LAZY_REGEX_NULL: c_int = -1;
+
+/// The pattern or the text isn't valid UTF-8.
+pub const LAZY_REGEX_INVALID_UTF8: c_int = -2;
+
+/// The pattern isn't a valid regular expression.
+pub const LAZY_REGEX_INVALID_PATTERN: c_int = -3;
+
+/// Borrow `len` bytes at `data` as a string.
+///
+/// # Safety
+///
+/// `data` must point to at least `len` readable bytes, or be null.
+unsafe fn text<'a>(data: *const c_char, len: usize) -> Result<&'a str, c_int> {
+	if data.is_null() {
+		return Err(LAZY_REGEX_NULL);
+	}
+
+	str::from_utf8(slice::from_raw_parts(data as *const u8, len))
+		.map_err(|_| LAZY_REGEX_INVALID_UTF8)
+}
+
+/// Create a lazy regular expression from the `len` bytes of UTF-8 at
+/// `pattern`, storing a pointer to it in `out`.
+///
+/// On failure `out` is set to null.
+///
+/// # Safety
+///
+/// `pattern` must point to at least `len` readable bytes and `out` must be
+/// valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn lazy_regex_new(pattern: *const c_char, len: usize, out: *mut *mut LazyRegex) -> c_int {
+	if out.is_null() {
+		return LAZY_REGEX_NULL;
+	}
+
+	*out = ptr::null_mut();
+
+	let pattern = match text(pattern, len) {
+		Ok(pattern) =>
+			pattern,
+
+		Err(code) =>
+			return code,
+	};
+
+	match LazyRegex::new(pattern) {
+		Ok(re) => {
+			*out = Box::into_raw(Box::new(re));
+			0
+		}
+
+		Err(_) =>
+			LAZY_REGEX_INVALID_PATTERN,
+	}
+}
+
+/// Check if the regular expression matches the `len` bytes of UTF-8 at
+/// `text`, returning `1` if it does and `0` if it doesn't.
+///
+/// # Safety
+///
+/// `re` must come from `lazy_regex_new` and not be freed yet, and `text`
+/// must point to at least `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn lazy_regex_is_match(re: *const LazyRegex, data: *const c_char, len: usize) -> c_int {
+	if re.is_null() {
+		return LAZY_REGEX_NULL;
+	}
+
+	match text(data, len) {
+		Ok(text) =>
+			(*re).is_match(text) as c_int,
+
+		Err(code) =>
+			code,
+	}
+}
+
+/// Release a regular expression created by `lazy_regex_new`, null is
+/// ignored.
+///
+/// # Safety
+///
+/// `re` must come from `lazy_regex_new` and not be freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn lazy_regex_free(re: *mut LazyRegex) {
+	if !re.is_null() {
+		drop(Box::from_raw(re));
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::ptr;
+	use super::*;
+
+	#[test]
+	fn roundtrip() {
+		unsafe {
+			let mut re = ptr::null_mut();
+			let pattern = r"^\d+$";
+
+			assert_eq!(lazy_regex_new(pattern.as_ptr() as *const c_char, pattern.len(), &mut re), 0);
+			assert_eq!(lazy_regex_is_match(re, "1234".as_ptr() as *const c_char, 4), 1);
+			assert_eq!(lazy_regex_is_match(re, "abcd".as_ptr() as *const c_char, 4), 0);
+			assert_eq!(lazy_regex_is_match(re, b"\xFF".as_ptr() as *const c_char, 1), LAZY_REGEX_INVALID_UTF8);
+			lazy_regex_free(re);
+
+			assert_eq!(lazy_regex_new("(".as_ptr() as *const c_char, 1, &mut re), LAZY_REGEX_INVALID_PATTERN);
+			assert!(re.is_null());
+			assert_eq!(lazy_regex_new(ptr::null(), 0, &mut re), LAZY_REGEX_NULL);
+			assert_eq!(lazy_regex_is_match(ptr::null(), ptr::null(), 0), LAZY_REGEX_NULL);
+			lazy_regex_free(ptr::null_mut());
+		}
+	}
+}
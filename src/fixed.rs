@@ -0,0 +1,187 @@
+// Copyright 2017 1aim GmbH
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is furnished to do
+// so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use regex::{Regex, RegexBuilder, Error};
+
+/// A cache for regular expressions holding up to `N` entries in an inline
+/// array, evicting them in insertion order.
+///
+/// Unlike `RegexCache` there's no hash map or linked list behind it, lookups
+/// are a linear scan, so it's only meant for a handful of patterns where the
+/// bookkeeping of a heap backed cache isn't wanted. The sources and compiled
+/// programs themselves are still allocated by the `regex` crate.
+///
+/// # Example
+///
+/// ```
+/// # use regex_cache::StaticRegexCache;
+/// let mut cache = StaticRegexCache::<2>::new();
+/// cache.compile("a").unwrap();
+/// cache.compile("b").unwrap();
+/// cache.compile("c").unwrap();
+///
+/// assert!(!cache.contains("a"));
+/// assert!(cache.compile(r"^\d+$").unwrap().is_match("1234"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct StaticRegexCache<const N: usize> {
+	entries: [Option<(String, Regex)>; N],
+	next:    usize,
+}
+
+impl<const N: usize> StaticRegexCache<N> {
+	const NOT_EMPTY: () = assert!(N > 0, "a StaticRegexCache needs room for at least one entry");
+
+	/// Create a new empty cache.
+	pub fn new() -> Self {
+		#[allow(clippy::let_unit_value)]
+		let () = Self::NOT_EMPTY;
+
+		StaticRegexCache {
+			entries: std::array::from_fn(|_| None),
+			next:    0,
+		}
+	}
+
+	/// Get the number of entries in the cache.
+	pub fn len(&self) -> usize {
+		self.entries.iter().filter(|entry| entry.is_some()).count()
+	}
+
+	/// Check if the cache is empty.
+	pub fn is_empty(&self) -> bool {
+		self.entries.iter().all(Option::is_none)
+	}
+
+	/// Get the maximum number of entries in the cache, which is `N`.
+	pub fn capacity(&self) -> usize {
+		N
+	}
+
+	/// Check if a regular expression for the given source is in the cache.
+	pub fn contains(&self, source: &str) -> bool {
+		self.position(source).is_some()
+	}
+
+	/// Remove all the entries from the cache.
+	pub fn clear(&mut self) {
+		for entry in &mut self.entries {
+			*entry = None;
+		}
+
+		self.next = 0;
+	}
+
+	/// Save the given regular expression in the cache.
+	///
+	/// See `RegexCache::save`.
+	pub fn save(&mut self, re: Regex) -> &Regex {
+		match self.position(re.as_str()) {
+			Some(index) =>
+				self.get(index),
+
+			None =>
+				self.put(re.as_str().to_owned(), re),
+		}
+	}
+
+	/// Create a new regular expression in the cache.
+	///
+	/// See `RegexCache::compile`.
+	pub fn compile(&mut self, source: &str) -> Result<&Regex, Error> {
+		self.configure(source, |b| b)
+	}
+
+	/// Configure a new regular expression.
+	///
+	/// Like for `RegexCache::configure` the entry is keyed by the source
+	/// alone.
+	pub fn configure<F>(&mut self, source: &str, f: F) -> Result<&Regex, Error>
+		where F: FnOnce(&mut RegexBuilder) -> &mut RegexBuilder
+	{
+		match self.position(source) {
+			Some(index) =>
+				Ok(self.get(index)),
+
+			None => {
+				let re = f(&mut RegexBuilder::new(source)).build()?;
+				Ok(self.put(source.to_owned(), re))
+			}
+		}
+	}
+
+	/// Find the slot holding the given source.
+	fn position(&self, source: &str) -> Option<usize> {
+		self.entries.iter().position(|entry|
+			entry.as_ref().is_some_and(|(s, _)| s == source))
+	}
+
+	/// Get the regular expression in the given occupied slot.
+	fn get(&self, index: usize) -> &Regex {
+		&self.entries[index].as_ref().unwrap().1
+	}
+
+	/// Insert an entry in the next slot, replacing the oldest entry once the
+	/// cache is full.
+	fn put(&mut self, source: String, re: Regex) -> &Regex {
+		let index = self.next;
+		self.next = (self.next + 1) % N;
+
+		&self.entries[index].insert((source, re)).1
+	}
+}
+
+impl<const N: usize> Default for StaticRegexCache<N> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use regex::Regex;
+	use crate::fixed::StaticRegexCache;
+
+	#[test]
+	fn fifo() {
+		let mut cache = StaticRegexCache::<3>::new();
+		assert!(cache.is_empty());
+
+		cache.compile("a").unwrap();
+		cache.save(Regex::new("b").unwrap());
+		assert!(cache.configure("c", |b| b.case_insensitive(true)).unwrap().is_match("C"));
+		assert_eq!(cache.len(), 3);
+
+		// Hits don't change the insertion order.
+		assert!(cache.compile("a").unwrap().is_match("a"));
+		cache.compile("d").unwrap();
+
+		assert!(!cache.contains("a"));
+		assert!(cache.contains("b") && cache.contains("c") && cache.contains("d"));
+		assert_eq!(cache.len(), 3);
+		assert_eq!(cache.capacity(), 3);
+
+		assert!(cache.compile("(").is_err());
+		assert_eq!(cache.len(), 3);
+
+		cache.clear();
+		assert!(cache.is_empty());
+	}
+}
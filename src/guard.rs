@@ -0,0 +1,164 @@
+// Copyright 2017 1aim GmbH
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is furnished to do
+// so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use std::error;
+use std::fmt;
+
+use regex::Error;
+use crate::syntax::ast::{self, ErrorKind};
+
+/// Cheap checks run on a pattern before it's compiled, to reject hostile
+/// patterns early.
+///
+/// The size limit of the compiler only kicks in after the pattern has been
+/// parsed, which can already take a while for huge or deeply nested patterns.
+///
+/// # Example
+///
+/// ```
+/// # use regex_cache::{PatternLimits, PatternError, check_pattern};
+/// let limits = PatternLimits {
+/// 	max_len:    Some(1024),
+/// 	nest_limit: Some(10),
+/// 	blocked:    vec![r"\p".to_owned()],
+/// };
+///
+/// assert!(check_pattern(r"^\d+$", &limits).is_ok());
+/// assert_eq!(check_pattern(r"\p{Greek}", &limits), Err(PatternError::Blocked(r"\p".to_owned())));
+/// ```
+#[derive(Clone, Eq, PartialEq, Default, Debug)]
+pub struct PatternLimits {
+	/// The maximum length of the pattern, in bytes.
+	pub max_len: Option<usize>,
+
+	/// The maximum nesting depth of groups, repetitions and classes.
+	pub nest_limit: Option<u32>,
+
+	/// Fragments that must not appear in the pattern, like `\p` to reject
+	/// Unicode classes.
+	pub blocked: Vec<String>,
+}
+
+/// A pattern rejected by its `PatternLimits`.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum PatternError {
+	/// The pattern is longer than allowed.
+	TooLong {
+		/// The length of the pattern, in bytes.
+		len: usize,
+
+		/// The maximum length.
+		max: usize,
+	},
+
+	/// The pattern is nested deeper than the given limit.
+	TooDeep(u32),
+
+	/// The pattern contains the given blocked fragment.
+	Blocked(String),
+}
+
+impl fmt::Display for PatternError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			PatternError::TooLong { len, max } =>
+				write!(f, "pattern is {} bytes long, the limit is {}", len, max),
+
+			PatternError::TooDeep(limit) =>
+				write!(f, "pattern is nested deeper than {}", limit),
+
+			PatternError::Blocked(ref fragment) =>
+				write!(f, "pattern contains the blocked construct {:?}", fragment),
+		}
+	}
+}
+
+impl error::Error for PatternError { }
+
+/// Check the pattern against the given limits without compiling it.
+///
+/// The length is checked first, so even a huge pattern is rejected right
+/// away. Syntax errors other than nesting too deep are left to the
+/// compiler.
+pub fn check_pattern(source: &str, limits: &PatternLimits) -> Result<(), PatternError> {
+	if let Some(max) = limits.max_len {
+		if source.len() > max {
+			return Err(PatternError::TooLong { len: source.len(), max: max });
+		}
+	}
+
+	if let Some(fragment) = limits.blocked.iter().find(|fragment| source.contains(fragment.as_str())) {
+		return Err(PatternError::Blocked(fragment.clone()));
+	}
+
+	if let Some(limit) = limits.nest_limit {
+		let parsed = ast::parse::ParserBuilder::new().nest_limit(limit).build().parse(source);
+
+		if let Err(err) = parsed {
+			if let ErrorKind::NestLimitExceeded(_) = *err.kind() {
+				return Err(PatternError::TooDeep(limit));
+			}
+		}
+	}
+
+	Ok(())
+}
+
+/// Check the pattern against the given limits, if any, as a compilation
+/// error.
+pub(crate) fn guard(source: &str, limits: Option<&PatternLimits>) -> Result<(), Error> {
+	match limits {
+		Some(limits) =>
+			check_pattern(source, limits).map_err(|err| Error::Syntax(err.to_string())),
+
+		None =>
+			Ok(()),
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::time::{Duration, Instant};
+	use crate::guard::{PatternLimits, PatternError, check_pattern};
+
+	#[test]
+	fn limits() {
+		let limits = PatternLimits {
+			max_len:    Some(1 << 20),
+			nest_limit: Some(4),
+			blocked:    vec!["{1000".to_owned()],
+		};
+
+		let huge  = "a".repeat(10 << 20);
+		let start = Instant::now();
+		assert_eq!(check_pattern(&huge, &limits), Err(PatternError::TooLong { len: 10 << 20, max: 1 << 20 }));
+		assert!(start.elapsed() < Duration::from_millis(10));
+
+		let deep = format!("{}a{}", "(".repeat(10), ")".repeat(10));
+		assert_eq!(check_pattern(&deep, &limits), Err(PatternError::TooDeep(4)));
+		assert!(check_pattern("((a))", &limits).is_ok());
+
+		assert_eq!(check_pattern("a{1000}", &limits), Err(PatternError::Blocked("{1000".to_owned())));
+
+		// Other syntax errors are left to the compiler.
+		assert!(check_pattern("(", &limits).is_ok());
+		assert!(check_pattern(&huge, &PatternLimits::default()).is_ok());
+	}
+}
@@ -19,20 +19,45 @@
 // SOFTWARE.
 
 use std::ops::Deref;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::error;
 use std::fmt;
+use std::slice;
 use std::str;
 
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use oncemutex::OnceMutex;
 
-use regex::{Regex, RegexBuilder, Error};
+use regex::{Regex, RegexBuilder, Error, Match, Captures};
 use crate::syntax;
-use crate::options::Options;
+use crate::syntax::hir::{Hir, HirKind, Anchor, Class, ClassUnicode, ClassUnicodeRange, Literal, Group, GroupKind, Repetition};
+use crate::options::{Options, FlagEq};
+use crate::replace::{self, ReplacementError};
+
+/// A failure while searching, as opposed to the absence of a match.
+///
+/// None of the searches can fail with the current engine, so there's no way
+/// to get one yet, see `LazyRegex::find_checked`.
+#[non_exhaustive]
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum MatchError { }
+
+impl fmt::Display for MatchError {
+	fn fmt(&self, _f: &mut fmt::Formatter) -> fmt::Result {
+		match *self { }
+	}
+}
+
+impl error::Error for MatchError { }
 
 /// A lazily created `Regex`.
 ///
-/// At the first `Deref` the given source will be compiled and saved in the
-/// Local Thread Storage, thus avoiding locking.
+/// The given source is compiled once, at the first `Deref`, and the result is
+/// shared with every clone. Only that first use takes a lock, later ones
+/// reach the compiled regex directly.
 ///
 /// # Example
 ///
@@ -47,7 +72,9 @@ use crate::options::Options;
 #[derive(Clone)]
 pub struct LazyRegex {
 	builder: LazyRegexBuilder,
-	regex:   Arc<OnceMutex<Option<Regex>>>
+	regex:   Arc<OnceMutex<Option<Regex>>>,
+	elapsed: Arc<OnceLock<Duration>>,
+	fixed:   Arc<OnceLock<Option<Fixed>>>,
 }
 
 impl LazyRegex {
@@ -58,18 +85,415 @@ impl LazyRegex {
 			return Err(Error::Syntax(err.to_string()));
 		}
 
-		Ok(LazyRegex::from(LazyRegexBuilder::new(source)))
+		Ok(LazyRegex::from_builder(LazyRegexBuilder::new(source)))
+	}
+
+	/// Create a new lazy `Regex` for the given source, without checking if the
+	/// syntax is valid.
+	///
+	/// Only use this if you know that the syntax is valid, an invalid pattern
+	/// will panic when first used. The `const_lazy_regex!` macro checks the
+	/// syntax at compile time and then uses this.
+	pub fn new_unchecked(source: &str) -> LazyRegex {
+		LazyRegex::from_builder(LazyRegexBuilder::new(source))
+	}
+
+	/// Create a new lazy `Regex` for the given source that only matches the
+	/// whole text, checking the syntax is valid.
+	///
+	/// See `LazyRegexBuilder::anchor_pattern`.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::LazyRegex;
+	/// let re = LazyRegex::anchored(r"\d+|[a-z]+").unwrap();
+	///
+	/// assert!(re.is_match("1234"));
+	/// assert!(!re.is_match("1234abcd"));
+	/// ```
+	pub fn anchored(source: &str) -> Result<LazyRegex, Error> {
+		LazyRegexBuilder::new(source).anchor_pattern(true).build()
+	}
+
+	/// Create a new lazy `Regex` for the given source with the given nesting
+	/// limit, checking the syntax is valid.
+	///
+	/// See `LazyRegexBuilder::nest_limit`.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::LazyRegex;
+	/// assert!(LazyRegex::with_nest_limit("((a))", 2).is_ok());
+	/// assert!(LazyRegex::with_nest_limit("((a))", 1).is_err());
+	/// ```
+	pub fn with_nest_limit(source: &str, limit: u32) -> Result<LazyRegex, Error> {
+		LazyRegexBuilder::new(source).nest_limit(limit).build()
+	}
+
+	/// Create a new case insensitive lazy `Regex` for the given source,
+	/// checking the syntax is valid.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::LazyRegex;
+	/// assert!(LazyRegex::case_insensitive("abc").unwrap().is_match("ABC"));
+	/// ```
+	pub fn case_insensitive(source: &str) -> Result<LazyRegex, Error> {
+		LazyRegexBuilder::new(source).case_insensitive(true).build()
+	}
+
+	/// Create a new multi-line lazy `Regex` for the given source, checking the
+	/// syntax is valid.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::LazyRegex;
+	/// assert!(LazyRegex::multi_line("^b$").unwrap().is_match("a\nb\nc"));
+	/// ```
+	pub fn multi_line(source: &str) -> Result<LazyRegex, Error> {
+		LazyRegexBuilder::new(source).multi_line(true).build()
+	}
+
+	/// Get a textual representation of the pattern together with its flags,
+	/// which are written as a leading inline flag group.
+	///
+	/// Only the flags that can be written inline are kept, size limits and the
+	/// other builder settings are lost.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::{LazyRegex, LazyRegexBuilder};
+	/// let re = LazyRegexBuilder::new(r"^abc$")
+	/// 	.case_insensitive(true).multi_line(true).build().unwrap();
+	///
+	/// assert_eq!(re.to_builder_repr(), r"(?im)^abc$");
+	///
+	/// let re = LazyRegex::from_builder_repr(&re.to_builder_repr()).unwrap();
+	/// assert!(re.is_match("x\nABC"));
+	/// ```
+	pub fn to_builder_repr(&self) -> String {
+		let options = &self.builder.options;
+		let mut flags = String::new();
+
+		for &(yes, name) in &[
+			(options.case_insensitive, 'i'),
+			(options.multi_line, 'm'),
+			(options.dot_matches_new_line, 's'),
+			(options.swap_greed, 'U'),
+			(options.ignore_whitespace, 'x'),
+		] {
+			if yes {
+				flags.push(name);
+			}
+		}
+
+		if !options.unicode {
+			flags.push_str("-u");
+		}
+
+		if flags.is_empty() {
+			self.builder.source.clone()
+		}
+		else {
+			format!("(?{}){}", flags, self.builder.source)
+		}
 	}
 
-	fn from(builder: LazyRegexBuilder) -> Self {
+	/// Create a new lazy `Regex` from the representation produced by
+	/// `to_builder_repr`, checking the syntax is valid.
+	///
+	/// A leading inline flag group is turned into the builder flags, anything
+	/// else is kept as the source.
+	pub fn from_builder_repr(repr: &str) -> Result<LazyRegex, Error> {
+		LazyRegexBuilder::from_inline_flags(repr)?.build()
+	}
+
+	/// Check if the leftmost match spans the whole `text`.
+	///
+	/// Note this isn't quite the same as an anchored pattern: the leftmost
+	/// match prefers earlier alternatives, so `a|ab` doesn't match all of
+	/// `"ab"`. Use `LazyRegex::anchored` when that matters.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::LazyRegex;
+	/// let re = LazyRegex::new(r"\d+").unwrap();
+	///
+	/// assert!(re.matches_entire("1234"));
+	/// assert!(!re.matches_entire("1234abcd"));
+	/// ```
+	pub fn matches_entire(&self, text: &str) -> bool {
+		self.find(text).is_some_and(|m| m.start() == 0 && m.end() == text.len())
+	}
+
+	/// Count the non-overlapping matches in `text`.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::LazyRegex;
+	/// let re = LazyRegex::new(r"\d+").unwrap();
+	///
+	/// assert_eq!(re.count_matches("1 22 333"), 3);
+	/// assert_eq!(re.count_matches("abcd"), 0);
+	/// ```
+	pub fn count_matches(&self, text: &str) -> usize {
+		self.find_iter(text).count()
+	}
+
+	/// Check if the regular expression matches `text`, comparing strings
+	/// instead of running the regular expression when the pattern is a plain
+	/// string.
+	///
+	/// A pattern is a plain string when it has no meta-characters once parsed
+	/// with its flags, possibly anchored at both ends, in which case the whole
+	/// text is compared. The pattern is only parsed once, and the regular
+	/// expression is compiled only if it's needed.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::LazyRegex;
+	/// assert!(LazyRegex::new("hello").unwrap().is_literal_match("oh hello there"));
+	/// assert!(!LazyRegex::new("^hello$").unwrap().is_literal_match("oh hello there"));
+	/// assert!(LazyRegex::new("h.llo").unwrap().is_literal_match("hallo"));
+	/// ```
+	pub fn is_literal_match(&self, text: &str) -> bool {
+		let fixed = self.fixed.get_or_init(||
+			self.builder.hir().and_then(|hir| Fixed::of(&hir)));
+
+		match *fixed {
+			Some(Fixed::Contains(ref literal)) =>
+				text.contains(literal.as_str()),
+
+			Some(Fixed::Equals(ref literal)) =>
+				text == literal,
+
+			None =>
+				self.is_match(text),
+		}
+	}
+
+	/// Count the non-overlapping matches in `text`, stopping after `limit` of
+	/// them without scanning the rest of the text.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::LazyRegex;
+	/// let re = LazyRegex::new(r"\d+").unwrap();
+	///
+	/// assert_eq!(re.count_matches_max("1 22 333", 2), 2);
+	/// assert_eq!(re.count_matches_max("1 22 333", 10), 3);
+	/// ```
+	pub fn count_matches_max(&self, text: &str, limit: usize) -> usize {
+		self.find_iter(text).take(limit).count()
+	}
+
+	/// Check if both regular expressions match the same language, even if
+	/// their patterns are written differently.
+	///
+	/// The patterns are parsed with their flags and compared after some light
+	/// normalization: non-capturing groups are dropped, alternations of single
+	/// characters become classes, and classes of a single character become
+	/// literals. This catches the common rewrites but isn't a full equivalence
+	/// check, a `false` doesn't mean the languages differ.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::{LazyRegex, LazyRegexBuilder};
+	/// let a = LazyRegex::new(r"[ab]+").unwrap();
+	/// let b = LazyRegex::new(r"(?:a|b)+").unwrap();
+	/// let c = LazyRegexBuilder::new(r"[AB]+").case_insensitive(true).build().unwrap();
+	///
+	/// assert!(a.semantic_eq(&b));
+	/// assert!(!a.semantic_eq(&c));
+	/// ```
+	pub fn semantic_eq(&self, other: &LazyRegex) -> bool {
+		match (self.builder.hir(), other.builder.hir()) {
+			(Some(a), Some(b)) =>
+				a == b,
+
+			_ =>
+				false,
+		}
+	}
+
+	/// Replace all the matches in `text` with the given replacement string,
+	/// checking every group it refers to exists.
+	///
+	/// `replace_all` silently expands references to missing groups to an
+	/// empty string, this reports them instead.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::{LazyRegex, ReplacementError};
+	/// let re = LazyRegex::new(r"(?P<y>\d{4})-(\d{2})").unwrap();
+	///
+	/// assert_eq!(re.replace_checked("2017-05", "$2/$y").unwrap(), "05/2017");
+	/// assert_eq!(re.replace_checked("2017-05", "$3").unwrap_err(),
+	/// 	ReplacementError::InvalidGroup(3));
+	/// ```
+	pub fn replace_checked(&self, text: &str, replacement: &str) -> Result<String, ReplacementError> {
+		replace::check(self, replacement)?;
+		Ok(self.replace_all(text, replacement).into_owned())
+	}
+
+	/// Find the leftmost-first match in `text`, reporting failures of the
+	/// search itself.
+	///
+	/// The engine behind `Regex` can't fail, so this always succeeds for now,
+	/// it allows switching to engines that can give up, like a DFA running
+	/// out of cache, without changing the callers.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::LazyRegex;
+	/// let re = LazyRegex::new(r"\d+").unwrap();
+	/// assert_eq!(re.find_checked("ab12").unwrap().unwrap().as_str(), "12");
+	/// ```
+	pub fn find_checked<'t>(&self, text: &'t str) -> Result<Option<Match<'t>>, MatchError> {
+		Ok(self.find(text))
+	}
+
+	/// Check if the regular expression matches `text`, reporting failures of
+	/// the search itself, see `find_checked`.
+	pub fn is_match_checked(&self, text: &str) -> Result<bool, MatchError> {
+		Ok(self.is_match(text))
+	}
+
+	/// Find the capture groups of the leftmost-first match in `text`,
+	/// reporting failures of the search itself, see `find_checked`.
+	pub fn captures_checked<'t>(&self, text: &'t str) -> Result<Option<Captures<'t>>, MatchError> {
+		Ok(self.captures(text))
+	}
+
+	/// Find all the non-overlapping matches in `text`, returning their byte
+	/// ranges.
+	///
+	/// Unlike `find_iter` the result doesn't borrow the regular expression or
+	/// the text.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::LazyRegex;
+	/// let re = LazyRegex::new(r"\d+").unwrap();
+	/// assert_eq!(re.find_iter_collect("a1 b22 c333"), [(1, 2), (4, 6), (8, 11)]);
+	/// ```
+	pub fn find_iter_collect(&self, text: &str) -> Vec<(usize, usize)> {
+		self.find_iter(text).map(|m| (m.start(), m.end())).collect()
+	}
+
+	/// Find the captures of all the non-overlapping matches in `text`, as
+	/// owned strings.
+	///
+	/// There's one entry per match, holding one element per group, the first
+	/// one being the whole match and `None` for the groups that didn't
+	/// participate. Unlike `captures_iter` the result doesn't borrow the
+	/// regular expression or the text.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::LazyRegex;
+	/// let re   = LazyRegex::new(r"(\w)=(\d)?").unwrap();
+	/// let caps = re.captures_iter_collect("a=1 b=");
+	///
+	/// assert_eq!(caps[0], [Some("a=1".to_owned()), Some("a".to_owned()), Some("1".to_owned())]);
+	/// assert_eq!(caps[1], [Some("b=".to_owned()), Some("b".to_owned()), None]);
+	/// ```
+	pub fn captures_iter_collect(&self, text: &str) -> Vec<Vec<Option<String>>> {
+		self.captures_iter(text).map(|caps|
+			caps.iter().map(|m| m.map(|m| m.as_str().to_owned())).collect())
+			.collect()
+	}
+
+	/// Find the named captures of all the non-overlapping matches in `text`,
+	/// as owned strings.
+	///
+	/// There's one map per match, holding the named groups that participated
+	/// in it.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::LazyRegex;
+	/// let re   = LazyRegex::new(r"(?P<key>\w)=(?P<value>\d)?").unwrap();
+	/// let caps = re.captures_named_collect("a=1 b=");
+	///
+	/// assert_eq!(caps[0]["value"], "1");
+	/// assert!(!caps[1].contains_key("value"));
+	/// ```
+	pub fn captures_named_collect(&self, text: &str) -> Vec<HashMap<String, String>> {
+		let names = self.capture_names().flatten().collect::<Vec<_>>();
+
+		self.captures_iter(text).map(|caps|
+			names.iter().filter_map(|&name|
+				caps.name(name).map(|m| (name.to_owned(), m.as_str().to_owned())))
+				.collect())
+			.collect()
+	}
+
+	/// Get how long the compilation of the regular expression took, or `None`
+	/// if it hasn't been compiled yet.
+	///
+	/// The regular expression is compiled once and shared by all the clones,
+	/// so is the compile time. A `LazyRegex` created from an already compiled
+	/// `Regex` has no compile time.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::LazyRegex;
+	/// let re = LazyRegex::new(r"^\d+$").unwrap();
+	/// assert!(re.compile_time().is_none());
+	///
+	/// assert!(re.is_match("1234"));
+	/// assert!(re.compile_time().is_some());
+	/// ```
+	pub fn compile_time(&self) -> Option<Duration> {
+		self.elapsed.get().copied()
+	}
+
+	fn from_builder(builder: LazyRegexBuilder) -> Self {
 		LazyRegex {
 			builder: builder,
 			regex:   Arc::new(OnceMutex::new(None)),
+			elapsed: Arc::new(OnceLock::new()),
+			fixed:   Arc::new(OnceLock::new()),
 		}
 	}
 
+	/// Get a lazy regular expression over bytes with the same pattern and
+	/// options, which is compiled at its own first use.
+	///
+	/// An anchored pattern stays anchored, the anchors become part of the
+	/// source of the result.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::LazyRegex;
+	/// let re = LazyRegex::case_insensitive("^abc$").unwrap().to_bytes();
+	///
+	/// assert!(re.is_match(b"ABC"));
+	/// ```
+	pub fn to_bytes(&self) -> crate::bytes::LazyRegex {
+		crate::bytes::LazyRegex::from_parts(self.builder.pattern().into_owned(), self.builder.options)
+	}
+
 	fn create(builder: &LazyRegexBuilder) -> Regex {
-		builder.options.define(&mut RegexBuilder::new(&builder.source))
+		builder.options.define(&mut RegexBuilder::new(&builder.pattern()))
 			.build().unwrap()
 	}
 }
@@ -85,16 +509,77 @@ impl Deref for LazyRegex {
 impl AsRef<Regex> for LazyRegex {
 	fn as_ref(&self) -> &Regex {
 		if let Some(mut guard) = self.regex.lock() {
-			*guard = Some(LazyRegex::create(&self.builder));
+			if guard.is_none() {
+				let start = Instant::now();
+				*guard = Some(LazyRegex::create(&self.builder));
+				self.elapsed.set(start.elapsed()).ok();
+			}
 		}
 
 		(*self.regex).as_ref().unwrap()
 	}
 }
 
-impl Into<Regex> for LazyRegex {
-	fn into(self) -> Regex {
-		let (regex, builder) = (self.regex, self.builder);
+impl From<Regex> for LazyRegex {
+	/// Wrap an already compiled `Regex`, which is used as is instead of being
+	/// compiled again.
+	///
+	/// The builder is created from the source of the regular expression with
+	/// the default options, since the options it was compiled with can't be
+	/// recovered.
+	fn from(re: Regex) -> LazyRegex {
+		LazyRegex {
+			builder: LazyRegexBuilder::new(re.as_str()),
+			regex:   Arc::new(OnceMutex::new(Some(re))),
+			elapsed: Arc::new(OnceLock::new()),
+			fixed:   Arc::new(OnceLock::new()),
+		}
+	}
+}
+
+impl From<LazyRegex> for LazyRegexBuilder {
+	/// Get back the builder the lazy `Regex` was created with, dropping the
+	/// compiled regular expression if any.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::{LazyRegex, LazyRegexBuilder};
+	/// let re = LazyRegex::new("abc").unwrap();
+	/// let re = LazyRegexBuilder::from(re).case_insensitive(true).build().unwrap();
+	///
+	/// assert!(re.is_match("ABC"));
+	/// ```
+	fn from(lazy: LazyRegex) -> LazyRegexBuilder {
+		lazy.builder
+	}
+}
+
+impl From<LazyRegexBuilder> for RegexBuilder {
+	/// Get a `RegexBuilder` configured like the lazy builder, to compile the
+	/// regular expression outside of `LazyRegex`.
+	///
+	/// The pattern is wrapped in anchors if `anchor_pattern` was set.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::{LazyRegexBuilder, RegexBuilder};
+	/// let lazy = LazyRegexBuilder::new("abc").case_insensitive(true).clone();
+	/// let re   = RegexBuilder::from(lazy).build().unwrap();
+	///
+	/// assert!(re.is_match("ABC"));
+	/// ```
+	fn from(builder: LazyRegexBuilder) -> RegexBuilder {
+		let mut regex = RegexBuilder::new(&builder.pattern());
+		builder.options.define(&mut regex);
+		regex
+	}
+}
+
+impl From<LazyRegex> for Regex {
+	fn from(lazy: LazyRegex) -> Regex {
+		let (regex, builder) = (lazy.regex, lazy.builder);
 
 		Arc::try_unwrap(regex).ok().and_then(|m| m.into_inner()).unwrap_or_else(||
 			LazyRegex::create(&builder))
@@ -121,11 +606,217 @@ impl str::FromStr for LazyRegex {
 	}
 }
 
+/// Split a leading inline flag group like `(?im-u)` from the rest of the
+/// pattern, returning the flags without the delimiters.
+fn split_flags(pattern: &str) -> Option<(&str, &str)> {
+	let rest  = pattern.strip_prefix("(?")?;
+	let end   = rest.find(')')?;
+	let flags = &rest[.. end];
+
+	let valid = !flags.is_empty() &&
+		flags.matches('-').count() <= 1 &&
+		!flags.ends_with('-') &&
+		flags.chars().all(|c| "imsUxu-".contains(c));
+
+	if valid {
+		Some((flags, &rest[end + 1 ..]))
+	}
+	else {
+		None
+	}
+}
+
+/// Rewrite equivalent constructs the same way, see `LazyRegex::semantic_eq`.
+fn normalize(hir: Hir) -> Hir {
+	match hir.into_kind() {
+		HirKind::Group(Group { kind: GroupKind::NonCapturing, hir }) =>
+			normalize(*hir),
+
+		HirKind::Group(Group { kind, hir }) =>
+			Hir::group(Group { kind: kind, hir: Box::new(normalize(*hir)) }),
+
+		HirKind::Repetition(Repetition { kind, greedy, hir }) =>
+			Hir::repetition(Repetition { kind: kind, greedy: greedy, hir: Box::new(normalize(*hir)) }),
+
+		HirKind::Concat(hirs) =>
+			Hir::concat(hirs.into_iter().map(normalize).collect()),
+
+		HirKind::Alternation(hirs) => {
+			let hirs = hirs.into_iter().map(normalize).collect::<Vec<_>>();
+
+			// Every alternative matches a single character, so their order
+			// doesn't matter and they're the same as a class.
+			let mut class = ClassUnicode::empty();
+			let single    = hirs.iter().all(|hir| match *hir.kind() {
+				HirKind::Literal(Literal::Unicode(c)) => {
+					class.union(&ClassUnicode::new(vec![ClassUnicodeRange::new(c, c)]));
+					true
+				}
+
+				HirKind::Class(Class::Unicode(ref other)) => {
+					class.union(other);
+					true
+				}
+
+				_ =>
+					false,
+			});
+
+			if single {
+				literal_or_class(class)
+			}
+			else {
+				Hir::alternation(hirs)
+			}
+		}
+
+		HirKind::Class(Class::Unicode(class)) =>
+			literal_or_class(class),
+
+		HirKind::Class(class) =>
+			Hir::class(class),
+
+		HirKind::Literal(literal) =>
+			Hir::literal(literal),
+
+		HirKind::Anchor(anchor) =>
+			Hir::anchor(anchor),
+
+		HirKind::WordBoundary(boundary) =>
+			Hir::word_boundary(boundary),
+
+		HirKind::Empty =>
+			Hir::empty(),
+	}
+}
+
+/// A pattern made of a plain string, see `LazyRegex::is_literal_match`.
+#[derive(Clone, Debug)]
+enum Fixed {
+	/// The string can appear anywhere in the text.
+	Contains(String),
+
+	/// The string has to be the whole text.
+	Equals(String),
+}
+
+impl Fixed {
+	/// Get the plain string matched by a normalized pattern, if it is one.
+	fn of(hir: &Hir) -> Option<Fixed> {
+		let parts = match *hir.kind() {
+			HirKind::Concat(ref hirs) =>
+				&hirs[..],
+
+			_ =>
+				slice::from_ref(hir),
+		};
+
+		match parts {
+			[first, inner @ .., last] if
+				*first.kind() == HirKind::Anchor(Anchor::StartText) &&
+				*last.kind() == HirKind::Anchor(Anchor::EndText) =>
+				Some(Fixed::Equals(literal(inner)?)),
+
+			_ =>
+				Some(Fixed::Contains(literal(parts)?)),
+		}
+	}
+}
+
+/// Get the string made of the given literals, if they are all characters.
+fn literal(hirs: &[Hir]) -> Option<String> {
+	let mut string = String::new();
+
+	for hir in hirs {
+		match *hir.kind() {
+			HirKind::Literal(Literal::Unicode(c)) =>
+				string.push(c),
+
+			HirKind::Concat(ref hirs) =>
+				string.push_str(&literal(hirs)?),
+
+			_ =>
+				return None,
+		}
+	}
+
+	Some(string)
+}
+
+/// Turn a class of a single character into a literal.
+fn literal_or_class(class: ClassUnicode) -> Hir {
+	match class.ranges() {
+		[range] if range.start() == range.end() =>
+			Hir::literal(Literal::Unicode(range.start())),
+
+		_ =>
+			Hir::class(Class::Unicode(class)),
+	}
+}
+
+/// A lazily created `Regex` handed out from a fixed number of copies in turn.
+///
+/// Each copy of a `Regex` has its own scratch space for searching, so spreading
+/// the searches over several copies keeps a workload that thrashes the cache
+/// of one copy from slowing down the others. All the copies share the same
+/// compiled program, which is only compiled once, on the first `get`.
+///
+/// # Example
+///
+/// ```
+/// # use regex_cache::LazyRegexPool;
+/// let pool = LazyRegexPool::new(r"^\d+$", 4).unwrap();
+///
+/// assert!(pool.get().is_match("1234"));
+/// assert!(!pool.get().is_match("abcd"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct LazyRegexPool {
+	regex:  LazyRegex,
+	size:   usize,
+	copies: Arc<OnceLock<Vec<Regex>>>,
+	next:   Arc<AtomicUsize>,
+}
+
+impl LazyRegexPool {
+	/// Create a new pool of `size` copies of the regular expression for the
+	/// given source, checking the syntax is valid.
+	///
+	/// A size of `0` is treated as `1`.
+	pub fn new(source: &str, size: usize) -> Result<LazyRegexPool, Error> {
+		Ok(LazyRegexPool::from_lazy(LazyRegex::new(source)?, size))
+	}
+
+	/// Create a new pool of `size` copies of the given lazy `Regex`.
+	pub fn from_lazy(regex: LazyRegex, size: usize) -> LazyRegexPool {
+		LazyRegexPool {
+			regex:  regex,
+			size:   size.max(1),
+			copies: Arc::new(OnceLock::new()),
+			next:   Arc::new(AtomicUsize::new(0)),
+		}
+	}
+
+	/// Get the number of copies in the pool.
+	pub fn size(&self) -> usize {
+		self.size
+	}
+
+	/// Get the next copy of the regular expression, compiling it if needed.
+	pub fn get(&self) -> &Regex {
+		let copies = self.copies.get_or_init(||
+			(0 .. self.size).map(|_| (*self.regex).clone()).collect());
+
+		&copies[self.next.fetch_add(1, Ordering::Relaxed) % copies.len()]
+	}
+}
+
 /// A configurable builder for a lazy `Regex`.
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub struct LazyRegexBuilder {
 	source: String,
 	options: Options,
+	anchored: bool,
 }
 
 impl LazyRegexBuilder {
@@ -137,20 +828,128 @@ impl LazyRegexBuilder {
 		LazyRegexBuilder {
 			source: source.to_owned(),
 			options: Default::default(),
+			anchored: false,
+		}
+	}
+
+	/// Create a builder with the given pattern and options.
+	pub(crate) fn with_options(source: &str, options: Options) -> LazyRegexBuilder {
+		LazyRegexBuilder {
+			source: source.to_owned(),
+			options: options,
+			anchored: false,
+		}
+	}
+
+	/// Create a builder from a pattern with leading inline flag groups like
+	/// `(?im)`, which are moved to the flags of the builder.
+	///
+	/// The groups are applied in order, the rest of the pattern becomes the
+	/// source. Fails if the rest isn't a valid pattern.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::LazyRegexBuilder;
+	/// let builder = LazyRegexBuilder::from_inline_flags(r"(?im)^abc$").unwrap();
+	///
+	/// assert_eq!(builder, LazyRegexBuilder::new(r"^abc$").case_insensitive(true).multi_line(true).clone());
+	/// assert!(builder.build().unwrap().is_match("x\nABC"));
+	/// ```
+	pub fn from_inline_flags(pattern: &str) -> Result<LazyRegexBuilder, Error> {
+		let mut builder = LazyRegexBuilder::new("");
+		let mut rest    = pattern;
+
+		while let Some((flags, source)) = split_flags(rest) {
+			let mut enable = true;
+
+			for flag in flags.chars() {
+				match flag {
+					'-' => enable = false,
+					'i' => builder.options.case_insensitive = enable,
+					'm' => builder.options.multi_line = enable,
+					's' => builder.options.dot_matches_new_line = enable,
+					'U' => builder.options.swap_greed = enable,
+					'x' => builder.options.ignore_whitespace = enable,
+					'u' => builder.options.unicode = enable,
+					_   => unreachable!(),
+				}
+			}
+
+			rest = source;
+		}
+
+		builder.source = rest.to_owned();
+		builder.check()?;
+
+		Ok(builder)
+	}
+
+	/// Get the pattern to compile, wrapping the source if it's anchored.
+	fn pattern(&self) -> Cow<'_, str> {
+		if !self.anchored {
+			return self.source.as_str().into();
+		}
+
+		// A comment in the source would swallow the closing group in
+		// whitespace insensitive mode, so end it on its own line.
+		if self.options.ignore_whitespace {
+			format!("\\A(?:{}\n)\\z", self.source).into()
+		}
+		else {
+			format!("\\A(?:{})\\z", self.source).into()
 		}
 	}
 
+	/// Parse the pattern with the flags of the builder, normalized for
+	/// `LazyRegex::semantic_eq`.
+	fn hir(&self) -> Option<Hir> {
+		let options = &self.options;
+		let hir     = syntax::ParserBuilder::new()
+			.case_insensitive(options.case_insensitive)
+			.multi_line(options.multi_line)
+			.dot_matches_new_line(options.dot_matches_new_line)
+			.swap_greed(options.swap_greed)
+			.ignore_whitespace(options.ignore_whitespace)
+			.unicode(options.unicode)
+			.nest_limit(options.nest_limit)
+			.build()
+			.parse(&self.pattern())
+			.ok()?;
+
+		Some(normalize(hir))
+	}
+
 	/// Consume the builder and compile the regular expression.
 	///
 	/// Note that calling `as_str` on the resulting `Regex` will produce the
 	/// pattern given to `new` verbatim. Notably, it will not incorporate any
 	/// of the flags set on this builder.
 	pub fn build(&self) -> Result<LazyRegex, Error> {
-		if let Err(err) = syntax::Parser::new().parse(&self.source) {
+		self.check()?;
+		Ok(LazyRegex::from_builder(self.clone()))
+	}
+
+	/// Check the syntax of the source.
+	fn check(&self) -> Result<(), Error> {
+		let mut parser = syntax::ParserBuilder::new().nest_limit(self.options.nest_limit).build();
+
+		if let Err(err) = parser.parse(&self.source) {
 			return Err(Error::Syntax(err.to_string()));
 		}
 
-		Ok(LazyRegex::from(self.clone()))
+		Ok(())
+	}
+
+	/// Set whether the pattern has to match the whole text.
+	///
+	/// The source is wrapped in a non-capturing group anchored at the start
+	/// and end of the text, so capture group indices are not affected and the
+	/// anchors keep their meaning in multi-line mode. Note that `as_str` on
+	/// the compiled `Regex` will include the anchors.
+	pub fn anchor_pattern(&mut self, yes: bool) -> &mut LazyRegexBuilder {
+		self.anchored = yes;
+		self
 	}
 
 	/// Set the value for the case insensitive (`i`) flag.
@@ -195,6 +994,24 @@ impl LazyRegexBuilder {
 		self
 	}
 
+	/// Set the line terminator used by `^` and `$` in multi-line mode, the
+	/// default is `\n`.
+	///
+	/// Only ASCII bytes are allowed when Unicode mode is enabled, otherwise
+	/// building the regular expression will fail.
+	pub fn line_terminator(&mut self, byte: u8) -> &mut LazyRegexBuilder {
+		self.options.line_terminator = byte;
+		self
+	}
+
+	/// Set the maximum nesting depth of the pattern, the default is `250`.
+	///
+	/// Both the syntax check in `build` and the compilation use this limit.
+	pub fn nest_limit(&mut self, limit: u32) -> &mut LazyRegexBuilder {
+		self.options.nest_limit = limit;
+		self
+	}
+
 	/// Set the approximate size limit of the compiled regular expression.
 	///
 	/// This roughly corresponds to the number of bytes occupied by a single
@@ -220,9 +1037,19 @@ impl LazyRegexBuilder {
 	}
 }
 
+/// Every field but the source is compared, including whether the pattern is
+/// anchored.
+impl FlagEq for LazyRegexBuilder {
+	fn flags_eq(&self, other: &LazyRegexBuilder) -> bool {
+		self.options.flags_eq(&other.options) && self.anchored == other.anchored
+	}
+}
+
 #[cfg(test)]
 mod test {
-	use crate::{LazyRegex, LazyRegexBuilder};
+	use regex::{Regex, RegexBuilder};
+	use crate::{LazyRegex, LazyRegexBuilder, LazyRegexPool, FlagEq};
+	use super::Fixed;
 
 	#[test]
 	fn new() {
@@ -253,4 +1080,265 @@ mod test {
 		assert!(re.is_match("1234"));
 		assert!(re.is_match("1234"));
 	}
+
+	#[test]
+	fn find_iter_collect() {
+		let matches = {
+			let re = LazyRegex::new(r"[a-z]+").unwrap();
+			re.find_iter_collect("abc 123 de f")
+		};
+
+		assert_eq!(matches, [(0, 3), (8, 10), (11, 12)]);
+		assert!(LazyRegex::new(r"\d").unwrap().find_iter_collect("abc").is_empty());
+	}
+
+	#[test]
+	fn captures_iter_collect() {
+		let (all, named) = {
+			let re = LazyRegex::new(r"(?P<k>[a-z]+):(\d+)?").unwrap();
+			(re.captures_iter_collect("ab:1 c: d"), re.captures_named_collect("ab:1 c: d"))
+		};
+
+		assert_eq!(all, [
+			vec![Some("ab:1".to_owned()), Some("ab".to_owned()), Some("1".to_owned())],
+			vec![Some("c:".to_owned()), Some("c".to_owned()), None],
+		]);
+
+		assert_eq!(named.len(), 2);
+		assert_eq!(named[1]["k"], "c");
+		assert_eq!(named[0].len(), 1);
+
+		assert!(LazyRegex::new(r"\d").unwrap().captures_iter_collect("abc").is_empty());
+	}
+
+	#[test]
+	fn checked() {
+		let re = LazyRegex::new(r"(\d)(\d)?").unwrap();
+
+		assert_eq!(re.find_checked("a1").unwrap().map(|m| m.range()), Some(1 .. 2));
+		assert_eq!(re.find_checked("abc").unwrap(), None);
+		assert!(re.is_match_checked("12").unwrap());
+		assert!(!re.is_match_checked("ab").unwrap());
+		assert_eq!(&re.captures_checked("x12").unwrap().unwrap()[2], "2");
+		assert!(re.captures_checked("x").unwrap().is_none());
+	}
+
+	#[test]
+	fn from_regex() {
+		let re = RegexBuilder::new("abc").case_insensitive(true).build().unwrap();
+		let re = LazyRegex::from(re);
+
+		assert!(re.is_match("ABC"));
+		assert_eq!(re.as_str(), "abc");
+	}
+
+	#[test]
+	fn line_terminator() {
+		let re = LazyRegexBuilder::new(r"^b$")
+			.multi_line(true).line_terminator(b'\x00').build().unwrap();
+
+		assert!(re.is_match("a\x00b\x00c"));
+		assert!(!re.is_match("a\nb\nc"));
+	}
+
+	#[test]
+	fn anchored() {
+		let re = LazyRegex::anchored(r"(a)|(b)").unwrap();
+		assert!(re.is_match("a"));
+		assert!(!re.is_match("ab"));
+		assert_eq!(&re.captures("b").unwrap()[2], "b");
+
+		let re = LazyRegexBuilder::new(r"a # comment")
+			.ignore_whitespace(true).multi_line(true).anchor_pattern(true).build().unwrap();
+		assert!(re.is_match("a"));
+		assert!(!re.is_match("a\na"));
+	}
+
+	#[test]
+	fn builder_repr() {
+		let re = LazyRegexBuilder::new(r"a b c").ignore_whitespace(true).unicode(false)
+			.case_insensitive(true).build().unwrap();
+
+		assert_eq!(re.to_builder_repr(), "(?ix-u)a b c");
+
+		let re = LazyRegex::from_builder_repr(&re.to_builder_repr()).unwrap();
+		assert!(re.is_match("ABC"));
+		assert_eq!(re.to_builder_repr(), "(?ix-u)a b c");
+
+		let re = LazyRegex::from_builder_repr(r"(?:a)b").unwrap();
+		assert_eq!(re.to_builder_repr(), "(?:a)b");
+
+		assert!(LazyRegex::from_builder_repr(r"(?i)(").is_err());
+	}
+
+	#[test]
+	fn compile_time() {
+		let re = LazyRegex::new(r"^\d+$").unwrap();
+		let other = re.clone();
+		assert_eq!(re.compile_time(), None);
+
+		assert!(other.is_match("1234"));
+		assert!(re.compile_time().is_some());
+		assert_eq!(re.compile_time(), other.compile_time());
+
+		let re = LazyRegex::from(regex::Regex::new("a").unwrap());
+		assert!(re.is_match("a"));
+		assert_eq!(re.compile_time(), None);
+	}
+
+	#[test]
+	fn replace_checked() {
+		use crate::ReplacementError;
+
+		let re = LazyRegex::new(r"(?P<k>\w+)=(\w+)").unwrap();
+		assert_eq!(re.replace_checked("a=1 b=2", "$2=${k}").unwrap(), "1=a 2=b");
+		assert_eq!(re.replace_checked("nothing", "$$5").unwrap(), "nothing");
+		assert_eq!(re.replace_checked("a=1", "$v"),
+			Err(ReplacementError::InvalidName("v".into())));
+	}
+
+	#[test]
+	fn into_builder() {
+		let re = LazyRegexBuilder::new("abc").multi_line(true).build().unwrap();
+		assert!(!re.is_match("ABC"));
+
+		let mut builder: LazyRegexBuilder = re.into();
+		assert_eq!(builder, *LazyRegexBuilder::new("abc").multi_line(true));
+
+		let re = builder.case_insensitive(true).build().unwrap();
+		assert!(re.is_match("ABC"));
+	}
+
+	#[test]
+	fn semantic_eq() {
+		let eq = |a: &str, b: &str| LazyRegex::new(a).unwrap().semantic_eq(&LazyRegex::new(b).unwrap());
+
+		assert!(eq(r"[ab]", r"(?:a|b)"));
+		assert!(eq(r"[a]x", r"ax"));
+		assert!(eq(r"(?:abc)+", r"(?:abc)+"));
+		assert!(eq(r"(?:a|[b-d])z", r"[a-d]z"));
+		assert!(eq(r"(?i)a", r"[aA]"));
+
+		assert!(!eq(r"(a|b)", r"[ab]"));
+		assert!(!eq(r"ab|c", r"[abc]"));
+		assert!(!eq(r"a+", r"a*"));
+
+		let anchored = LazyRegexBuilder::new("a").anchor_pattern(true).build().unwrap();
+		assert!(anchored.semantic_eq(&LazyRegex::new(r"\Aa\z").unwrap()));
+		assert!(!anchored.semantic_eq(&LazyRegex::new("a").unwrap()));
+	}
+
+	#[test]
+	fn matches_entire() {
+		let re = LazyRegex::new(r"[a-z]+").unwrap();
+		assert!(re.matches_entire("abc"));
+		assert!(!re.matches_entire("abc1"));
+		assert!(!re.matches_entire("1abc"));
+		assert!(!re.matches_entire(""));
+
+		assert!(LazyRegex::new("a*").unwrap().matches_entire(""));
+		assert!(!LazyRegex::new("a|ab").unwrap().matches_entire("ab"));
+		assert!(LazyRegex::anchored("a|ab").unwrap().is_match("ab"));
+	}
+
+	#[test]
+	fn flags_eq() {
+		let a = LazyRegexBuilder::new("a").multi_line(true).size_limit(1024).clone();
+		let b = LazyRegexBuilder::new("b").multi_line(true).size_limit(1024).clone();
+
+		assert!(a.flags_eq(&b));
+		assert!(!a.flags_eq(b.clone().size_limit(2048)));
+		assert!(!a.flags_eq(b.clone().anchor_pattern(true)));
+		assert!(!a.flags_eq(b.clone().line_terminator(b'\0')));
+		assert_ne!(a, b);
+	}
+
+	#[test]
+	fn count_matches() {
+		let re = LazyRegex::new(r"a*").unwrap();
+
+		assert_eq!(re.count_matches("baab"), 3);
+		assert_eq!(re.count_matches_max("baab", 0), 0);
+		assert_eq!(re.count_matches_max("baab", 2), 2);
+		assert_eq!(re.count_matches_max("baab", usize::MAX), 3);
+	}
+
+	#[test]
+	fn into_regex_builder() {
+		let lazy = LazyRegexBuilder::new(r"a.b").dot_matches_new_line(true).anchor_pattern(true).clone();
+		let re   = RegexBuilder::from(lazy.clone()).build().unwrap();
+
+		assert!(re.is_match("a\nb"));
+		assert!(!re.is_match("a\nbc"));
+		assert_eq!(re.as_str(), lazy.build().unwrap().as_str());
+	}
+
+	#[test]
+	fn shortcuts() {
+		let deep = format!("{}a{}", "(".repeat(300), ")".repeat(300));
+
+		assert!(LazyRegex::new(&deep).is_err());
+		assert!(LazyRegex::with_nest_limit(&deep, 1000).unwrap().is_match("a"));
+		assert!(LazyRegex::with_nest_limit("(a)", 0).is_err());
+
+		assert!(LazyRegex::case_insensitive("abc").unwrap().is_match("xABC"));
+		assert!(LazyRegex::multi_line("^abc$").unwrap().is_match("x\nabc"));
+		assert!(LazyRegex::case_insensitive("(").is_err());
+	}
+
+	#[test]
+	fn pool() {
+		let pool  = LazyRegexPool::new(r"\d+", 3).unwrap();
+		let other = pool.clone();
+
+		let first = pool.get() as *const Regex;
+		assert_ne!(other.get() as *const Regex, first);
+		assert!(pool.get().is_match("42"));
+		assert_eq!(other.get() as *const Regex, first);
+
+		assert_eq!(LazyRegexPool::new("a", 0).unwrap().size(), 1);
+		assert!(LazyRegexPool::new("(", 2).is_err());
+	}
+
+	#[test]
+	fn is_literal_match() {
+		let cases = [
+			LazyRegex::new("hello").unwrap(),
+			LazyRegex::new(r"^hello\.$").unwrap(),
+			LazyRegexBuilder::new("hello").anchor_pattern(true).build().unwrap(),
+			LazyRegex::case_insensitive("hello").unwrap(),
+			LazyRegex::new("hel+o").unwrap(),
+			LazyRegex::multi_line("^hello$").unwrap(),
+		];
+
+		for text in ["hello", "hello.", "Hello", "helllo", "say\nhello\n", ""] {
+			for re in &cases {
+				assert_eq!(re.is_literal_match(text), re.is_match(text), "{:?} on {:?}", re.as_str(), text);
+			}
+		}
+
+		assert!(matches!(*cases[0].fixed.get().unwrap(), Some(Fixed::Contains(_))));
+		assert!(matches!(*cases[1].fixed.get().unwrap(), Some(Fixed::Equals(_))));
+		assert!(matches!(*cases[2].fixed.get().unwrap(), Some(Fixed::Equals(_))));
+		assert!(cases[3].fixed.get().unwrap().is_none());
+		assert!(cases[5].fixed.get().unwrap().is_none());
+	}
+
+	#[test]
+	fn from_inline_flags() {
+		let builder = LazyRegexBuilder::from_inline_flags("(?i)(?x-u) a b c ").unwrap();
+		let expect  = LazyRegexBuilder::new(" a b c ")
+			.case_insensitive(true)
+			.ignore_whitespace(true)
+			.unicode(false)
+			.clone();
+
+		assert_eq!(builder, expect);
+		assert!(builder.build().unwrap().is_match("ABC"));
+
+		assert_eq!(LazyRegexBuilder::from_inline_flags("(?i)a(?m)b").unwrap(),
+			LazyRegexBuilder::new("a(?m)b").case_insensitive(true).clone());
+		assert_eq!(LazyRegexBuilder::from_inline_flags("(?:a)").unwrap(), LazyRegexBuilder::new("(?:a)"));
+		assert!(LazyRegexBuilder::from_inline_flags("(?i)(").is_err());
+	}
 }
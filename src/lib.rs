@@ -21,17 +21,61 @@
 //! This crate provides a library for caching or lazily creating regular
 //! expressions.
 
+#![allow(clippy::redundant_field_names, clippy::tabs_in_doc_comments)]
+
 extern crate regex;
 extern crate regex_syntax as syntax;
-extern crate lru_cache as lru;
+extern crate linked_hash_map;
 extern crate oncemutex;
 
 pub use regex::{Regex, RegexBuilder, Error};
 
 mod options;
+pub use crate::options::{Options, RegexFlags, FlagEq};
+
+mod clock;
+pub use crate::clock::{Clock, SystemClock};
+
+mod guard;
+pub use crate::guard::{PatternLimits, PatternError, check_pattern};
+
+mod replace;
+pub use crate::replace::ReplacementError;
+
+mod captures;
+pub use crate::captures::{OwnedCaptures, OwnedMatch};
+
+mod policy;
+pub use crate::policy::{EvictionPolicy, LruPolicy, LfuPolicy};
 
 mod cache;
-pub use crate::cache::{RegexCache, CachedRegex, CachedRegexBuilder};
+pub use crate::cache::{RegexCache, MetaRegexCache, RegexCacheBuilder, GenericRegexCache, DEFAULT_CAPACITY, RegexCacheKey, CacheStats, EntryStats, CacheError, Collision, EvictionReason, CacheSnapshot, CachedRegex, CachedRegexBuilder};
+
+mod fixed;
+pub use crate::fixed::StaticRegexCache;
+
+mod shared;
+pub use crate::shared::SharedRegexCache;
+
+mod rwlock;
+pub use crate::rwlock::RwLockRegexCache;
+
+mod unified;
+pub use crate::unified::UnifiedRegexCache;
+
+mod local;
+pub use crate::local::{ThreadLocalRegexCache, TieredStats};
+
+mod sharded;
+pub use crate::sharded::ShardedRegexCache;
 
 mod lazy;
-pub use crate::lazy::{LazyRegex, LazyRegexBuilder};
+pub use crate::lazy::{LazyRegex, LazyRegexBuilder, LazyRegexPool, MatchError};
+
+pub mod bytes;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+#[cfg(feature = "macros")]
+pub use regex_cache_macros::const_lazy_regex;
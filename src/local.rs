@@ -0,0 +1,285 @@
+// Copyright 2017 1aim GmbH
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is furnished to do
+// so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, AtomicU64, Ordering};
+
+use regex::{Regex, Error};
+use linked_hash_map::LinkedHashMap;
+use crate::cache::CacheStats;
+use crate::shared::SharedRegexCache;
+
+/// A unique identifier for each family of `ThreadLocalRegexCache` clones.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+	/// The first level of every `ThreadLocalRegexCache` used on this thread.
+	static LOCAL: RefCell<HashMap<usize, Tier>> = RefCell::new(HashMap::new());
+}
+
+/// The first level of a cache on a single thread.
+struct Tier {
+	generation: u64,
+	map:        LinkedHashMap<String, Regex>,
+}
+
+/// Hit and miss statistics of a `ThreadLocalRegexCache`.
+#[derive(Copy, Clone, Eq, PartialEq, Default, Debug)]
+pub struct TieredStats {
+	/// The number of lookups answered by the thread-local cache, without
+	/// locking the shared one.
+	pub l1_hits: u64,
+
+	/// The statistics of the shared cache, for the lookups that missed the
+	/// thread-local one.
+	pub l2: CacheStats,
+}
+
+/// A small per-thread LRU cache in front of a `SharedRegexCache`.
+///
+/// Each thread first looks in its own cache, which needs no locking, and
+/// only falls back to the shared cache on a miss, keeping a clone of the
+/// result. Cloning the handle is cheap, and all the clones share the same
+/// caches.
+///
+/// Invalidation is best-effort: entries evicted from the shared cache stay
+/// in the thread-local caches until they're pushed out, which is harmless
+/// since a compiled `Regex` never changes. Going through `clear` or
+/// `recompile` on this handle flushes the thread-local caches of every
+/// thread on their next lookup, changes made directly on the shared cache
+/// don't. The thread-local caches of other threads are only freed when the
+/// threads exit.
+///
+/// # Example
+///
+/// ```
+/// # use regex_cache::SharedRegexCache;
+/// let cache = SharedRegexCache::new(100).with_thread_local(16);
+///
+/// assert!(cache.is_match(r"^\d+$", "1234").unwrap());
+/// assert!(cache.is_match(r"^\d+$", "5678").unwrap());
+/// assert_eq!(cache.stats().l1_hits, 1);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ThreadLocalRegexCache {
+	shared:     SharedRegexCache,
+	capacity:   usize,
+	id:         usize,
+	generation: Arc<AtomicU64>,
+	l1_hits:    Arc<AtomicU64>,
+}
+
+impl ThreadLocalRegexCache {
+	/// Put a thread-local cache holding up to `l1_capacity` entries per
+	/// thread in front of the given shared cache.
+	pub fn new(shared: SharedRegexCache, l1_capacity: usize) -> ThreadLocalRegexCache {
+		ThreadLocalRegexCache {
+			shared:     shared,
+			capacity:   l1_capacity,
+			id:         NEXT_ID.fetch_add(1, Ordering::Relaxed),
+			generation: Arc::new(AtomicU64::new(0)),
+			l1_hits:    Arc::new(AtomicU64::new(0)),
+		}
+	}
+
+	/// Get the shared cache behind the thread-local ones.
+	pub fn shared(&self) -> &SharedRegexCache {
+		&self.shared
+	}
+
+	/// Get the maximum number of entries in the cache of each thread.
+	pub fn l1_capacity(&self) -> usize {
+		self.capacity
+	}
+
+	/// Get a compiled regular expression, looking in the cache of the current
+	/// thread first.
+	///
+	/// See `RegexCache::compile`.
+	pub fn compile(&self, source: &str) -> Result<Regex, Error> {
+		if let Some(re) = self.local(|map| map.get_refresh(source).cloned()) {
+			self.l1_hits.fetch_add(1, Ordering::Relaxed);
+			return Ok(re);
+		}
+
+		let re = self.shared.compile(source)?;
+
+		self.local(|map| {
+			map.insert(source.to_owned(), re.clone());
+
+			while map.len() > self.capacity {
+				map.pop_front();
+			}
+		});
+
+		Ok(re)
+	}
+
+	/// Check if the regular expression for the given source matches `text`.
+	///
+	/// See `RegexCache::is_match`.
+	pub fn is_match(&self, source: &str, text: &str) -> Result<bool, Error> {
+		Ok(self.compile(source)?.is_match(text))
+	}
+
+	/// Compile the regular expression for the given source again, flushing the
+	/// thread-local caches.
+	///
+	/// See `RegexCache::recompile`.
+	pub fn recompile(&self, source: &str) -> Result<Regex, Error> {
+		let re = self.shared.recompile(source);
+		self.invalidate();
+		re
+	}
+
+	/// Remove all the entries from the shared cache and flush the
+	/// thread-local caches.
+	pub fn clear(&self) {
+		self.shared.clear();
+		self.invalidate();
+	}
+
+	/// Flush the thread-local caches of every thread, which happens lazily
+	/// on their next lookup.
+	pub fn invalidate(&self) {
+		self.generation.fetch_add(1, Ordering::Release);
+	}
+
+	/// Get the statistics of both levels.
+	pub fn stats(&self) -> TieredStats {
+		TieredStats {
+			l1_hits: self.l1_hits.load(Ordering::Relaxed),
+			l2:      self.shared.stats(),
+		}
+	}
+
+	/// Run the given function on the cache of the current thread, flushing
+	/// it first if it was invalidated.
+	fn local<T, F: FnOnce(&mut LinkedHashMap<String, Regex>) -> T>(&self, f: F) -> T {
+		let generation = self.generation.load(Ordering::Acquire);
+
+		LOCAL.with(|local| {
+			let mut local = local.borrow_mut();
+			let     tier  = local.entry(self.id).or_insert_with(|| Tier {
+				generation: generation,
+				map:        LinkedHashMap::new(),
+			});
+
+			if tier.generation != generation {
+				tier.generation = generation;
+				tier.map.clear();
+			}
+
+			f(&mut tier.map)
+		})
+	}
+}
+
+impl Drop for ThreadLocalRegexCache {
+	fn drop(&mut self) {
+		// The last clone frees the cache of the current thread at least.
+		if Arc::strong_count(&self.generation) == 1 {
+			LOCAL.try_with(|local| local.borrow_mut().remove(&self.id)).ok();
+		}
+	}
+}
+
+impl SharedRegexCache {
+	/// Put a thread-local cache holding up to `l1_capacity` entries per
+	/// thread in front of this cache.
+	///
+	/// See `ThreadLocalRegexCache`.
+	pub fn with_thread_local(&self, l1_capacity: usize) -> ThreadLocalRegexCache {
+		ThreadLocalRegexCache::new(self.clone(), l1_capacity)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::thread;
+	use crate::shared::SharedRegexCache;
+
+	#[test]
+	fn levels() {
+		let shared = SharedRegexCache::new(10);
+		let cache  = shared.with_thread_local(2);
+
+		cache.compile("a").unwrap();
+		cache.compile("a").unwrap();
+		cache.compile("b").unwrap();
+		cache.compile("c").unwrap();
+
+		// "a" fell out of the thread-local cache but is still shared.
+		assert!(cache.is_match("a", "a").unwrap());
+
+		let stats = cache.stats();
+		assert_eq!(stats.l1_hits, 1);
+		assert_eq!((stats.l2.hits, stats.l2.misses), (1, 3));
+
+		assert!(cache.compile("(").is_err());
+		assert_eq!(shared.len(), 3);
+	}
+
+	#[test]
+	fn invalidate() {
+		let shared = SharedRegexCache::new(10);
+		let cache  = shared.with_thread_local(4);
+
+		shared.configure("abc", |b| b.case_insensitive(true)).unwrap();
+		assert!(cache.is_match("abc", "ABC").unwrap());
+
+		cache.recompile("abc").unwrap();
+		assert!(!cache.is_match("abc", "ABC").unwrap());
+
+		cache.clear();
+		assert!(shared.is_empty());
+		cache.compile("abc").unwrap();
+		assert_eq!(cache.stats().l1_hits, 0);
+	}
+
+	#[test]
+	fn threads() {
+		let cache   = SharedRegexCache::new(16).with_thread_local(8);
+		let handles = (0 .. 8).map(|i| {
+			let cache = cache.clone();
+
+			thread::spawn(move || {
+				for j in 0 .. 100 {
+					let digit = ((b'0' + (i + j) % 4) as char).to_string();
+
+					assert!(cache.is_match(r"^\d+$", "1234").unwrap());
+					assert!(cache.is_match(&format!("^{}+$", digit), &digit.repeat(3)).unwrap());
+				}
+			})
+		}).collect::<Vec<_>>();
+
+		for handle in handles {
+			handle.join().unwrap();
+		}
+
+		let stats = cache.stats();
+		assert_eq!(stats.l1_hits + stats.l2.hits + stats.l2.misses, 8 * 100 * 2);
+
+		// Each thread only goes to the shared cache once per pattern.
+		assert!(stats.l2.hits + stats.l2.misses <= 8 * 5);
+	}
+}
@@ -18,22 +18,60 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-use regex::RegexBuilder;
+use std::ops::{BitOr, BitOrAssign};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::env;
+use regex::{RegexBuilder, Error, bytes};
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+/// The options used to compile a regular expression.
+///
+/// The fields mirror the setters on `RegexBuilder`.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
 pub struct Options {
+	/// The case insensitive (`i`) flag.
 	pub case_insensitive: bool,
+
+	/// The multi-line matching (`m`) flag.
 	pub multi_line: bool,
+
+	/// The any character (`s`) flag.
 	pub dot_matches_new_line: bool,
+
+	/// The greedy swap (`U`) flag.
 	pub swap_greed: bool,
+
+	/// The ignore whitespace (`x`) flag.
 	pub ignore_whitespace: bool,
+
+	/// The Unicode (`u`) flag.
 	pub unicode: bool,
+
+	/// The byte treated as a line terminator by `^` and `$` in multi-line
+	/// mode.
+	pub line_terminator: u8,
+
+	/// The maximum nesting depth of the pattern, `250` by default like the
+	/// `regex` crate.
+	pub nest_limit: u32,
+
+	/// The approximate size limit of the compiled regular expression.
+	///
+	/// Defaults to the `REGEX_SIZE_LIMIT` environment variable if it's set,
+	/// or 10MB otherwise.
 	pub size_limit: usize,
+
+	/// The approximate size of the cache used by the DFA.
+	///
+	/// Defaults to the `REGEX_DFA_SIZE_LIMIT` environment variable if it's
+	/// set, or 2MB otherwise.
 	pub dfa_size_limit: usize,
 }
 
 impl Default for Options {
 	fn default() -> Self {
+		let (size_limit, dfa_size_limit) = default_limits();
+
 		Options {
 			case_insensitive: false,
 			multi_line: false,
@@ -41,22 +79,333 @@ impl Default for Options {
 			swap_greed: false,
 			ignore_whitespace: false,
 			unicode: true,
-			size_limit: 10 * (1 << 20),
-			dfa_size_limit: 2 * (1 << 20),
+			line_terminator: b'\n',
+			nest_limit: DEFAULT_NEST_LIMIT,
+			size_limit: size_limit,
+			dfa_size_limit: dfa_size_limit,
 		}
 	}
 }
 
+/// The default nesting limit of the `regex` crate.
+pub(crate) const DEFAULT_NEST_LIMIT: u32 = 250;
+
+/// Get the default size limits, reading the environment only once per
+/// process.
+fn default_limits() -> (usize, usize) {
+	static LIMITS: OnceLock<(usize, usize)> = OnceLock::new();
+
+	*LIMITS.get_or_init(|| (
+		limit_from_env("REGEX_SIZE_LIMIT", 10 * (1 << 20)),
+		limit_from_env("REGEX_DFA_SIZE_LIMIT", 2 * (1 << 20)),
+	))
+}
+
+/// Read a limit in bytes from the given environment variable, falling back to
+/// the default if it's missing or invalid.
+fn limit_from_env(name: &str, default: usize) -> usize {
+	env::var(name).ok()
+		.and_then(|value| value.trim().parse().ok())
+		.unwrap_or(default)
+}
+
+/// Apply the options to any of the `regex` builders, which all have the same
+/// setters.
+macro_rules! define {
+	($options:expr, $builder:expr) => (
+		$builder
+			.case_insensitive($options.case_insensitive)
+			.multi_line($options.multi_line)
+			.dot_matches_new_line($options.dot_matches_new_line)
+			.swap_greed($options.swap_greed)
+			.ignore_whitespace($options.ignore_whitespace)
+			.unicode($options.unicode)
+			.line_terminator($options.line_terminator)
+			.nest_limit($options.nest_limit)
+			.size_limit($options.size_limit)
+			.dfa_size_limit($options.dfa_size_limit)
+	)
+}
+
 impl Options {
+	/// Apply the options to the given builder.
+	pub fn define<'b>(&self, builder: &'b mut RegexBuilder) -> &'b mut RegexBuilder {
+		define!(self, builder)
+	}
+
+	/// Get these options with the flags replaced by the given ones, keeping
+	/// the limits and the line terminator.
+	pub(crate) fn with_flags(self, flags: RegexFlags) -> Options {
+		Options {
+			line_terminator: self.line_terminator,
+			nest_limit:      self.nest_limit,
+			size_limit:      self.size_limit,
+			dfa_size_limit:  self.dfa_size_limit,
+			.. Options::from(flags)
+		}
+	}
+
+	/// Apply the options to the given bytes builder.
+	pub(crate) fn define_bytes<'b>(&self, builder: &'b mut bytes::RegexBuilder) -> &'b mut bytes::RegexBuilder {
+		define!(self, builder)
+	}
+
+	/// Apply the options to the given bytes set builder.
+	pub(crate) fn define_bytes_set<'b>(&self, builder: &'b mut bytes::RegexSetBuilder) -> &'b mut bytes::RegexSetBuilder {
+		define!(self, builder)
+	}
+}
+
+/// Compare the way two regular expressions are compiled, regardless of their
+/// patterns.
+///
+/// This is handy to group patterns that can share the same builder, or to
+/// check that two differently written patterns are compiled the same way.
+///
+/// # Example
+///
+/// ```
+/// # use regex_cache::{LazyRegexBuilder, FlagEq};
+/// let a = LazyRegexBuilder::new("abc").case_insensitive(true).clone();
+/// let b = LazyRegexBuilder::new("def").case_insensitive(true).clone();
+/// let c = LazyRegexBuilder::new("abc").clone();
+///
+/// assert!(a.flags_eq(&b));
+/// assert!(!a.flags_eq(&c));
+/// ```
+pub trait FlagEq {
+	/// Check if both are compiled with the same flags and options.
+	fn flags_eq(&self, other: &Self) -> bool;
+}
+
+impl FlagEq for Options {
+	fn flags_eq(&self, other: &Options) -> bool {
+		self == other
+	}
+}
+
+/// A set of boolean flags used to compile a regular expression.
+///
+/// # Example
+///
+/// ```
+/// # use regex_cache::RegexFlags;
+/// let flags = RegexFlags::default() | RegexFlags::CASE_INSENSITIVE;
+///
+/// assert!(flags.contains(RegexFlags::CASE_INSENSITIVE));
+/// assert!(flags.contains(RegexFlags::UNICODE));
+/// ```
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct RegexFlags(u8);
+
+impl RegexFlags {
+	/// The case insensitive (`i`) flag.
+	pub const CASE_INSENSITIVE: RegexFlags = RegexFlags(1 << 0);
+
+	/// The multi-line matching (`m`) flag.
+	pub const MULTI_LINE: RegexFlags = RegexFlags(1 << 1);
+
+	/// The any character (`s`) flag.
+	pub const DOT_MATCHES_NEW_LINE: RegexFlags = RegexFlags(1 << 2);
+
+	/// The greedy swap (`U`) flag.
+	pub const SWAP_GREED: RegexFlags = RegexFlags(1 << 3);
+
+	/// The ignore whitespace (`x`) flag.
+	pub const IGNORE_WHITESPACE: RegexFlags = RegexFlags(1 << 4);
+
+	/// The Unicode (`u`) flag.
+	pub const UNICODE: RegexFlags = RegexFlags(1 << 5);
+
+	/// Create a set with no flags, note that this disables Unicode.
+	pub fn empty() -> RegexFlags {
+		RegexFlags(0)
+	}
+
+	/// Get the raw bits of the set.
+	pub fn bits(&self) -> u8 {
+		self.0
+	}
+
+	/// Check if all the given flags are set.
+	pub fn contains(&self, flags: RegexFlags) -> bool {
+		self.0 & flags.0 == flags.0
+	}
+
+	/// Set or clear the given flags.
+	pub fn set(&mut self, flags: RegexFlags, yes: bool) {
+		if yes {
+			self.0 |= flags.0;
+		}
+		else {
+			self.0 &= !flags.0;
+		}
+	}
+
+	/// Apply the flags to the given builder.
 	pub fn define<'b>(&self, builder: &'b mut RegexBuilder) -> &'b mut RegexBuilder {
 		builder
-			.case_insensitive(self.case_insensitive)
-			.multi_line(self.multi_line)
-			.dot_matches_new_line(self.dot_matches_new_line)
-			.swap_greed(self.swap_greed)
-			.ignore_whitespace(self.ignore_whitespace)
-			.unicode(self.unicode)
-			.size_limit(self.size_limit)
-			.dfa_size_limit(self.dfa_size_limit)
+			.case_insensitive(self.contains(RegexFlags::CASE_INSENSITIVE))
+			.multi_line(self.contains(RegexFlags::MULTI_LINE))
+			.dot_matches_new_line(self.contains(RegexFlags::DOT_MATCHES_NEW_LINE))
+			.swap_greed(self.contains(RegexFlags::SWAP_GREED))
+			.ignore_whitespace(self.contains(RegexFlags::IGNORE_WHITESPACE))
+			.unicode(self.contains(RegexFlags::UNICODE))
+	}
+
+	/// Wrap the given pattern in a group setting the flags inline, the result
+	/// compiled with the default flags behaves like the pattern compiled with
+	/// these flags.
+	pub(crate) fn inline(&self, source: &str) -> String {
+		const ENABLED: [(RegexFlags, char); 5] = [
+			(RegexFlags::CASE_INSENSITIVE, 'i'),
+			(RegexFlags::MULTI_LINE, 'm'),
+			(RegexFlags::DOT_MATCHES_NEW_LINE, 's'),
+			(RegexFlags::SWAP_GREED, 'U'),
+			(RegexFlags::IGNORE_WHITESPACE, 'x'),
+		];
+
+		let mut pattern = String::with_capacity(source.len() + 12);
+		pattern.push_str("(?");
+
+		for &(flag, name) in &ENABLED {
+			if self.contains(flag) {
+				pattern.push(name);
+			}
+		}
+
+		if !self.contains(RegexFlags::UNICODE) {
+			pattern.push_str("-u");
+		}
+
+		pattern.push(':');
+		pattern.push_str(source);
+
+		// A trailing comment would swallow the closing parenthesis.
+		if self.contains(RegexFlags::IGNORE_WHITESPACE) {
+			pattern.push('\n');
+		}
+
+		pattern.push(')');
+		pattern
+	}
+}
+
+/// Parse flags written like inline flags, as in `"imsxU"`, on top of the
+/// default ones, with the flags after a `-` being cleared.
+///
+/// # Example
+///
+/// ```
+/// # use regex_cache::RegexFlags;
+/// let flags = "i-u".parse::<RegexFlags>().unwrap();
+///
+/// assert_eq!(flags, RegexFlags::CASE_INSENSITIVE);
+/// assert!("iq".parse::<RegexFlags>().is_err());
+/// ```
+impl FromStr for RegexFlags {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<RegexFlags, Error> {
+		let mut flags  = RegexFlags::default();
+		let mut enable = true;
+
+		for c in s.chars() {
+			let flag = match c {
+				'-' if enable => {
+					enable = false;
+					continue;
+				}
+
+				'i' => RegexFlags::CASE_INSENSITIVE,
+				'm' => RegexFlags::MULTI_LINE,
+				's' => RegexFlags::DOT_MATCHES_NEW_LINE,
+				'U' => RegexFlags::SWAP_GREED,
+				'x' => RegexFlags::IGNORE_WHITESPACE,
+				'u' => RegexFlags::UNICODE,
+
+				_ =>
+					return Err(Error::Syntax(format!("unknown regex flag {:?} in {:?}", c, s))),
+			};
+
+			flags.set(flag, enable);
+		}
+
+		Ok(flags)
+	}
+}
+
+impl Default for RegexFlags {
+	fn default() -> Self {
+		RegexFlags::UNICODE
+	}
+}
+
+impl BitOr for RegexFlags {
+	type Output = RegexFlags;
+
+	fn bitor(self, other: RegexFlags) -> RegexFlags {
+		RegexFlags(self.0 | other.0)
+	}
+}
+
+impl BitOrAssign for RegexFlags {
+	fn bitor_assign(&mut self, other: RegexFlags) {
+		self.0 |= other.0;
+	}
+}
+
+impl From<Options> for RegexFlags {
+	fn from(options: Options) -> RegexFlags {
+		let mut flags = RegexFlags::empty();
+		flags.set(RegexFlags::CASE_INSENSITIVE, options.case_insensitive);
+		flags.set(RegexFlags::MULTI_LINE, options.multi_line);
+		flags.set(RegexFlags::DOT_MATCHES_NEW_LINE, options.dot_matches_new_line);
+		flags.set(RegexFlags::SWAP_GREED, options.swap_greed);
+		flags.set(RegexFlags::IGNORE_WHITESPACE, options.ignore_whitespace);
+		flags.set(RegexFlags::UNICODE, options.unicode);
+		flags
+	}
+}
+
+impl From<RegexFlags> for Options {
+	/// Get the default options with the given flags.
+	fn from(flags: RegexFlags) -> Options {
+		Options {
+			case_insensitive:     flags.contains(RegexFlags::CASE_INSENSITIVE),
+			multi_line:           flags.contains(RegexFlags::MULTI_LINE),
+			dot_matches_new_line: flags.contains(RegexFlags::DOT_MATCHES_NEW_LINE),
+			swap_greed:           flags.contains(RegexFlags::SWAP_GREED),
+			ignore_whitespace:    flags.contains(RegexFlags::IGNORE_WHITESPACE),
+			unicode:              flags.contains(RegexFlags::UNICODE),
+			.. Default::default()
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::env;
+	use super::{limit_from_env, RegexFlags};
+
+	#[test]
+	fn limits_from_env() {
+		assert_eq!(limit_from_env("REGEX_CACHE_TEST_MISSING_LIMIT", 42), 42);
+
+		env::set_var("REGEX_CACHE_TEST_LIMIT", " 1024 ");
+		assert_eq!(limit_from_env("REGEX_CACHE_TEST_LIMIT", 42), 1024);
+
+		env::set_var("REGEX_CACHE_TEST_LIMIT", "1MB");
+		assert_eq!(limit_from_env("REGEX_CACHE_TEST_LIMIT", 42), 42);
+	}
+
+	#[test]
+	fn parse_flags() {
+		assert_eq!("".parse::<RegexFlags>().unwrap(), RegexFlags::default());
+		assert_eq!("imsxU".parse::<RegexFlags>().unwrap().bits(), 0b111111);
+		assert_eq!("x-u".parse::<RegexFlags>().unwrap(), RegexFlags::IGNORE_WHITESPACE);
+
+		assert!("i-m-s".parse::<RegexFlags>().is_err());
+		assert!("(?i)".parse::<RegexFlags>().is_err());
 	}
 }
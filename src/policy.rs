@@ -0,0 +1,378 @@
+// Copyright 2017 1aim GmbH
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is furnished to do
+// so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::fmt;
+
+use regex::Regex;
+use linked_hash_map::LinkedHashMap;
+use crate::cache::RegexCacheKey;
+
+/// The storage of a `GenericRegexCache`, deciding in which order entries are
+/// evicted.
+///
+/// The cache takes care of making room before inserting, so implementations
+/// are never asked to hold more than `capacity` entries.
+pub trait EvictionPolicy {
+	/// Create an empty policy with the given capacity.
+	fn with_capacity(capacity: usize) -> Self where Self: Sized;
+
+	/// Insert an entry, replacing any entry with the same key.
+	fn insert(&mut self, key: RegexCacheKey, re: Regex);
+
+	/// Get the entry for the given key, recording the access.
+	fn get(&mut self, key: &RegexCacheKey) -> Option<&Regex>;
+
+	/// Get the entry for the given key without recording the access.
+	fn peek(&self, key: &RegexCacheKey) -> Option<&Regex>;
+
+	/// Get the entry that was last inserted or accessed through `get`.
+	fn most_recent(&self) -> Option<&Regex>;
+
+	/// Remove the entry for the given key.
+	fn remove(&mut self, key: &RegexCacheKey) -> Option<Regex>;
+
+	/// Get the number of entries.
+	fn len(&self) -> usize;
+
+	/// Check if there are no entries.
+	fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// Get the maximum number of entries.
+	fn capacity(&self) -> usize;
+
+	/// Change the maximum number of entries.
+	fn set_capacity(&mut self, capacity: usize);
+
+	/// Remove all the entries.
+	fn clear(&mut self);
+
+	/// Free the memory held for entries that were removed.
+	fn shrink_to_fit(&mut self) { }
+
+	/// Iterate over the entries in eviction order, the first one being the
+	/// next to evict.
+	fn entries(&self) -> Box<dyn Iterator<Item = (&RegexCacheKey, &Regex)> + '_>;
+
+	/// Get the next entry to evict, the first one of `entries`.
+	fn first(&self) -> Option<(&RegexCacheKey, &Regex)> {
+		self.entries().next()
+	}
+}
+
+/// Evict the least recently used entry first.
+///
+/// The keys are hashed with `S`, see `RegexCache::with_hasher`.
+pub struct LruPolicy<S: BuildHasher = RandomState> {
+	map:      LinkedHashMap<RegexCacheKey, Regex, S>,
+	capacity: usize,
+}
+
+impl<S: BuildHasher + Clone> Clone for LruPolicy<S> {
+	fn clone(&self) -> Self {
+		LruPolicy {
+			map:      self.map.clone(),
+			capacity: self.capacity,
+		}
+	}
+}
+
+impl<S: BuildHasher> fmt::Debug for LruPolicy<S> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("LruPolicy")
+			.field("map", &self.map)
+			.field("capacity", &self.capacity)
+			.finish()
+	}
+}
+
+impl<S: BuildHasher> LruPolicy<S> {
+	/// Create an empty policy with the given capacity, hashing the keys with
+	/// the given hasher.
+	pub fn with_hasher(capacity: usize, hasher: S) -> Self {
+		LruPolicy {
+			map:      LinkedHashMap::with_hasher(hasher),
+			capacity: capacity,
+		}
+	}
+}
+
+impl<S: BuildHasher + Default> EvictionPolicy for LruPolicy<S> {
+	fn with_capacity(capacity: usize) -> Self {
+		LruPolicy::with_hasher(capacity, S::default())
+	}
+
+	fn insert(&mut self, key: RegexCacheKey, re: Regex) {
+		self.map.insert(key, re);
+
+		while self.map.len() > self.capacity {
+			self.map.pop_front();
+		}
+	}
+
+	fn get(&mut self, key: &RegexCacheKey) -> Option<&Regex> {
+		self.map.get_refresh(key).map(|re| &*re)
+	}
+
+	fn peek(&self, key: &RegexCacheKey) -> Option<&Regex> {
+		self.map.get(key)
+	}
+
+	fn most_recent(&self) -> Option<&Regex> {
+		self.map.back().map(|(_, re)| re)
+	}
+
+	fn remove(&mut self, key: &RegexCacheKey) -> Option<Regex> {
+		self.map.remove(key)
+	}
+
+	fn len(&self) -> usize {
+		self.map.len()
+	}
+
+	fn capacity(&self) -> usize {
+		self.capacity
+	}
+
+	fn set_capacity(&mut self, capacity: usize) {
+		self.capacity = capacity;
+
+		while self.map.len() > self.capacity {
+			self.map.pop_front();
+		}
+	}
+
+	fn clear(&mut self) {
+		self.map.clear()
+	}
+
+	fn shrink_to_fit(&mut self) {
+		self.map.shrink_to_fit()
+	}
+
+	fn entries(&self) -> Box<dyn Iterator<Item = (&RegexCacheKey, &Regex)> + '_> {
+		Box::new(self.map.iter())
+	}
+
+	fn first(&self) -> Option<(&RegexCacheKey, &Regex)> {
+		self.map.front()
+	}
+}
+
+/// Evict the least frequently used entry first, and the least recently used
+/// among the ones used as often.
+///
+/// Unlike `LruPolicy` a burst of patterns used only once can't push out the
+/// patterns used all the time. The counts never decay, so a pattern that was
+/// hot in the past stays until hotter ones replace it.
+///
+/// # Example
+///
+/// ```
+/// # use regex_cache::{RegexCacheBuilder, LfuPolicy};
+/// let mut cache = RegexCacheBuilder::new().capacity(100).build_with_policy::<LfuPolicy>();
+///
+/// assert!(cache.compile(r"^\d+$").unwrap().is_match("1234"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct LfuPolicy {
+	entries:  Vec<LfuEntry>,
+	index:    HashMap<RegexCacheKey, usize>,
+	capacity: usize,
+	tick:     u64,
+	last:     usize,
+}
+
+#[derive(Clone, Debug)]
+struct LfuEntry {
+	key:    RegexCacheKey,
+	re:     Regex,
+	hits:   u64,
+	used:   u64,
+}
+
+impl LfuPolicy {
+	/// Record an access to the entry at the given position.
+	fn hit(&mut self, position: usize) {
+		self.tick += 1;
+		self.last  = position;
+
+		let entry = &mut self.entries[position];
+		entry.hits += 1;
+		entry.used  = self.tick;
+	}
+
+	/// Get the positions of the entries in eviction order.
+	fn order(&self) -> Vec<usize> {
+		let mut order = (0 .. self.entries.len()).collect::<Vec<_>>();
+		order.sort_unstable_by_key(|&i| (self.entries[i].hits, self.entries[i].used));
+		order
+	}
+
+	/// Remove the entries that don't fit in the capacity.
+	fn shrink(&mut self) {
+		while self.entries.len() > self.capacity {
+			let victim = self.order()[0];
+			let key    = self.entries[victim].key.clone();
+			self.remove(&key);
+		}
+	}
+}
+
+impl EvictionPolicy for LfuPolicy {
+	fn with_capacity(capacity: usize) -> Self {
+		LfuPolicy {
+			entries:  Vec::new(),
+			index:    HashMap::new(),
+			capacity: capacity,
+			tick:     0,
+			last:     0,
+		}
+	}
+
+	fn insert(&mut self, key: RegexCacheKey, re: Regex) {
+		if let Some(&position) = self.index.get(&key) {
+			self.entries[position].re = re;
+			self.hit(position);
+			return;
+		}
+
+		self.index.insert(key.clone(), self.entries.len());
+		self.entries.push(LfuEntry {
+			key:    key,
+			re:     re,
+			hits:   0,
+			used:   0,
+		});
+
+		self.hit(self.entries.len() - 1);
+		self.shrink();
+	}
+
+	fn get(&mut self, key: &RegexCacheKey) -> Option<&Regex> {
+		let position = *self.index.get(key)?;
+		self.hit(position);
+
+		Some(&self.entries[position].re)
+	}
+
+	fn peek(&self, key: &RegexCacheKey) -> Option<&Regex> {
+		self.index.get(key).map(|&position| &self.entries[position].re)
+	}
+
+	fn most_recent(&self) -> Option<&Regex> {
+		self.entries.get(self.last).map(|entry| &entry.re)
+	}
+
+	fn remove(&mut self, key: &RegexCacheKey) -> Option<Regex> {
+		let position = self.index.remove(key)?;
+		let entry    = self.entries.swap_remove(position);
+
+		// The last entry took the place of the removed one.
+		if let Some(moved) = self.entries.get(position) {
+			self.index.insert(moved.key.clone(), position);
+
+			if self.last == self.entries.len() {
+				self.last = position;
+			}
+		}
+
+		Some(entry.re)
+	}
+
+	fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	fn capacity(&self) -> usize {
+		self.capacity
+	}
+
+	fn set_capacity(&mut self, capacity: usize) {
+		self.capacity = capacity;
+		self.shrink();
+	}
+
+	fn clear(&mut self) {
+		self.entries.clear();
+		self.index.clear();
+	}
+
+	fn shrink_to_fit(&mut self) {
+		self.entries.shrink_to_fit();
+		self.index.shrink_to_fit();
+	}
+
+	fn entries(&self) -> Box<dyn Iterator<Item = (&RegexCacheKey, &Regex)> + '_> {
+		Box::new(self.order().into_iter().map(move |i|
+			(&self.entries[i].key, &self.entries[i].re)))
+	}
+
+	fn first(&self) -> Option<(&RegexCacheKey, &Regex)> {
+		self.entries.iter().min_by_key(|entry| (entry.hits, entry.used))
+			.map(|entry| (&entry.key, &entry.re))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::cache::{RegexCache, GenericRegexCache};
+	use crate::policy::LfuPolicy;
+
+	#[test]
+	fn lfu_order() {
+		let mut cache = GenericRegexCache::<LfuPolicy>::new(3);
+		cache.warm(["a", "b", "c", "a", "a", "b"]);
+		assert_eq!(cache.export_patterns(), ["c", "b", "a"]);
+
+		cache.compile("d").unwrap();
+		assert_eq!(cache.export_patterns(), ["d", "b", "a"]);
+
+		assert!(cache.compile("b").unwrap().is_match("b"));
+		assert_eq!(cache.len(), 3);
+
+		cache.resize(1);
+		assert_eq!(cache.export_patterns(), ["b"]);
+	}
+
+	#[test]
+	fn scan_resistance() {
+		let frequent = ["^f1$", "^f2$"];
+		let scan     = (0 .. 50).map(|i| format!("^scan{}$", i)).collect::<Vec<_>>();
+
+		let mut lru = RegexCache::new(4);
+		let mut lfu = GenericRegexCache::<LfuPolicy>::new(4);
+
+		for _ in 0 .. 10 {
+			lru.warm(frequent);
+			lfu.warm(frequent);
+		}
+
+		lru.warm(&scan);
+		lfu.warm(&scan);
+
+		assert!(frequent.iter().all(|f| lfu.contains(f)));
+		assert!(frequent.iter().all(|f| !lru.contains(f)));
+	}
+}
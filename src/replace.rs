@@ -0,0 +1,117 @@
+// Copyright 2017 1aim GmbH
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is furnished to do
+// so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use std::error;
+use std::fmt;
+
+use regex::Regex;
+
+/// An invalid group reference in a replacement string.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum ReplacementError {
+	/// The replacement refers to a group index the regular expression
+	/// doesn't have.
+	InvalidGroup(usize),
+
+	/// The replacement refers to a group name the regular expression doesn't
+	/// have.
+	InvalidName(String),
+}
+
+impl fmt::Display for ReplacementError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			ReplacementError::InvalidGroup(index) =>
+				write!(f, "replacement refers to missing group {}", index),
+
+			ReplacementError::InvalidName(ref name) =>
+				write!(f, "replacement refers to missing group {:?}", name),
+		}
+	}
+}
+
+impl error::Error for ReplacementError { }
+
+/// Check every group reference in the replacement exists in the regular
+/// expression, following the syntax of `Captures::expand`.
+pub(crate) fn check(re: &Regex, replacement: &str) -> Result<(), ReplacementError> {
+	let mut rest = replacement;
+
+	while let Some(dollar) = rest.find('$') {
+		rest = &rest[dollar + 1 ..];
+
+		if let Some(after) = rest.strip_prefix('$') {
+			rest = after;
+			continue;
+		}
+
+		let name = if let Some(braced) = rest.strip_prefix('{') {
+			match braced.find('}') {
+				Some(end) => {
+					rest = &braced[end + 1 ..];
+					&braced[.. end]
+				}
+
+				None =>
+					continue,
+			}
+		}
+		else {
+			let end = rest.find(|c: char| c != '_' && !c.is_ascii_alphanumeric())
+				.unwrap_or(rest.len());
+
+			let name = &rest[.. end];
+			rest = &rest[end ..];
+			name
+		};
+
+		if name.is_empty() {
+			continue;
+		}
+
+		if let Ok(index) = name.parse::<usize>() {
+			if index >= re.captures_len() {
+				return Err(ReplacementError::InvalidGroup(index));
+			}
+		}
+		else if !re.capture_names().any(|n| n == Some(name)) {
+			return Err(ReplacementError::InvalidName(name.to_owned()));
+		}
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use regex::Regex;
+	use super::{check, ReplacementError};
+
+	#[test]
+	fn references() {
+		let re = Regex::new(r"(?P<year>\d{4})-(\d{2})").unwrap();
+
+		assert_eq!(check(&re, "$0 $1 $2 ${year} $year $$99 $ ${"), Ok(()));
+		assert_eq!(check(&re, "$3"), Err(ReplacementError::InvalidGroup(3)));
+		assert_eq!(check(&re, "${99}x"), Err(ReplacementError::InvalidGroup(99)));
+		assert_eq!(check(&re, "$1a"), Err(ReplacementError::InvalidName("1a".into())));
+		assert_eq!(check(&re, "$month"), Err(ReplacementError::InvalidName("month".into())));
+	}
+}
@@ -0,0 +1,205 @@
+// Copyright 2017 1aim GmbH
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is furnished to do
+// so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use regex::{Regex, Error};
+use crate::cache::{RegexCache, CacheStats};
+
+/// The number of hits between two attempts at promoting an entry.
+const PROMOTE_EVERY: u64 = 64;
+
+/// A thread-safe handle to a `RegexCache` for workloads made mostly of hits.
+///
+/// Unlike `SharedRegexCache` hits only take a read lock, so threads looking up
+/// cached patterns don't wait on each other. The write lock is taken for
+/// misses, and every 64 hits to move the entry that was hit to the most
+/// recently used position, if no other thread holds the lock then. Recency is
+/// thus only tracked coarsely, a pattern that is hit often enough still stays
+/// away from eviction.
+///
+/// # Example
+///
+/// ```
+/// # use regex_cache::RwLockRegexCache;
+/// let cache = RwLockRegexCache::new(100);
+///
+/// assert!(cache.compile(r"^\d+$").unwrap().is_match("1234"));
+/// assert!(cache.is_match(r"^\d+$", "5678").unwrap());
+/// assert_eq!((cache.stats().hits, cache.stats().misses), (1, 1));
+/// ```
+#[derive(Clone, Debug)]
+pub struct RwLockRegexCache {
+	cache: Arc<RwLock<RegexCache>>,
+	hits:  Arc<AtomicU64>,
+}
+
+impl RwLockRegexCache {
+	/// Create a new shared LRU cache with the given size limit.
+	pub fn new(capacity: usize) -> RwLockRegexCache {
+		RwLockRegexCache::from(RegexCache::new(capacity))
+	}
+
+	/// Lock the cache for reading.
+	///
+	/// Only the methods taking `&self` are available, and they don't record
+	/// any access.
+	pub fn read(&self) -> RwLockReadGuard<'_, RegexCache> {
+		self.cache.read().unwrap()
+	}
+
+	/// Lock the cache for writing, giving access to the whole `RegexCache`
+	/// API.
+	///
+	/// Calling any other method on this handle while the guard is alive will
+	/// deadlock.
+	pub fn write(&self) -> RwLockWriteGuard<'_, RegexCache> {
+		self.cache.write().unwrap()
+	}
+
+	/// Refer to `RegexCache::compile`.
+	///
+	/// Hits only take the read lock, misses take the write lock.
+	pub fn compile(&self, source: &str) -> Result<Regex, Error> {
+		// The read lock has to be released before promoting the entry.
+		let hit = self.read().peek(source).cloned();
+
+		if let Some(re) = hit {
+			let hits = self.hits.fetch_add(1, Ordering::Relaxed) + 1;
+
+			if hits.is_multiple_of(PROMOTE_EVERY) {
+				if let Ok(mut cache) = self.cache.try_write() {
+					// The entry may have been evicted in the meantime.
+					if cache.contains(source) {
+						self.hits.fetch_sub(1, Ordering::Relaxed);
+						cache.compile(source)?;
+					}
+				}
+			}
+
+			return Ok(re);
+		}
+
+		self.write().compile(source).cloned()
+	}
+
+	/// Refer to `RegexCache::is_match`.
+	pub fn is_match(&self, source: &str, text: &str) -> Result<bool, Error> {
+		Ok(self.compile(source)?.is_match(text))
+	}
+
+	/// Get the statistics of the cache, including the hits that only took the
+	/// read lock.
+	pub fn stats(&self) -> CacheStats {
+		let mut stats = self.read().stats();
+		stats.hits += self.hits.load(Ordering::Relaxed);
+		stats
+	}
+
+	/// Refer to `RegexCache::reset_stats`.
+	pub fn reset_stats(&self) {
+		let mut cache = self.write();
+
+		cache.reset_stats();
+		self.hits.store(0, Ordering::Relaxed);
+	}
+
+	/// Get the number of entries in the cache.
+	pub fn len(&self) -> usize {
+		self.read().len()
+	}
+
+	/// Check if the cache is empty.
+	pub fn is_empty(&self) -> bool {
+		self.read().is_empty()
+	}
+
+	/// Get the maximum number of entries in the cache.
+	pub fn capacity(&self) -> usize {
+		self.read().capacity()
+	}
+
+	/// Remove all the entries from the cache.
+	pub fn clear(&self) {
+		self.write().clear()
+	}
+}
+
+impl From<RegexCache> for RwLockRegexCache {
+	fn from(cache: RegexCache) -> RwLockRegexCache {
+		RwLockRegexCache {
+			cache: Arc::new(RwLock::new(cache)),
+			hits:  Arc::new(AtomicU64::new(0)),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::thread;
+	use crate::RwLockRegexCache;
+
+	#[test]
+	fn promotion() {
+		let cache = RwLockRegexCache::new(2);
+		cache.compile("a").unwrap();
+		cache.compile("b").unwrap();
+
+		for _ in 0 .. 64 {
+			cache.compile("a").unwrap();
+		}
+
+		cache.compile("c").unwrap();
+		assert!(cache.read().contains("a"));
+		assert!(!cache.read().contains("b"));
+
+		let stats = cache.stats();
+		assert_eq!((stats.hits, stats.misses, stats.evictions), (64, 3, 1));
+	}
+
+	#[test]
+	fn stress() {
+		let cache   = RwLockRegexCache::new(16);
+		let handles = (0 .. 8).map(|i| {
+			let cache = cache.clone();
+
+			thread::spawn(move || {
+				for j in 0 .. 2000 {
+					// Mostly hot patterns, with a few cold ones evicting entries.
+					let n  = if j % 10 == 0 { 100 + (i * 31 + j) % 50 } else { (i + j) % 8 };
+					let re = cache.compile(&format!("^{}x+$", n)).unwrap();
+
+					assert!(re.is_match(&format!("{}xx", n)));
+					assert!(!re.is_match(&format!("{}yy", n)));
+				}
+			})
+		}).collect::<Vec<_>>();
+
+		for handle in handles {
+			handle.join().unwrap();
+		}
+
+		let stats = cache.stats();
+		assert_eq!(stats.hits + stats.misses, 8 * 2000);
+		assert!(cache.len() <= 16);
+		assert!(cache.compile("(").is_err());
+	}
+}
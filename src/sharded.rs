@@ -0,0 +1,287 @@
+// Copyright 2017 1aim GmbH
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is furnished to do
+// so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::Arc;
+use std::time::Duration;
+
+use regex::{Regex, RegexBuilder, Error};
+use crate::cache::{RegexCache, CacheStats};
+use crate::shared::SharedRegexCache;
+
+/// A thread-safe cache split in independently locked shards.
+///
+/// Patterns are assigned to a shard by their hash, so threads working on
+/// different patterns rarely contend on the same lock. Each shard is an LRU
+/// cache with its share of the total capacity, which means the recency order
+/// is only tracked within a shard.
+///
+/// # Example
+///
+/// ```
+/// # use regex_cache::ShardedRegexCache;
+/// let cache = ShardedRegexCache::new(1024, 8);
+///
+/// assert!(cache.compile(r"^\d+$").unwrap().is_match("1234"));
+/// assert_eq!(cache.stats().misses, 1);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ShardedRegexCache {
+	shards: Arc<[SharedRegexCache]>,
+	hasher: RandomState,
+}
+
+impl ShardedRegexCache {
+	/// Create a new sharded cache with the given total size limit split
+	/// across the given number of shards.
+	///
+	/// Every shard holds at least one entry, and a size limit of `0` means
+	/// the cache is unbounded like for `RegexCache::new`.
+	///
+	/// # Panics
+	///
+	/// If the number of shards is zero.
+	pub fn new(capacity: usize, shards: usize) -> ShardedRegexCache {
+		assert!(shards > 0, "a sharded cache needs at least one shard");
+
+		ShardedRegexCache {
+			shards: split(capacity, shards).map(SharedRegexCache::new).collect(),
+			hasher: RandomState::new(),
+		}
+	}
+
+	/// Get the number of shards.
+	pub fn shards(&self) -> usize {
+		self.shards.len()
+	}
+
+	/// Get the shard the given pattern belongs to.
+	pub fn shard(&self, source: &str) -> &SharedRegexCache {
+		let hash = self.hasher.hash_one(source);
+		&self.shards[(hash % self.shards.len() as u64) as usize]
+	}
+
+	/// Refer to `RegexCache::save`.
+	pub fn save(&self, re: Regex) -> Regex {
+		self.shard(re.as_str()).save(re)
+	}
+
+	/// Refer to `RegexCache::compile`.
+	pub fn compile(&self, source: &str) -> Result<Regex, Error> {
+		self.shard(source).compile(source)
+	}
+
+	/// Refer to `RegexCache::is_match`.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::ShardedRegexCache;
+	/// let cache = ShardedRegexCache::new(100, 4);
+	/// assert!(cache.is_match(r"^\d+$", "1234").unwrap());
+	/// ```
+	pub fn is_match(&self, source: &str, text: &str) -> Result<bool, Error> {
+		self.shard(source).is_match(source, text)
+	}
+
+	/// Refer to `RegexCache::recompile`.
+	pub fn recompile(&self, source: &str) -> Result<Regex, Error> {
+		self.shard(source).recompile(source)
+	}
+
+	/// Refer to `RegexCache::compile_with_hint`.
+	pub fn compile_with_hint(&self, source: &str, access_weight: u32) -> Result<Regex, Error> {
+		self.shard(source).compile_with_hint(source, access_weight)
+	}
+
+	/// Refer to `RegexCache::configure`.
+	pub fn configure<F>(&self, source: &str, f: F) -> Result<Regex, Error>
+		where F: FnOnce(&mut RegexBuilder) -> &mut RegexBuilder
+	{
+		self.shard(source).configure(source, f)
+	}
+
+	/// Refer to `RegexCache::get_or_insert_with`.
+	///
+	/// The function is called while the shard is locked.
+	pub fn get_or_insert_with<F>(&self, key: &str, f: F) -> Result<Regex, Error>
+		where F: FnOnce() -> Result<Regex, Error>
+	{
+		self.shard(key).get_or_insert_with(key, f)
+	}
+
+	/// Refer to `RegexCache::warm`.
+	pub fn warm<I, S>(&self, patterns: I) -> Vec<(String, Result<(), Error>)>
+		where I: IntoIterator<Item = S>,
+		      S: AsRef<str>
+	{
+		patterns.into_iter().map(|source| {
+			let source = source.as_ref();
+			let result = self.compile(source).map(|_| ());
+
+			(source.to_owned(), result)
+		}).collect()
+	}
+
+	/// Get the statistics of all the shards combined.
+	pub fn stats(&self) -> CacheStats {
+		self.shards.iter().map(SharedRegexCache::stats)
+			.fold(CacheStats::default(), CacheStats::combine)
+	}
+
+	/// Refer to `RegexCache::reset_stats`.
+	pub fn reset_stats(&self) {
+		for shard in self.shards.iter() {
+			shard.reset_stats();
+		}
+	}
+
+	/// Change the total number of entries the cache can hold, splitting it
+	/// across the shards.
+	pub fn resize(&self, capacity: usize) {
+		for (shard, capacity) in self.shards.iter().zip(split(capacity, self.shards.len())) {
+			shard.resize(capacity);
+		}
+	}
+
+	/// Refer to `RegexCache::set_ttl`.
+	pub fn set_ttl(&self, ttl: Duration) {
+		for shard in self.shards.iter() {
+			shard.set_ttl(ttl);
+		}
+	}
+
+	/// Refer to `RegexCache::purge_expired`.
+	pub fn purge_expired(&self) {
+		for shard in self.shards.iter() {
+			shard.purge_expired();
+		}
+	}
+
+	/// Get the patterns in the cache, shard by shard, each from the least to
+	/// the most recently used.
+	pub fn export_patterns(&self) -> Vec<String> {
+		self.shards.iter().flat_map(SharedRegexCache::export_patterns).collect()
+	}
+
+	/// Get the number of entries in the cache.
+	pub fn len(&self) -> usize {
+		self.shards.iter().map(SharedRegexCache::len).sum()
+	}
+
+	/// Check if the cache is empty.
+	pub fn is_empty(&self) -> bool {
+		self.shards.iter().all(SharedRegexCache::is_empty)
+	}
+
+	/// Get the maximum number of entries in the cache, which is `usize::MAX`
+	/// if it's unbounded.
+	pub fn capacity(&self) -> usize {
+		self.shards.iter().map(SharedRegexCache::capacity).fold(0, usize::saturating_add)
+	}
+
+	/// Remove all the entries from the cache.
+	pub fn clear(&self) {
+		for shard in self.shards.iter() {
+			shard.clear();
+		}
+	}
+}
+
+impl From<Vec<RegexCache>> for ShardedRegexCache {
+	/// Use the given caches as shards.
+	///
+	/// # Panics
+	///
+	/// If there are no caches.
+	fn from(caches: Vec<RegexCache>) -> ShardedRegexCache {
+		assert!(!caches.is_empty(), "a sharded cache needs at least one shard");
+
+		ShardedRegexCache {
+			shards: caches.into_iter().map(SharedRegexCache::from).collect(),
+			hasher: RandomState::new(),
+		}
+	}
+}
+
+/// Split the capacity as evenly as possible across the shards.
+///
+/// Every shard holds at least one entry, since a capacity of `0` means the
+/// shard is unbounded, which is also how an unbounded cache is split.
+fn split(capacity: usize, shards: usize) -> impl Iterator<Item = usize> {
+	(0 .. shards).map(move |i| {
+		if capacity == 0 {
+			0
+		}
+		else {
+			(capacity / shards + if i < capacity % shards { 1 } else { 0 }).max(1)
+		}
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use std::thread;
+	use crate::ShardedRegexCache;
+
+	#[test]
+	fn capacity() {
+		let cache = ShardedRegexCache::new(10, 4);
+		assert_eq!(cache.shards(), 4);
+		assert_eq!(cache.capacity(), 10);
+
+		cache.resize(6);
+		assert_eq!(cache.capacity(), 6);
+
+		cache.resize(3);
+		assert_eq!(cache.capacity(), 4);
+
+		let cache = ShardedRegexCache::new(0, 4);
+		assert_eq!(cache.capacity(), usize::MAX);
+	}
+
+	#[test]
+	fn stress() {
+		let cache   = ShardedRegexCache::new(64, 8);
+		let handles = (0 .. 8).map(|i| {
+			let cache = cache.clone();
+
+			thread::spawn(move || {
+				for j in 0 .. 200 {
+					let n  = (i * 7 + j) % 100;
+					let re = cache.compile(&format!("^{}x+$", n)).unwrap();
+
+					assert!(re.is_match(&format!("{}xx", n)));
+					assert!(!re.is_match(&format!("{}yy", n)));
+				}
+			})
+		}).collect::<Vec<_>>();
+
+		for handle in handles {
+			handle.join().unwrap();
+		}
+
+		let stats = cache.stats();
+		assert_eq!(stats.hits + stats.misses, 8 * 200);
+		assert!(cache.len() <= 64);
+		assert!(cache.compile("(").is_err());
+	}
+}
@@ -0,0 +1,379 @@
+// Copyright 2017 1aim GmbH
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is furnished to do
+// so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+use std::fmt;
+use std::time::Duration;
+
+use regex::{Regex, RegexBuilder, Error};
+use crate::cache::{RegexCache, CacheStats, compile_parallel};
+
+/// A thread-safe handle to a `RegexCache`.
+///
+/// Cloning the handle is cheap and all the clones share the same cache. The
+/// lock is only held for the duration of each call, the compiled regular
+/// expressions are returned as owned clones, which are cheap since `Regex`
+/// is reference counted internally.
+///
+/// # Example
+///
+/// ```
+/// # use std::thread;
+/// # use regex_cache::SharedRegexCache;
+/// let cache  = SharedRegexCache::new(100);
+/// let shared = cache.clone();
+///
+/// thread::spawn(move || {
+/// 	assert!(shared.compile(r"^\d+$").unwrap().is_match("1234"));
+/// }).join().unwrap();
+///
+/// assert_eq!(cache.stats().misses, 1);
+/// ```
+#[derive(Clone)]
+pub struct SharedRegexCache(Arc<Mutex<RegexCache>>);
+
+impl SharedRegexCache {
+	/// Create a new shared LRU cache with the given size limit.
+	pub fn new(capacity: usize) -> SharedRegexCache {
+		SharedRegexCache::from(RegexCache::new(capacity))
+	}
+
+	/// Lock the cache, giving access to the whole `RegexCache` API.
+	///
+	/// Calling any other method on this handle while the guard is alive will
+	/// deadlock.
+	pub fn lock(&self) -> MutexGuard<'_, RegexCache> {
+		self.0.lock().unwrap()
+	}
+
+	/// Lock the cache if it isn't locked already, without blocking.
+	pub fn try_lock(&self) -> Option<MutexGuard<'_, RegexCache>> {
+		match self.0.try_lock() {
+			Ok(cache) =>
+				Some(cache),
+
+			Err(TryLockError::Poisoned(err)) =>
+				panic!("{}", err),
+
+			Err(TryLockError::WouldBlock) =>
+				None,
+		}
+	}
+
+	/// Refer to `RegexCache::save`.
+	pub fn save(&self, re: Regex) -> Regex {
+		self.lock().save(re).clone()
+	}
+
+	/// Refer to `RegexCache::compile`.
+	pub fn compile(&self, source: &str) -> Result<Regex, Error> {
+		self.lock().compile(source).cloned()
+	}
+
+	/// Refer to `RegexCache::compile`, without blocking.
+	///
+	/// Returns `Ok(None)` when another thread holds the lock, in which case
+	/// the caller has to fall back to something else, like compiling the
+	/// regular expression without the cache or skipping the match.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::{Regex, SharedRegexCache};
+	/// let cache = SharedRegexCache::new(100);
+	///
+	/// let re = match cache.try_compile(r"^\d+$").unwrap() {
+	/// 	Some(re) => re,
+	/// 	None     => Regex::new(r"^\d+$").unwrap(),
+	/// };
+	///
+	/// assert!(re.is_match("1234"));
+	/// ```
+	pub fn try_compile(&self, source: &str) -> Result<Option<Regex>, Error> {
+		match self.try_lock() {
+			Some(mut cache) =>
+				cache.compile(source).map(|re| Some(re.clone())),
+
+			None =>
+				Ok(None),
+		}
+	}
+
+	/// Refer to `RegexCache::with`.
+	///
+	/// The function runs while the cache is locked, calling back into the
+	/// cache from it will deadlock.
+	pub fn with<R, F: FnOnce(&Regex) -> R>(&self, source: &str, f: F) -> Result<R, Error> {
+		self.lock().with(source, f)
+	}
+
+	/// Refer to `RegexCache::is_match`.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # use regex_cache::SharedRegexCache;
+	/// let cache = SharedRegexCache::new(100);
+	/// assert!(cache.is_match(r"^\d+$", "1234").unwrap());
+	/// ```
+	pub fn is_match(&self, source: &str, text: &str) -> Result<bool, Error> {
+		self.lock().is_match(source, text)
+	}
+
+	/// Refer to `RegexCache::is_match`, without blocking.
+	///
+	/// Returns `Ok(None)` when another thread holds the lock, see
+	/// `try_compile`.
+	pub fn try_is_match(&self, source: &str, text: &str) -> Result<Option<bool>, Error> {
+		Ok(self.try_compile(source)?.map(|re| re.is_match(text)))
+	}
+
+	/// Refer to `RegexCache::recompile`.
+	pub fn recompile(&self, source: &str) -> Result<Regex, Error> {
+		self.lock().recompile(source).cloned()
+	}
+
+	/// Refer to `RegexCache::compile_with_hint`.
+	pub fn compile_with_hint(&self, source: &str, access_weight: u32) -> Result<Regex, Error> {
+		self.lock().compile_with_hint(source, access_weight).cloned()
+	}
+
+	/// Refer to `RegexCache::configure`.
+	pub fn configure<F>(&self, source: &str, f: F) -> Result<Regex, Error>
+		where F: FnOnce(&mut RegexBuilder) -> &mut RegexBuilder
+	{
+		self.lock().configure(source, f).cloned()
+	}
+
+	/// Refer to `RegexCache::get_or_insert_with`.
+	///
+	/// The function is called while the cache is locked.
+	pub fn get_or_insert_with<F>(&self, key: &str, f: F) -> Result<Regex, Error>
+		where F: FnOnce() -> Result<Regex, Error>
+	{
+		self.lock().get_or_insert_with(key, f).cloned()
+	}
+
+	/// Refer to `RegexCache::warm`.
+	pub fn warm<I, S>(&self, patterns: I) -> Vec<(String, Result<(), Error>)>
+		where I: IntoIterator<Item = S>,
+		      S: AsRef<str>
+	{
+		self.lock().warm(patterns)
+	}
+
+	/// Refer to `RegexCache::warm_parallel`.
+	///
+	/// The patterns are compiled without holding the lock, and the lock is
+	/// only taken to insert each of them, so other threads can use the cache
+	/// in the meantime.
+	pub fn warm_parallel<I: IntoIterator<Item = String>>(&self, patterns: I) -> Vec<(String, Result<(), Error>)> {
+		let patterns = patterns.into_iter().collect::<Vec<_>>();
+		let sources  = patterns.iter().map(String::as_str).collect::<Vec<_>>();
+		let (options, limits) = {
+			let cache = self.lock();
+			(cache.defaults(), cache.pattern_limits().cloned())
+		};
+
+		let compiled = compile_parallel(&sources, &options, limits.as_ref());
+
+		patterns.into_iter().zip(compiled).map(|(source, (result, elapsed))| {
+			let result = self.lock().insert_compiled(&source, result, elapsed).map(|_| ());
+			(source, result)
+		}).collect()
+	}
+
+	/// Refer to `RegexCache::stats`.
+	pub fn stats(&self) -> CacheStats {
+		self.lock().stats()
+	}
+
+	/// Refer to `RegexCache::reset_stats`.
+	pub fn reset_stats(&self) {
+		self.lock().reset_stats()
+	}
+
+	/// Refer to `RegexCache::resize`.
+	pub fn resize(&self, capacity: usize) {
+		self.lock().resize(capacity)
+	}
+
+	/// Refer to `RegexCache::set_ttl`.
+	pub fn set_ttl(&self, ttl: Duration) {
+		self.lock().set_ttl(ttl)
+	}
+
+	/// Refer to `RegexCache::purge_expired`.
+	pub fn purge_expired(&self) {
+		self.lock().purge_expired()
+	}
+
+	/// Refer to `RegexCache::export_patterns`.
+	pub fn export_patterns(&self) -> Vec<String> {
+		self.lock().export_patterns()
+	}
+
+	/// Get the number of entries in the cache.
+	pub fn len(&self) -> usize {
+		self.lock().len()
+	}
+
+	/// Check if the cache is empty.
+	pub fn is_empty(&self) -> bool {
+		self.lock().is_empty()
+	}
+
+	/// Get the maximum number of entries in the cache.
+	pub fn capacity(&self) -> usize {
+		self.lock().capacity()
+	}
+
+	/// Remove all the entries from the cache.
+	pub fn clear(&self) {
+		self.lock().clear()
+	}
+}
+
+/// Shows the cache if it's not locked, it never blocks.
+impl fmt::Debug for SharedRegexCache {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let mut tuple = f.debug_tuple("SharedRegexCache");
+
+		match self.0.try_lock() {
+			Ok(cache) =>
+				tuple.field(&*cache),
+
+			Err(TryLockError::Poisoned(err)) =>
+				tuple.field(&*err.into_inner()),
+
+			Err(TryLockError::WouldBlock) =>
+				tuple.field(&format_args!("<locked>")),
+		};
+
+		tuple.finish()
+	}
+}
+
+impl From<RegexCache> for SharedRegexCache {
+	fn from(cache: RegexCache) -> SharedRegexCache {
+		SharedRegexCache(Arc::new(Mutex::new(cache)))
+	}
+}
+
+impl From<Arc<Mutex<RegexCache>>> for SharedRegexCache {
+	fn from(cache: Arc<Mutex<RegexCache>>) -> SharedRegexCache {
+		SharedRegexCache(cache)
+	}
+}
+
+impl From<SharedRegexCache> for Arc<Mutex<RegexCache>> {
+	fn from(cache: SharedRegexCache) -> Arc<Mutex<RegexCache>> {
+		cache.0
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::thread;
+	use crate::SharedRegexCache;
+
+	#[test]
+	fn threads() {
+		let cache   = SharedRegexCache::new(4);
+		let handles = (0 .. 8).map(|i| {
+			let cache = cache.clone();
+
+			thread::spawn(move || {
+				for j in 0 .. 100 {
+					let letter = ((b'a' + (i + j) % 8) as char).to_string();
+					let shared = cache.compile(r"^\d+$").unwrap();
+					let own    = cache.compile(&format!("^{}+$", letter)).unwrap();
+
+					assert!(shared.is_match("1234"));
+					assert!(own.is_match(&letter.repeat(3)));
+				}
+			})
+		}).collect::<Vec<_>>();
+
+		for handle in handles {
+			handle.join().unwrap();
+		}
+
+		let stats = cache.stats();
+		assert_eq!(stats.hits + stats.misses, 8 * 100 * 2);
+		assert!(cache.len() <= 4);
+	}
+
+	#[test]
+	fn warm_parallel() {
+		let patterns = vec!["^a+$".to_owned(), "(".to_owned(), "^b+$".to_owned()];
+		let cache    = SharedRegexCache::new(10);
+		let serial   = SharedRegexCache::new(10);
+
+		let report   = cache.warm_parallel(patterns.clone());
+		let expected = serial.warm(&patterns);
+
+		assert_eq!(report.iter().map(|(s, r)| (s, r.is_ok())).collect::<Vec<_>>(),
+			expected.iter().map(|(s, r)| (s, r.is_ok())).collect::<Vec<_>>());
+		assert_eq!(cache.export_patterns(), serial.export_patterns());
+	}
+
+	#[test]
+	fn debug() {
+		let cache = SharedRegexCache::new(10);
+		cache.compile("abc").unwrap();
+		assert!(format!("{:?}", cache).contains(r#"recent: ["abc"]"#));
+
+		let _guard = cache.lock();
+		assert_eq!(format!("{:?}", cache), "SharedRegexCache(<locked>)");
+	}
+
+	#[test]
+	fn try_compile() {
+		let cache = SharedRegexCache::new(10);
+		assert!(cache.try_is_match(r"^\d+$", "1234").unwrap().unwrap());
+		assert!(cache.try_compile("(").is_err());
+
+		let guard  = cache.lock();
+		let shared = cache.clone();
+
+		thread::spawn(move || {
+			assert!(shared.try_compile(r"^\d+$").unwrap().is_none());
+			assert!(shared.try_is_match(r"^\d+$", "1234").unwrap().is_none());
+		}).join().unwrap();
+
+		drop(guard);
+		assert!(cache.try_compile(r"^\d+$").unwrap().is_some());
+		assert_eq!(cache.stats().hits, 1);
+	}
+
+	#[test]
+	fn with() {
+		let cache = SharedRegexCache::new(10);
+		let inner = cache.clone();
+
+		let count = thread::spawn(move || inner.with(r"\d", |re| re.find_iter("1a2b3").count()).unwrap())
+			.join().unwrap();
+
+		assert_eq!(count, 3);
+		assert_eq!(cache.with(r"\d", |re| re.as_str().len()).unwrap(), 2);
+		assert_eq!(cache.stats().hits, 1);
+	}
+}
@@ -0,0 +1,241 @@
+// Copyright 2017 1aim GmbH
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is furnished to do
+// so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use linked_hash_map::LinkedHashMap;
+
+use regex::{Regex, Error, bytes};
+use crate::options::Options;
+use crate::cache::{RegexCacheKey, CacheStats, bounded};
+
+/// An LRU cache for both regular expressions over text and over bytes.
+///
+/// Both kinds share the same capacity and recency order, so an application
+/// using both doesn't have to size and manage two caches. The same source can
+/// be cached as both kinds at the same time, taking two entries.
+///
+/// # Example
+///
+/// ```
+/// # use regex_cache::UnifiedRegexCache;
+/// let mut cache = UnifiedRegexCache::new(2);
+///
+/// assert!(cache.compile_text(r"^\d+$").unwrap().is_match("1234"));
+/// assert!(cache.compile_bytes(r"(?-u)\xFF").unwrap().is_match(b"\x00\xFF"));
+/// assert!(cache.compile_bytes(r"^\d+$").unwrap().is_match(b"1234"));
+///
+/// assert_eq!(cache.len(), 2);
+/// assert!(!cache.contains_text(r"^\d+$"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct UnifiedRegexCache {
+	cache:    LinkedHashMap<UnifiedKey, Compiled>,
+	capacity: usize,
+	stats:    CacheStats,
+	scratch:  UnifiedKey,
+}
+
+/// The kind of regular expression an entry holds.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+enum Kind {
+	Text,
+	Bytes,
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+struct UnifiedKey {
+	kind: Kind,
+	key:  RegexCacheKey,
+}
+
+#[derive(Clone, Debug)]
+enum Compiled {
+	Text(Regex),
+	Bytes(bytes::Regex),
+}
+
+impl UnifiedRegexCache {
+	/// Create a new LRU cache with the given size limit, where `0` means the
+	/// cache is unbounded.
+	pub fn new(capacity: usize) -> UnifiedRegexCache {
+		UnifiedRegexCache {
+			cache:    LinkedHashMap::new(),
+			capacity: bounded(capacity),
+			stats:    CacheStats::default(),
+
+			scratch: UnifiedKey {
+				kind: Kind::Text,
+				key:  RegexCacheKey::new("", Options::default()),
+			},
+		}
+	}
+
+	/// Get the number of entries in the cache, of both kinds.
+	pub fn len(&self) -> usize {
+		self.cache.len()
+	}
+
+	/// Check if the cache is empty.
+	pub fn is_empty(&self) -> bool {
+		self.cache.is_empty()
+	}
+
+	/// Get the maximum number of entries in the cache, of both kinds.
+	pub fn capacity(&self) -> usize {
+		self.capacity
+	}
+
+	/// Check if a regular expression over text for the given source is in the
+	/// cache, without counting as an access.
+	pub fn contains_text(&self, source: &str) -> bool {
+		self.contains(Kind::Text, source)
+	}
+
+	/// Check if a regular expression over bytes for the given source is in the
+	/// cache, without counting as an access.
+	pub fn contains_bytes(&self, source: &str) -> bool {
+		self.contains(Kind::Bytes, source)
+	}
+
+	/// Get the hit and miss statistics of the cache, for both kinds.
+	pub fn stats(&self) -> CacheStats {
+		self.stats
+	}
+
+	/// Reset the hit and miss statistics of the cache.
+	pub fn reset_stats(&mut self) {
+		self.stats = CacheStats::default();
+	}
+
+	/// Remove all the entries from the cache.
+	pub fn clear(&mut self) {
+		self.cache.clear();
+	}
+
+	/// Save the given regular expression over text in the cache, keyed by its
+	/// source.
+	pub fn save_text(&mut self, re: Regex) -> &Regex {
+		let source = re.as_str().to_owned();
+		text(self.fetch(Kind::Text, &source, || Ok(Compiled::Text(re))).unwrap())
+	}
+
+	/// Save the given regular expression over bytes in the cache, keyed by
+	/// its source.
+	pub fn save_bytes(&mut self, re: bytes::Regex) -> &bytes::Regex {
+		let source = re.as_str().to_owned();
+		binary(self.fetch(Kind::Bytes, &source, || Ok(Compiled::Bytes(re))).unwrap())
+	}
+
+	/// Create a new regular expression over text in the cache.
+	pub fn compile_text(&mut self, source: &str) -> Result<&Regex, Error> {
+		self.fetch(Kind::Text, source, || Ok(Compiled::Text(Regex::new(source)?))).map(text)
+	}
+
+	/// Create a new regular expression over bytes in the cache.
+	pub fn compile_bytes(&mut self, source: &str) -> Result<&bytes::Regex, Error> {
+		self.fetch(Kind::Bytes, source, || Ok(Compiled::Bytes(bytes::Regex::new(source)?))).map(binary)
+	}
+
+	/// Check if an entry of the given kind is in the cache.
+	fn contains(&self, kind: Kind, source: &str) -> bool {
+		self.cache.contains_key(&UnifiedKey {
+			kind: kind,
+			key:  RegexCacheKey::new(source, Options::default()),
+		})
+	}
+
+	/// Look up the entry of the given kind for the given source, inserting the
+	/// result of `f` if it's missing.
+	///
+	/// The key buffer is reused so hits don't allocate.
+	fn fetch<F>(&mut self, kind: Kind, source: &str, f: F) -> Result<&Compiled, Error>
+		where F: FnOnce() -> Result<Compiled, Error>
+	{
+		self.scratch.kind = kind;
+		self.scratch.key.source.clear();
+		self.scratch.key.source.push_str(source);
+
+		if self.cache.get_refresh(&self.scratch).is_some() {
+			self.stats.hits += 1;
+		}
+		else {
+			self.stats.misses += 1;
+			let re = f()?;
+
+			while self.cache.len() >= self.capacity && self.cache.pop_front().is_some() {
+				self.stats.evictions += 1;
+			}
+
+			self.cache.insert(self.scratch.clone(), re);
+		}
+
+		Ok(self.cache.back().unwrap().1)
+	}
+}
+
+/// Get the regular expression of an entry looked up as text.
+fn text(compiled: &Compiled) -> &Regex {
+	match *compiled {
+		Compiled::Text(ref re) =>
+			re,
+
+		Compiled::Bytes(_) =>
+			unreachable!(),
+	}
+}
+
+/// Get the regular expression of an entry looked up as bytes.
+fn binary(compiled: &Compiled) -> &bytes::Regex {
+	match *compiled {
+		Compiled::Bytes(ref re) =>
+			re,
+
+		Compiled::Text(_) =>
+			unreachable!(),
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use regex::{Regex, bytes};
+	use crate::UnifiedRegexCache;
+
+	#[test]
+	fn shared_budget() {
+		let mut cache = UnifiedRegexCache::new(3);
+
+		cache.compile_text("a").unwrap();
+		cache.compile_bytes("a").unwrap();
+		cache.save_text(Regex::new("b").unwrap());
+		assert_eq!(cache.len(), 3);
+
+		assert!(cache.compile_text("a").unwrap().is_match("a"));
+		cache.save_bytes(bytes::Regex::new("c").unwrap());
+
+		assert!(cache.contains_text("a"));
+		assert!(!cache.contains_bytes("a"));
+		assert!(cache.contains_text("b") && cache.contains_bytes("c"));
+
+		let stats = cache.stats();
+		assert_eq!((stats.hits, stats.misses, stats.evictions), (1, 4, 1));
+
+		assert!(cache.compile_text(r"(?-u)\xFF").is_err());
+		assert!(cache.compile_bytes(r"(?-u)\xFF").is_ok());
+	}
+}
@@ -0,0 +1,94 @@
+// Copyright 2017 1aim GmbH
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is furnished to do
+// so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Check that cache hits don't allocate.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use regex_cache::{Regex, RegexCache};
+
+struct Counting;
+
+thread_local! {
+	static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		ALLOCATIONS.with(|n| n.set(n.get() + 1));
+		System.alloc(layout)
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		System.dealloc(ptr, layout)
+	}
+
+	unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, size: usize) -> *mut u8 {
+		ALLOCATIONS.with(|n| n.set(n.get() + 1));
+		System.realloc(ptr, layout, size)
+	}
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn allocations<F: FnOnce()>(f: F) -> usize {
+	let before = ALLOCATIONS.with(Cell::get);
+	f();
+	ALLOCATIONS.with(Cell::get) - before
+}
+
+#[test]
+fn hits_do_not_allocate() {
+	let mut cache = RegexCache::new(10);
+	cache.compile(r"^\d+$").unwrap();
+	cache.configure(r"abc", |b| b.case_insensitive(true)).unwrap();
+
+	assert_eq!(allocations(|| { cache.compile(r"^\d+$").unwrap(); }), 0);
+	assert_eq!(allocations(|| { cache.configure(r"abc", |b| b).unwrap(); }), 0);
+
+	let re = Regex::new(r"^\d+$").unwrap();
+	assert_eq!(allocations(|| { cache.save(re); }), 0);
+}
+
+#[test]
+fn misses_allocate() {
+	let mut cache = RegexCache::new(10);
+	assert!(allocations(|| { cache.compile(r"^\d+$").unwrap(); }) > 0);
+}
+
+#[test]
+fn owned_misses_do_not_copy_the_source() {
+	// Both caches have already grown their key buffer and their maps.
+	let mut borrowed = RegexCache::new(10);
+	borrowed.compile(r"^a long enough warm-up pattern$").unwrap();
+
+	let mut owned = RegexCache::new(10);
+	owned.compile(r"^a long enough warm-up pattern$").unwrap();
+
+	let source  = r"^[a-z]+-\d+$".to_owned();
+	let copying = allocations(|| { borrowed.compile_cow(source.as_str()).unwrap(); });
+
+	let source = r"^[a-z]+-\d+$".to_owned();
+	let     moving = allocations(|| { owned.compile_cow(source).unwrap(); });
+
+	assert_eq!(copying, moving + 1);
+}